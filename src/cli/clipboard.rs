@@ -0,0 +1,347 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use cli_clipboard::ClipboardProvider;
+use openssl::base64;
+use ssap::ssap::error::SsapError;
+use ssap::ssap::ssap::{ClipboardBackend, CLEAR_CLIPBOARD_COMMAND};
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Copy a secret to the clipboard and, if `timeout` is not zero, spawn a
+/// background process that restores the previous clipboard contents after
+/// `timeout` seconds.
+///
+/// # Arguments
+/// * `password` - The secret to copy
+/// * `timeout` - Seconds before the clipboard is restored, 0 to keep it
+/// * `backend` - The clipboard backend to use
+/// # Returns
+/// * An error if the clipboard could not be set
+///
+pub fn copy_to_clipboard(
+    password: String,
+    timeout: u64,
+    backend: &ClipboardBackend,
+) -> Result<(), SsapError> {
+    let mut backend = resolve_backend(backend);
+    let mut previous = String::new();
+    if backend != ClipboardBackend::Osc52 {
+        previous = get_contents(&backend).unwrap_or_default();
+        let saved = set_contents(&backend, &password)
+            .and_then(|_| get_contents(&backend))
+            .map(|contents| contents == password);
+        match saved {
+            Ok(true) => {}
+            // Displays may be advertised but unreachable, e.g. a stale
+            // DISPLAY forwarded over SSH: try the terminal instead
+            _ if backend == ClipboardBackend::Auto => {
+                backend = ClipboardBackend::Osc52
+            }
+            _ => return Err(SsapError::ErrorSavingClipboard),
+        }
+    }
+    if backend == ClipboardBackend::Osc52 {
+        osc52_set(&password)?;
+    }
+    if timeout > 0 {
+        spawn_clear(&password, &previous, timeout, &backend)?;
+        eprintln!("> Clipboard will be cleared in {}s", timeout);
+    }
+    Ok(())
+}
+
+/// Pick OSC 52 when `auto` is requested and no graphical display is
+/// available, as it happens in SSH and headless sessions.
+fn resolve_backend(backend: &ClipboardBackend) -> ClipboardBackend {
+    if *backend != ClipboardBackend::Auto {
+        return backend.clone();
+    }
+    if cfg!(any(target_os = "macos", windows)) {
+        return ClipboardBackend::Auto;
+    }
+    let has_display = ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()));
+    if has_display {
+        ClipboardBackend::Auto
+    } else {
+        ClipboardBackend::Osc52
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "emscripten"
+    ))
+))]
+fn get_contents(backend: &ClipboardBackend) -> Result<String, SsapError> {
+    use cli_clipboard::wayland_clipboard::WaylandClipboardContext;
+    use cli_clipboard::x11_clipboard::X11ClipboardContext;
+
+    let contents = match backend {
+        ClipboardBackend::X11 => X11ClipboardContext::<
+            cli_clipboard::x11_clipboard::Clipboard,
+        >::new()
+        .and_then(|mut ctx| ctx.get_contents()),
+        ClipboardBackend::Wayland => WaylandClipboardContext::new()
+            .and_then(|mut ctx| ctx.get_contents()),
+        ClipboardBackend::Auto => cli_clipboard::get_contents(),
+        ClipboardBackend::Osc52 => return Err(SsapError::ErrorSavingClipboard),
+    };
+    contents.map_err(|_| SsapError::ErrorSavingClipboard)
+}
+
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "emscripten"
+    ))
+))]
+fn set_contents(
+    backend: &ClipboardBackend,
+    contents: &str,
+) -> Result<(), SsapError> {
+    use cli_clipboard::wayland_clipboard::WaylandClipboardContext;
+    use cli_clipboard::x11_clipboard::X11ClipboardContext;
+
+    let contents = contents.to_owned();
+    let result = match backend {
+        ClipboardBackend::X11 => X11ClipboardContext::<
+            cli_clipboard::x11_clipboard::Clipboard,
+        >::new()
+        .and_then(|mut ctx| ctx.set_contents(contents)),
+        ClipboardBackend::Wayland => WaylandClipboardContext::new()
+            .and_then(|mut ctx| ctx.set_contents(contents)),
+        ClipboardBackend::Auto => cli_clipboard::set_contents(contents),
+        ClipboardBackend::Osc52 => return osc52_set(&contents),
+    };
+    result.map_err(|_| SsapError::ErrorSavingClipboard)
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "emscripten"
+    ))
+)))]
+fn get_contents(backend: &ClipboardBackend) -> Result<String, SsapError> {
+    match backend {
+        ClipboardBackend::Auto => cli_clipboard::get_contents()
+            .map_err(|_| SsapError::ErrorSavingClipboard),
+        _ => Err(SsapError::ErrorSavingClipboard),
+    }
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "emscripten"
+    ))
+)))]
+fn set_contents(
+    backend: &ClipboardBackend,
+    contents: &str,
+) -> Result<(), SsapError> {
+    match backend {
+        ClipboardBackend::Auto => cli_clipboard::set_contents(contents.into())
+            .map_err(|_| SsapError::ErrorSavingClipboard),
+        ClipboardBackend::Osc52 => osc52_set(contents),
+        _ => Err(SsapError::ErrorSavingClipboard),
+    }
+}
+
+/// Set the clipboard of the terminal emulator through the OSC 52 escape
+/// sequence. The sequence is written to the controlling terminal, so it
+/// also works when stdout is redirected.
+fn osc52_set(contents: &str) -> Result<(), SsapError> {
+    let sequence = osc52_sequence(
+        contents,
+        std::env::var_os("TMUX").is_some(),
+        std::env::var("TERM").is_ok_and(|term| term.starts_with("screen"))
+            || std::env::var_os("STY").is_some(),
+    );
+    let tty = OpenOptions::new().write(true).open("/dev/tty");
+    let written = match tty {
+        Ok(mut tty) => tty.write_all(sequence.as_bytes()),
+        Err(_e) => std::io::stderr().write_all(sequence.as_bytes()),
+    };
+    if written.is_err() {
+        return Err(SsapError::ErrorSavingClipboard);
+    }
+    Ok(())
+}
+
+/// Build the OSC 52 sequence, wrapped in the DCS passthrough of tmux or
+/// GNU screen when running inside them.
+fn osc52_sequence(contents: &str, tmux: bool, screen: bool) -> String {
+    let osc = format!(
+        "\x1b]52;c;{}\x07",
+        base64::encode_block(contents.as_bytes())
+    );
+    if tmux {
+        // Every ESC inside the passthrough has to be doubled
+        format!("\x1bPtmux;{}\x1b\\", osc.replace('\x1b', "\x1b\x1b"))
+    } else if screen {
+        // Screen limits the length of a DCS string, split it in chunks
+        osc.as_bytes()
+            .chunks(76)
+            .map(|chunk| {
+                format!("\x1bP{}\x1b\\", String::from_utf8_lossy(chunk))
+            })
+            .collect()
+    } else {
+        osc
+    }
+}
+
+/// Spawn a detached copy of ssap running `CLEAR_CLIPBOARD_COMMAND`. The
+/// secret and the previous contents are sent through stdin, so they never
+/// show up in the process arguments. The child is never waited on: ssap
+/// exits right away and the orphan gets reaped by init.
+#[allow(clippy::zombie_processes)]
+fn spawn_clear(
+    password: &str,
+    previous: &str,
+    timeout: u64,
+    backend: &ClipboardBackend,
+) -> Result<(), SsapError> {
+    let exe = std::env::current_exe();
+    if exe.is_err() {
+        return Err(SsapError::ErrorClearingClipboard);
+    }
+    let mut command = Command::new(exe.unwrap());
+    command
+        .arg(CLEAR_CLIPBOARD_COMMAND)
+        .arg("--clip-timeout")
+        .arg(timeout.to_string())
+        .arg("--clipboard-backend")
+        .arg(backend.name())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Leave the terminal process group, so that the child survives
+        // a Ctrl-C sent to the shell right after ssap returns.
+        command.process_group(0);
+    }
+
+    let child = command.spawn();
+    if child.is_err() {
+        return Err(SsapError::ErrorClearingClipboard);
+    }
+    let mut child = child.unwrap();
+    let payload =
+        format!("{}\n{}\n", hex::encode(password), hex::encode(previous));
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(_e) = stdin.write_all(payload.as_bytes()) {
+            return Err(SsapError::ErrorClearingClipboard);
+        }
+    }
+    Ok(())
+}
+
+/// Entry point of the background process: wait `timeout` seconds, then
+/// restore the previous clipboard contents if the clipboard still holds
+/// the secret. Anything else the user copied in the meantime is left alone.
+/// The terminal clipboard cannot be read back, so with OSC 52 it is
+/// simply cleared.
+///
+/// # Arguments
+/// * `timeout` - Seconds to wait before restoring the clipboard
+/// * `backend` - The clipboard backend holding the secret
+/// # Returns
+/// * An error if the payload on stdin is malformed
+///
+pub fn clear_clipboard(
+    timeout: u64,
+    backend: &ClipboardBackend,
+) -> Result<(), SsapError> {
+    let mut payload = String::new();
+    if let Err(_e) = std::io::stdin().read_to_string(&mut payload) {
+        return Err(SsapError::ErrorClearingClipboard);
+    }
+    let mut lines = payload.lines();
+    let password = decode_line(lines.next())?;
+    let previous = decode_line(lines.next())?;
+
+    thread::sleep(Duration::from_secs(timeout));
+
+    if *backend == ClipboardBackend::Osc52 {
+        return osc52_set("").map_err(|_| SsapError::ErrorClearingClipboard);
+    }
+    match get_contents(backend) {
+        Ok(current) if current == password => {}
+        _ => return Ok(()),
+    }
+    set_contents(backend, &previous)
+        .map_err(|_| SsapError::ErrorClearingClipboard)
+}
+
+fn decode_line(line: Option<&str>) -> Result<String, SsapError> {
+    let bytes = hex::decode(line.unwrap_or(""));
+    if bytes.is_err() {
+        return Err(SsapError::ErrorClearingClipboard);
+    }
+    match String::from_utf8(bytes.unwrap()) {
+        Ok(s) => Ok(s),
+        Err(_e) => Err(SsapError::ErrorClearingClipboard),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(
+            osc52_sequence("hunter2", false, false),
+            "\x1b]52;c;aHVudGVyMg==\x07"
+        );
+        assert_eq!(
+            osc52_sequence("hunter2", true, false),
+            "\x1bPtmux;\x1b\x1b]52;c;aHVudGVyMg==\x07\x1b\\"
+        );
+        let screen = osc52_sequence(&"x".repeat(100), false, true);
+        assert!(screen.starts_with("\x1bP\x1b]52;c;"));
+        assert_eq!(screen.matches("\x1bP").count(), 2);
+    }
+}
@@ -0,0 +1,424 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::cli::spec::{accepts, Flag, Positional, Spec, COMMANDS, FLAGS};
+use ssap::ssap::error::SsapError;
+
+/// Shells with a completion script
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn from_name(name: &str) -> Option<Shell> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+}
+
+/// Generate the completion script of a shell. Scripts complete commands,
+/// the flags of each command and their values, the names of the
+/// registered vaults and the password names of the selected vault, read
+/// with `ssap list --format raw`.
+///
+/// # Arguments
+/// * `shell` - The name of the shell
+/// # Returns
+/// * The completion script
+/// * `SsapError::InvalidShell` if the shell is not supported
+///
+pub fn script(shell: &str) -> Result<String, SsapError> {
+    match Shell::from_name(shell) {
+        Some(Shell::Bash) => Ok(bash()),
+        Some(Shell::Zsh) => Ok(zsh()),
+        Some(Shell::Fish) => Ok(fish()),
+        None => Err(SsapError::InvalidShell),
+    }
+}
+
+const BASH: &str = r#"# bash completion for ssap
+_ssap() {
+    local cur prev command i args=0
+    local -a vault
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            --) break ;;
+            -p|--path|--vault)
+                vault=("${COMP_WORDS[i]}" "${COMP_WORDS[i+1]}"); ((i++)) ;;
+            @VALUE_FLAGS@) ((i++)) ;;
+            -*) ;;
+            *) [[ -z "$command" ]] && command="${COMP_WORDS[i]}" || ((args++)) ;;
+        esac
+    done
+    local IFS=$'\n'
+    case "$prev" in
+@FLAG_VALUES@    esac
+    if [[ -z "$command" ]]; then
+        COMPREPLY=($(compgen -W "$(printf '%s\n' @COMMANDS@)" -- "$cur"))
+        return
+    fi
+    if [[ "$cur" == -* ]]; then
+        case "$command" in
+@COMMAND_FLAGS@        esac
+        return
+    fi
+    case "$command" in
+@ACTIONS@    esac
+    case "$command" in
+        @NAME_COMMANDS@) COMPREPLY=($(compgen -W "$(ssap list --format raw "${vault[@]}" 2>/dev/null)" -- "$cur")) ;;
+        @VAULT_COMMANDS@) COMPREPLY=($(compgen -W "$(ssap vault list --format raw 2>/dev/null)" -- "$cur")) ;;
+        @HELP_COMMANDS@) COMPREPLY=($(compgen -W "$(printf '%s\n' @COMMANDS@)" -- "$cur")) ;;
+        @SHELL_COMMANDS@) COMPREPLY=($(compgen -W "$(printf '%s\n' bash zsh fish)" -- "$cur")) ;;
+        @PATH_COMMANDS@) COMPREPLY=($(compgen -f -- "$cur")) ;;
+    esac
+}
+complete -F _ssap ssap
+"#;
+
+const ZSH: &str = r#"#compdef ssap
+
+_ssap() {
+    local command i args=0
+    local -a vault names flags commands
+    for ((i = 2; i < CURRENT; i++)); do
+        case "${words[i]}" in
+            --) break ;;
+            -p|--path|--vault) vault=("${words[i]}" "${words[i+1]}"); ((i++)) ;;
+            @VALUE_FLAGS@) ((i++)) ;;
+            -*) ;;
+            *) [[ -z "$command" ]] && command="${words[i]}" || ((args++)) ;;
+        esac
+    done
+    case "${words[CURRENT-1]}" in
+@FLAG_VALUES@    esac
+    if [[ -z "$command" ]]; then
+        commands=(
+@DESCRIPTIONS@        )
+        _describe command commands
+        return
+    fi
+    if [[ "${words[CURRENT]}" == -* ]]; then
+        case "$command" in
+@COMMAND_FLAGS@        esac
+        compadd -a flags
+        return
+    fi
+    case "$command" in
+@ACTIONS@    esac
+    case "$command" in
+        @NAME_COMMANDS@)
+            names=("${(@f)$(ssap list --format raw "${vault[@]}" 2>/dev/null)}")
+            compadd -a names ;;
+        @VAULT_COMMANDS@)
+            names=("${(@f)$(ssap vault list --format raw 2>/dev/null)}")
+            compadd -a names ;;
+        @HELP_COMMANDS@) compadd -- @COMMANDS@ ;;
+        @SHELL_COMMANDS@) compadd -- bash zsh fish ;;
+        @PATH_COMMANDS@) _files ;;
+    esac
+}
+
+if [[ "$funcstack[1]" == "_ssap" ]]; then
+    _ssap "$@"
+else
+    compdef _ssap ssap
+fi
+"#;
+
+const FISH: &str = r#"# fish completion for ssap
+function __ssap_positionals
+    set -l tokens (commandline -opc)
+    set -e tokens[1]
+    set -l skip 0
+    for token in $tokens
+        if test $skip -eq 1
+            set skip 0
+            continue
+        end
+        switch $token
+            case @VALUE_FLAGS@
+                set skip 1
+            case '-*'
+            case '*'
+                echo $token
+        end
+    end
+end
+
+function __ssap_command
+    set -l positionals (__ssap_positionals)
+    test (count $positionals) -gt 0; or return 1
+    echo $positionals[1]
+end
+
+function __ssap_using
+    set -l command (__ssap_command); or return 1
+    contains -- $command $argv
+end
+
+function __ssap_needs_action
+    set -l positionals (__ssap_positionals)
+    test (count $positionals) -eq 1; and test $positionals[1] = $argv[1]
+end
+
+function __ssap_names
+    set -l tokens (commandline -opc)
+    set -l vault
+    for i in (seq (math (count $tokens) - 1))
+        if contains -- $tokens[$i] -p --path --vault
+            set vault $tokens[$i] $tokens[(math $i + 1)]
+        end
+    end
+    ssap list --format raw $vault 2>/dev/null
+end
+
+complete -c ssap -f
+@COMPLETIONS@complete -c ssap -n '__ssap_using @NAME_COMMANDS@' -a '(__ssap_names)'
+complete -c ssap -n '__ssap_using @VAULT_COMMANDS@' -a '(ssap vault list --format raw 2>/dev/null)'
+complete -c ssap -n '__ssap_using @HELP_COMMANDS@' -a '@COMMANDS@'
+complete -c ssap -n '__ssap_using @SHELL_COMMANDS@' -a 'bash zsh fish'
+complete -c ssap -n '__ssap_using @PATH_COMMANDS@' -r -F
+"#;
+
+fn commands() -> impl Iterator<Item = &'static Spec> {
+    COMMANDS.iter().filter(|s| !s.hidden)
+}
+
+/// The names of a flag, like `-c|--clipboard`
+fn flag_names(flag: &Flag, separator: &str) -> String {
+    match flag.short {
+        Some(short) => format!("{}{}{}", short, separator, flag.long),
+        None => flag.long.to_owned(),
+    }
+}
+
+/// Every flag of a command, global flags included
+fn command_flags(spec: &Spec, separator: &str) -> String {
+    FLAGS
+        .iter()
+        .filter(|f| accepts(spec.command, f.long))
+        .map(|f| flag_names(f, separator))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Replace the placeholders shared by every script
+fn fill(script: &str, separator: &str) -> String {
+    let taking = |positional: Positional| {
+        let commands: Vec<&str> = commands()
+            .filter(|s| s.positional == positional)
+            .map(|s| s.command.name())
+            .collect();
+        commands.join(separator)
+    };
+    let value_flags: Vec<String> = FLAGS
+        .iter()
+        .filter(|f| f.value.is_some())
+        .map(|f| flag_names(f, separator))
+        .collect();
+    let commands: Vec<&str> = commands().map(|s| s.command.name()).collect();
+    script
+        .replace("@VALUE_FLAGS@", &value_flags.join(separator))
+        .replace("@NAME_COMMANDS@", &taking(Positional::Name))
+        .replace("@VAULT_COMMANDS@", &taking(Positional::Vault))
+        .replace("@HELP_COMMANDS@", &taking(Positional::Command))
+        .replace("@SHELL_COMMANDS@", &taking(Positional::Shell))
+        .replace("@PATH_COMMANDS@", &taking(Positional::Path))
+        .replace("@COMMANDS@", &commands.join(" "))
+}
+
+fn bash() -> String {
+    let mut values = String::new();
+    for flag in FLAGS.iter().filter(|f| f.value.is_some()) {
+        let words = match flag.value {
+            Some("path") => "-f".to_owned(),
+            Some("vault") => {
+                "-W \"$(ssap vault list --format raw 2>/dev/null)\"".to_owned()
+            }
+            _ => format!("-W \"{}\"", flag.choices.join(" ")),
+        };
+        values.push_str(&format!(
+            "        {}) COMPREPLY=($(compgen {} -- \"$cur\")); return ;;\n",
+            flag_names(flag, "|"),
+            words
+        ));
+    }
+    let mut flags = String::new();
+    let mut actions = String::new();
+    for spec in commands() {
+        flags.push_str(&format!(
+            "            {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;\n",
+            spec.command.name(),
+            command_flags(spec, " ")
+        ));
+        if !spec.actions.is_empty() {
+            actions.push_str(&format!(
+                "        {}) [[ $args -eq 0 ]] && COMPREPLY=($(compgen -W \
+                 \"{}\" -- \"$cur\")) && return ;;\n",
+                spec.command.name(),
+                spec.actions.join(" ")
+            ));
+        }
+    }
+    fill(BASH, "|")
+        .replace("@FLAG_VALUES@", &values)
+        .replace("@COMMAND_FLAGS@", &flags)
+        .replace("@ACTIONS@", &actions)
+}
+
+fn zsh() -> String {
+    let mut values = String::new();
+    for flag in FLAGS.iter().filter(|f| f.value.is_some()) {
+        let action = match flag.value {
+            Some("path") => "_files".to_owned(),
+            Some("vault") => {
+                "compadd -- ${(f)\"$(ssap vault list --format raw 2>/dev/null)\"}"
+                    .to_owned()
+            }
+            _ => format!("compadd -- {}", flag.choices.join(" ")),
+        };
+        values.push_str(&format!(
+            "        {}) {}; return ;;\n",
+            flag_names(flag, "|"),
+            action.trim_end()
+        ));
+    }
+    let mut descriptions = String::new();
+    let mut flags = String::new();
+    let mut actions = String::new();
+    for spec in commands() {
+        descriptions.push_str(&format!(
+            "            '{}:{}'\n",
+            spec.command.name(),
+            spec.about.replace('\'', "'\\''")
+        ));
+        flags.push_str(&format!(
+            "            {}) flags=({}) ;;\n",
+            spec.command.name(),
+            command_flags(spec, " ")
+        ));
+        if !spec.actions.is_empty() {
+            actions.push_str(&format!(
+                "        {}) (( args == 0 )) && compadd -- {} && return ;;\n",
+                spec.command.name(),
+                spec.actions.join(" ")
+            ));
+        }
+    }
+    fill(ZSH, "|")
+        .replace("@FLAG_VALUES@", &values)
+        .replace("@DESCRIPTIONS@", &descriptions)
+        .replace("@COMMAND_FLAGS@", &flags)
+        .replace("@ACTIONS@", &actions)
+}
+
+fn fish() -> String {
+    let mut lines = String::new();
+    for spec in commands() {
+        lines.push_str(&format!(
+            "complete -c ssap -n 'not __ssap_command' -a {} -d '{}'\n",
+            spec.command.name(),
+            spec.about.replace('\'', "\\'")
+        ));
+        for action in spec.actions {
+            lines.push_str(&format!(
+                "complete -c ssap -n '__ssap_needs_action {}' -a {}\n",
+                spec.command.name(),
+                action
+            ));
+        }
+    }
+    for flag in FLAGS {
+        let using: Vec<&str> = commands()
+            .filter(|s| accepts(s.command, flag.long))
+            .map(|s| s.command.name())
+            .collect();
+        let mut line =
+            format!("complete -c ssap -n '__ssap_using {}'", using.join(" "));
+        if let Some(short) = flag.short {
+            line.push_str(&format!(" -s {}", &short[1..]));
+        }
+        line.push_str(&format!(" -l {}", &flag.long[2..]));
+        match flag.value {
+            Some("path") => line.push_str(" -r -F"),
+            Some("vault") => line.push_str(
+                " -x -a '(ssap vault list --format raw 2>/dev/null)'",
+            ),
+            Some(_) if !flag.choices.is_empty() => {
+                line.push_str(&format!(" -x -a '{}'", flag.choices.join(" ")))
+            }
+            Some(_) => line.push_str(" -x"),
+            None => {}
+        }
+        line.push_str(&format!(
+            " -d '{}'\n",
+            flag.about[0].replace('\'', "\\'")
+        ));
+        lines.push_str(&line);
+    }
+    fill(FISH, " ").replace("@COMPLETIONS@", &lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts() {
+        assert!(script("powershell").is_err());
+        for shell in ["bash", "zsh", "fish"] {
+            let script = script(shell).unwrap();
+            assert!(script.contains("ssap list --format raw"));
+            assert!(script.contains("ssap vault list --format raw"));
+            let placeholder = script
+                .split('@')
+                .skip(1)
+                .any(|rest| rest.starts_with(|c: char| c.is_ascii_uppercase()));
+            assert!(!placeholder, "{} has a placeholder", shell);
+            for spec in commands() {
+                let name = spec.command.name();
+                assert!(script.contains(name), "{} misses {}", shell, name);
+            }
+            assert!(!script.contains("__clear-clipboard"));
+        }
+        let bash = bash();
+        let get = bash.lines().find(|l| l.contains("get) COMPREPLY")).unwrap();
+        assert!(get.contains("-c --clipboard") && get.contains("--path"));
+        assert!(!get.contains("--otp"));
+        assert!(
+            bash.contains("-f|--format) COMPREPLY=($(compgen -W \"text raw")
+        );
+    }
+}
@@ -0,0 +1,296 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use ssap::ssap::error::SsapError;
+use std::cmp::Reverse;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Text written in place of a secret when masking the output
+const MASK: &[u8] = b"*****";
+
+/// Decrypt a file with the local `gpg`, as pass does. The passphrase of
+/// the key, if needed, is asked by the gpg agent.
+///
+/// # Arguments
+/// * `path` - The encrypted file
+/// # Returns
+/// * The decrypted content
+/// * `SsapError::GpgNotFound` if gpg cannot be run
+/// * `SsapError::ErrorDecrypting` if gpg fails
+///
+pub fn gpg_decrypt(path: &Path) -> Result<String, SsapError> {
+    let output = Command::new("gpg")
+        .args(["--quiet", "--yes", "--batch", "--decrypt"])
+        .arg(path)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|_| SsapError::GpgNotFound)?;
+    if !output.status.success() {
+        return Err(SsapError::ErrorDecrypting);
+    }
+    String::from_utf8(output.stdout).map_err(|_| SsapError::ErrorDecrypting)
+}
+
+/// Run a command with additional environment variables.
+///
+/// Without masking the command inherits the standard streams and, on unix,
+/// replaces the ssap process. With masking its stdout and stderr are piped
+/// through ssap, which replaces every occurrence of a secret with `*****`.
+///
+/// # Arguments
+/// * `command` - The program followed by its arguments
+/// * `envs` - The environment variables to set
+/// * `mask_output` - Whether to scrub the secrets from the output
+/// # Returns
+/// * The exit code of the command
+/// * An error if the command could not be started
+///
+pub fn run_command(
+    command: &[String],
+    envs: &[(String, String)],
+    mask_output: bool,
+) -> Result<i32, SsapError> {
+    if command.is_empty() {
+        return Err(SsapError::MissingCommand);
+    }
+    let mut child = Command::new(&command[0]);
+    child.args(&command[1..]);
+    for (key, value) in envs {
+        child.env(key, value);
+    }
+
+    if !mask_output {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // exec only returns on failure
+            let _e = child.exec();
+            return Err(SsapError::ErrorRunningCommand);
+        }
+        #[cfg(not(unix))]
+        {
+            return match child.status() {
+                Ok(status) => Ok(status.code().unwrap_or(1)),
+                Err(_e) => Err(SsapError::ErrorRunningCommand),
+            };
+        }
+    }
+
+    let secrets: Vec<Vec<u8>> = envs
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(_, value)| value.as_bytes().to_vec())
+        .collect();
+    child.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let child = child.spawn();
+    if child.is_err() {
+        return Err(SsapError::ErrorRunningCommand);
+    }
+    let mut child = child.unwrap();
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let out_secrets = secrets.clone();
+    let out = thread::spawn(move || {
+        mask_stream(stdout, std::io::stdout(), &out_secrets)
+    });
+    let err =
+        thread::spawn(move || mask_stream(stderr, std::io::stderr(), &secrets));
+    let _ = out.join();
+    let _ = err.join();
+
+    match child.wait() {
+        Ok(status) => Ok(status.code().unwrap_or(1)),
+        Err(_e) => Err(SsapError::ErrorRunningCommand),
+    }
+}
+
+/// Size of the buffer used to read the output of the command
+const BUFFER_SIZE: usize = 4096;
+
+/// How long the beginning of a secret is held back waiting for the rest
+const HOLD_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Copy `input` to `output` replacing every secret with `MASK`. The tail of
+/// the output that could be the beginning of a secret is held back until
+/// more output arrives, so secrets written in several parts are masked as
+/// well. If nothing more arrives within `HOLD_TIMEOUT`, the command may be
+/// waiting for input, such as after a prompt, and the tail is written out.
+fn mask_stream<R: Read + Send + 'static, W: Write>(
+    mut input: R,
+    mut output: W,
+    secrets: &[Vec<u8>],
+) -> std::io::Result<()> {
+    // A secret starting with a shorter one is matched first
+    let mut secrets = secrets.to_vec();
+    secrets.sort_by_key(|secret| Reverse(secret.len()));
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        loop {
+            let read = input.read(&mut buffer);
+            let done = !matches!(read, Ok(n) if n > 0);
+            let chunk = read.map(|n| buffer[..n].to_vec());
+            if sender.send(chunk).is_err() || done {
+                break;
+            }
+        }
+    });
+
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let (chunk, last) = match receiver.recv_timeout(HOLD_TIMEOUT) {
+            Ok(Ok(chunk)) => (chunk, false),
+            Ok(Err(e)) => return Err(e),
+            // Nothing more for now: write out what was held back
+            Err(RecvTimeoutError::Timeout) => (Vec::new(), true),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if chunk.is_empty() && !last {
+            break;
+        }
+        pending.extend_from_slice(&chunk);
+        let (masked, held) = mask(&pending, &secrets, last);
+        output.write_all(&masked)?;
+        output.flush()?;
+        pending = pending[pending.len() - held..].to_vec();
+    }
+    let (masked, _) = mask(&pending, &secrets, true);
+    output.write_all(&masked)?;
+    output.flush()
+}
+
+/// Mask the secrets found in `data`. Unless `last` is set, a trailing
+/// partial match is not written and its length is returned instead.
+fn mask(data: &[u8], secrets: &[Vec<u8>], last: bool) -> (Vec<u8>, usize) {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    'outer: while i < data.len() {
+        let rest = &data[i..];
+        for secret in secrets {
+            if rest.starts_with(secret) {
+                out.extend_from_slice(MASK);
+                i += secret.len();
+                continue 'outer;
+            }
+        }
+        if !last && secrets.iter().any(|s| s.starts_with(rest)) {
+            return (out, rest.len());
+        }
+        out.push(data[i]);
+        i += 1;
+    }
+    (out, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_mask() {
+        let secrets = vec![b"hunter2".to_vec(), b"s3cr3t".to_vec()];
+        let (out, held) = mask(b"pass=hunter2 key=s3cr3t", &secrets, false);
+        assert_eq!(out, b"pass=***** key=*****");
+        assert_eq!(held, 0);
+
+        let (out, held) = mask(b"pass=hunt", &secrets, false);
+        assert_eq!(out, b"pass=");
+        assert_eq!(held, 4);
+        let (out, _) = mask(b"pass=hunt", &secrets, true);
+        assert_eq!(out, b"pass=hunt");
+    }
+
+    /// Output written in chunks, each after a delay in milliseconds
+    struct Chunks(Vec<(u64, &'static [u8])>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let (delay, chunk) = self.0.remove(0);
+            thread::sleep(Duration::from_millis(delay));
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    fn masked(chunks: Vec<(u64, &'static [u8])>, secrets: &[&[u8]]) -> Vec<u8> {
+        let secrets: Vec<Vec<u8>> =
+            secrets.iter().map(|s| s.to_vec()).collect();
+        let mut output = Vec::new();
+        mask_stream(Chunks(chunks), &mut output, &secrets).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_mask_stream_split_reads() {
+        let chunks = vec![(0, &b"user=admin pass=hun"[..]), (0, b"ter2\nhun")];
+        assert_eq!(
+            masked(chunks, &[b"hunter2"]),
+            b"user=admin pass=*****\nhun"
+        );
+        // A secret written in two parts, a moment apart
+        let chunks = vec![(0, &b"pass=hunter"[..]), (200, b"2\n")];
+        assert_eq!(masked(chunks, &[b"hunter2"]), b"pass=*****\n");
+        // A secret starting with another one
+        let chunks = vec![(0, &b"abcdef abc"[..])];
+        assert_eq!(masked(chunks, &[b"abc", b"abcdef"]), b"***** *****");
+    }
+
+    #[test]
+    fn test_mask_stream_prompt() {
+        // A prompt is written out while the command waits for input, even
+        // if it ends like the beginning of a secret
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let shared = Shared(output.clone());
+        let waiting = thread::spawn(move || {
+            let input = Chunks(vec![(0, b"password: "), (2_000, b"")]);
+            mask_stream(input, shared, &[b" hunter2".to_vec()]).unwrap();
+        });
+        thread::sleep(HOLD_TIMEOUT * 2);
+        assert_eq!(output.lock().unwrap().as_slice(), b"password: ");
+        waiting.join().unwrap();
+    }
+}
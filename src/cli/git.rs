@@ -0,0 +1,380 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! Sync of the vault through git.
+//!
+//! The repository is the directory of the vault. `ssap git init` sets up a
+//! merge driver for the vault, so that a vault changed on two machines is
+//! merged password by password instead of conflicting as a whole, and
+//! every command changing the vault commits it afterwards. Only the vault,
+//! `.gitattributes` and `.gitignore` are ever added or committed.
+
+use ssap::ssap::error::SsapError;
+use ssap::ssap::merge::{merge, Conflict};
+use ssap::ssap::storage::open_storage;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Name of the merge driver in the git configuration and `.gitattributes`
+const DRIVER: &str = "ssap";
+
+/// The git repository holding a vault
+#[derive(Debug, Clone)]
+pub struct Repo {
+    dir: PathBuf,
+    vault: OsString,
+}
+
+impl Repo {
+    /// The repository of the vault at `vault`, in the directory of the
+    /// vault
+    pub fn of(vault: &Path) -> Repo {
+        let dir = match vault.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        Repo {
+            dir,
+            vault: vault.file_name().unwrap_or_default().to_owned(),
+        }
+    }
+
+    /// Whether the vault is in a repository set up by `init`, whose root
+    /// is the directory of the vault
+    pub fn is_managed(&self) -> bool {
+        let key = format!("merge.{}.driver", DRIVER);
+        match self.output(["config", "--get", &key]) {
+            Ok(output) => output.status.success() && self.is_root(),
+            Err(_e) => false,
+        }
+    }
+
+    /// Whether the directory of the vault is the root of a repository,
+    /// rather than a directory inside another one
+    fn is_root(&self) -> bool {
+        let output = match self.output(["rev-parse", "--show-toplevel"]) {
+            Ok(output) if output.status.success() => output,
+            _ => return false,
+        };
+        let top = String::from_utf8_lossy(&output.stdout);
+        match (
+            fs::canonicalize(top.trim_end()),
+            fs::canonicalize(&self.dir),
+        ) {
+            (Ok(top), Ok(dir)) => top == dir,
+            _ => false,
+        }
+    }
+
+    /// Create the repository if needed, set up the merge driver and commit
+    /// the vault
+    ///
+    /// # Arguments
+    /// * `driver` - The command git runs to merge the vault
+    /// * `remote` - The URL of the `origin` remote, if any
+    /// # Returns
+    /// * `SsapError::GitNotFound` if git cannot be run
+    /// * `SsapError::GitFailed` if a git command fails
+    ///
+    pub fn init(
+        &self,
+        driver: &str,
+        remote: Option<&str>,
+    ) -> Result<(), SsapError> {
+        fs::create_dir_all(&self.dir).map_err(|_| SsapError::InvalidPath)?;
+        if !self.is_root() {
+            self.run(["init", "--quiet"])?;
+        }
+        let name = format!("merge.{}.name", DRIVER);
+        self.run(["config", &name, "ssap merge of the vault by password"])?;
+        self.run(["config", &format!("merge.{}.driver", DRIVER), driver])?;
+        if let Some(remote) = remote {
+            let exists = self.output(["remote", "get-url", "origin"])?;
+            match exists.status.success() {
+                true => self.run(["remote", "set-url", "origin", remote])?,
+                false => self.run(["remote", "add", "origin", remote])?,
+            }
+        }
+
+        let vault = self.vault.to_string_lossy();
+        add_line(
+            &self.dir.join(".gitattributes"),
+            &format!("/{} merge={} -diff", vault, DRIVER),
+        )?;
+        add_line(
+            &self.dir.join(".gitignore"),
+            &format!("/{}.backups/", vault),
+        )?;
+        self.run(["add", "--", ".gitattributes", ".gitignore"])?;
+        self.commit("Track the vault with ssap")?;
+        Ok(())
+    }
+
+    /// Commit the changes of the vault, if any. Only the files ssap
+    /// manages are committed, other staged changes are left alone.
+    ///
+    /// # Returns
+    /// * Whether a commit was made
+    /// * `SsapError::GitFailed` if a git command fails
+    ///
+    pub fn commit(&self, message: &str) -> Result<bool, SsapError> {
+        if self.dir.join(&self.vault).exists() {
+            self.run(
+                [OsStr::new("add"), "--all".as_ref(), "--".as_ref()]
+                    .into_iter()
+                    .chain([self.vault.as_os_str()]),
+            )?;
+        }
+        // git refuses paths it does not know of
+        let mut paths = Vec::new();
+        for path in [
+            self.vault.as_os_str(),
+            ".gitattributes".as_ref(),
+            ".gitignore".as_ref(),
+        ] {
+            if self.is_known(path)? {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Ok(false);
+        }
+        // Exits with 1 when something is staged
+        let staged = self.output(
+            ["diff", "--cached", "--quiet", "--"]
+                .map(OsStr::new)
+                .into_iter()
+                .chain(paths.iter().copied()),
+        )?;
+        if staged.status.success() {
+            return Ok(false);
+        }
+        self.run(
+            ["commit", "--quiet", "--message", message, "--only", "--"]
+                .map(OsStr::new)
+                .into_iter()
+                .chain(paths.iter().copied()),
+        )?;
+        Ok(true)
+    }
+
+    /// Whether a path is in the index or in the last commit
+    fn is_known(&self, path: &OsStr) -> Result<bool, SsapError> {
+        let indexed = self.output(
+            [
+                OsStr::new("ls-files"),
+                "--error-unmatch".as_ref(),
+                "--".as_ref(),
+            ]
+            .into_iter()
+            .chain([path]),
+        )?;
+        if indexed.status.success() {
+            return Ok(true);
+        }
+        let mut object = OsString::from("HEAD:");
+        object.push(path);
+        let committed =
+            self.output([OsStr::new("cat-file"), "-e".as_ref(), &object])?;
+        Ok(committed.status.success())
+    }
+
+    /// Push the current branch to `origin`, tracking it
+    pub fn push(&self) -> Result<(), SsapError> {
+        self.run(["push", "--set-upstream", "origin", "HEAD"])
+    }
+
+    /// Pull the current branch of `origin`, merging it with the merge
+    /// driver
+    pub fn pull(&self) -> Result<(), SsapError> {
+        let branch = self.output(["symbolic-ref", "--short", "HEAD"])?;
+        if !branch.status.success() {
+            return Err(SsapError::GitFailed);
+        }
+        let branch = String::from_utf8_lossy(&branch.stdout);
+        self.run(["pull", "--no-rebase", "--no-edit", "origin", branch.trim()])
+    }
+
+    /// Print the commits of the repository
+    pub fn log(&self) -> Result<(), SsapError> {
+        self.run(["log", "--date=iso", "--format=%h %ad %s"])
+    }
+
+    fn command<I, S>(&self, args: I) -> Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.dir).args(args);
+        command
+    }
+
+    /// Run git, capturing its output
+    fn output<I, S>(&self, args: I) -> Result<Output, SsapError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|_| SsapError::GitNotFound)
+    }
+
+    /// Run git with the standard streams of ssap
+    fn run<I, S>(&self, args: I) -> Result<(), SsapError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let status = self
+            .command(args)
+            .status()
+            .map_err(|_| SsapError::GitNotFound)?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(SsapError::GitFailed),
+        }
+    }
+}
+
+/// Merge three versions of a vault, as the git merge driver: the result is
+/// written to `ours`. Passwords changed on both sides are kept twice, so
+/// the merge never fails on them.
+///
+/// # Arguments
+/// * `base` - The common ancestor, an empty file if there is none
+/// * `ours` - Our version, replaced by the result
+/// * `theirs` - Their version
+/// # Returns
+/// * The conflicts
+/// * `SsapError::IncompatibleVaults` if the vaults have different keys
+///
+pub fn merge_files(
+    base: &Path,
+    ours: &Path,
+    theirs: &Path,
+) -> Result<Vec<Conflict>, SsapError> {
+    let base = open_storage(base).load()?;
+    let mut storage = open_storage(ours);
+    let ours = storage.load()?.ok_or(SsapError::InvalidVersion)?;
+    let theirs = open_storage(theirs)
+        .load()?
+        .ok_or(SsapError::InvalidVersion)?;
+    let (merged, conflicts) = merge(base.as_ref(), &ours, &theirs)?;
+    storage.save(&merged)?;
+    Ok(conflicts)
+}
+
+/// Append a line to a file, unless the file already has it
+fn add_line(path: &Path, line: &str) -> Result<(), SsapError> {
+    let mut content = fs::read_to_string(path).unwrap_or_default();
+    if content.lines().any(|l| l == line) {
+        return Ok(());
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(line);
+    content.push('\n');
+    fs::write(path, content).map_err(|_| SsapError::InvalidWrite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn test_sync() {
+        let dir = std::env::temp_dir().join("ssap_test_git");
+        let _ = fs::remove_dir_all(&dir);
+        let (remote, one, two) =
+            (dir.join("remote.git"), dir.join("one"), dir.join("two"));
+        fs::create_dir_all(&dir).unwrap();
+        if Command::new("git").arg("--version").output().is_err() {
+            return;
+        }
+        git(&dir, &["init", "--quiet", "--bare", "remote.git"]);
+        git(&dir, &["init", "--quiet", "one"]);
+        git(&one, &["config", "user.name", "ssap"]);
+        git(&one, &["config", "user.email", "ssap@localhost"]);
+
+        let repo = Repo::of(&one.join("vault.ssap"));
+        assert!(!repo.is_managed());
+        repo.init("true", remote.to_str()).unwrap();
+        assert!(repo.is_managed());
+        fs::write(one.join("vault.ssap"), "1.0.0\n").unwrap();
+        assert!(repo.commit("Add a password").unwrap());
+        assert!(!repo.commit("Nothing").unwrap());
+        // Other staged changes are neither checked nor committed
+        fs::write(one.join("other"), "").unwrap();
+        git(&one, &["add", "other"]);
+        assert!(!repo.commit("Nothing").unwrap());
+        fs::write(one.join("vault.ssap"), "1.1.0\n").unwrap();
+        assert!(repo.commit("Change a password").unwrap());
+        let staged = Command::new("git")
+            .arg("-C")
+            .arg(&one)
+            .args(["diff", "--cached", "--name-only"])
+            .output()
+            .unwrap();
+        assert_eq!(staged.stdout, b"other\n");
+        git(&one, &["rm", "--quiet", "--cached", "other"]);
+        // A directory inside the repository is not managed
+        fs::create_dir_all(one.join("nested")).unwrap();
+        assert!(!Repo::of(&one.join("nested/vault.ssap")).is_managed());
+        repo.push().unwrap();
+
+        git(&dir, &["clone", "--quiet", "remote.git", "two"]);
+        assert_eq!(
+            fs::read_to_string(two.join("vault.ssap")).unwrap(),
+            "1.1.0\n"
+        );
+        let attributes = fs::read_to_string(two.join(".gitattributes"));
+        assert_eq!(attributes.unwrap(), "/vault.ssap merge=ssap -diff\n");
+
+        // A clone is set up again by init, without a new commit
+        let clone = Repo::of(&two.join("vault.ssap"));
+        clone.init("true", None).unwrap();
+        assert!(clone.is_managed());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,356 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::cli::spec::{accepts, flag, spec};
+use ssap::ssap::config::load_settings;
+use ssap::ssap::csv::parse_mapping;
+use ssap::ssap::error::SsapError;
+use ssap::ssap::otp::OtpAlgorithm;
+use ssap::ssap::ssap::{
+    ClipboardBackend, Command, Encryption, ExportFormat, ImportFormat,
+    OutputFormat, Ssap,
+};
+use ssap::ssap::storage::StorageKind;
+use std::env::Args;
+use std::path::Path;
+
+/// Parse the command line arguments into a Ssap struct
+/// # Arguments
+/// * `args` - The command line arguments
+/// # Returns
+/// * A Ssap struct with the parsed arguments
+/// * An error if the arguments are invalid
+///
+pub fn parse(args: Args) -> Result<Ssap, SsapError> {
+    let mut ssap = load_settings()?;
+    let mut args = args.into_iter().skip(1);
+    let mut seen: Vec<&str> = Vec::new();
+    let mut format = None;
+    while let Some(arg) = args.next() {
+        // Everything after `--` is the command to run for exec, or
+        // positional arguments otherwise, even if starting with `-`
+        if arg == "--" {
+            if ssap.command == Some(Command::Exec) {
+                ssap.exec_command = args.by_ref().collect();
+            } else {
+                for arg in args.by_ref() {
+                    positional(&mut ssap, arg)?;
+                }
+            }
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            if ssap.command.is_none() {
+                match Command::from_name(&arg) {
+                    Some(command) => ssap.command = Some(command),
+                    None => return Err(SsapError::InvalidCommand),
+                }
+            } else {
+                positional(&mut ssap, arg)?;
+            }
+            continue;
+        }
+
+        let long = match flag(&arg) {
+            Some(flag) => flag.long,
+            None => return Err(SsapError::InvalidFlag),
+        };
+        seen.push(long);
+        match long {
+            "--help" => {
+                ssap.show_help = true;
+            }
+            "--env" => {
+                let var = args.next();
+                match var.as_ref().and_then(|v| v.split_once('=')) {
+                    Some((key, name))
+                        if !key.is_empty() && !name.is_empty() =>
+                    {
+                        ssap.env_vars.push((key.to_owned(), name.to_owned()));
+                    }
+                    _ => return Err(SsapError::InvalidEnv),
+                }
+            }
+            "--tag" | "--untag" => {
+                let tag = args.next();
+                match tag.as_ref().map(|t| t.trim()) {
+                    Some(t) if !t.is_empty() && !t.contains(',') => {
+                        if long == "--tag" {
+                            ssap.tags.push(t.to_owned());
+                        } else {
+                            ssap.untags.push(t.to_owned());
+                        }
+                    }
+                    _ => return Err(SsapError::InvalidTag),
+                }
+            }
+            "--query" => {
+                if let Some(query) = args.next() {
+                    ssap.query = Some(query);
+                } else {
+                    return Err(SsapError::MissingQuery);
+                }
+            }
+            "--regex" => {
+                ssap.regex = true;
+            }
+            "--unlock" => {
+                ssap.unlock = true;
+            }
+            "--field" => {
+                let field = args.next();
+                match field.as_ref().and_then(|f| f.split_once('=')) {
+                    Some((key, value)) if !key.is_empty() => {
+                        ssap.fields.push((key.to_owned(), value.to_owned()));
+                    }
+                    _ => return Err(SsapError::InvalidField),
+                }
+            }
+            "--input" => {
+                if let Some(path) = args.next() {
+                    ssap.template = Some(Path::new(&path).into());
+                } else {
+                    return Err(SsapError::MissingTemplate);
+                }
+            }
+            "--output" => {
+                if let Some(path) = args.next() {
+                    ssap.output = Some(Path::new(&path).into());
+                } else {
+                    return Err(SsapError::MissingPath);
+                }
+            }
+            "--force" => {
+                ssap.force = true;
+            }
+            "--encrypt" => {
+                ssap.encrypt = true;
+            }
+            "--plaintext" => {
+                ssap.plaintext = true;
+            }
+            "--keep-both" => {
+                ssap.keep_both = true;
+            }
+            "--mask-output" => {
+                ssap.mask_output = true;
+            }
+            "--clipboard" => {
+                ssap.copy_to_clipboard = true;
+            }
+            "--clip-timeout" => {
+                if let Some(timeout) = args.next() {
+                    if let Ok(timeout) = timeout.parse::<u64>() {
+                        ssap.clip_timeout = timeout;
+                    } else {
+                        return Err(SsapError::InvalidClipTimeout);
+                    }
+                } else {
+                    return Err(SsapError::InvalidClipTimeout);
+                }
+            }
+            "--clipboard-backend" => {
+                if let Some(backend) = args.next() {
+                    match ClipboardBackend::from_name(&backend) {
+                        Some(backend) => ssap.clipboard_backend = backend,
+                        None => return Err(SsapError::InvalidClipboardBackend),
+                    }
+                } else {
+                    return Err(SsapError::InvalidClipboardBackend);
+                }
+            }
+            "--silent" => {
+                ssap.silent = true;
+            }
+            "--format" => {
+                // Checked once the command is known
+                if let Some(value) = args.next() {
+                    format = Some(value);
+                } else {
+                    return Err(SsapError::InvalidFormat);
+                }
+            }
+            "--length" => {
+                if let Some(length) = args.next() {
+                    if let Ok(length) = length.parse::<usize>() {
+                        ssap.password_len = length;
+                    } else {
+                        return Err(SsapError::InvalidPasswordLength);
+                    }
+                } else {
+                    return Err(SsapError::MissingPasswordLength);
+                }
+            }
+            "--path" => {
+                if let Some(path) = args.next() {
+                    ssap.path = Path::new(&path.clone()).into();
+                } else {
+                    return Err(SsapError::MissingPath);
+                }
+            }
+            "--vault" => {
+                let vault = args.next();
+                let path = ssap
+                    .vaults
+                    .iter()
+                    .find(|(name, _)| Some(name) == vault.as_ref())
+                    .map(|(_, path)| path.clone());
+                match path {
+                    Some(path) => ssap.path = path,
+                    None => return Err(SsapError::VaultNotFound),
+                }
+            }
+            "--all-vaults" => {
+                ssap.all_vaults = true;
+            }
+            "--encryption" => {
+                let encryption = args.next();
+                match encryption.and_then(|e| Encryption::from_name(&e)) {
+                    Some(encryption) => ssap.encryption = encryption,
+                    None => return Err(SsapError::InvalidEncryptionName),
+                }
+            }
+            "--storage" => {
+                let storage = args.next();
+                match storage.and_then(|s| StorageKind::from_name(&s)) {
+                    Some(storage) => ssap.storage = storage,
+                    None => return Err(SsapError::InvalidStorage),
+                }
+            }
+            "--from" => {
+                let format = args.next();
+                match format.and_then(|f| ImportFormat::from_name(&f)) {
+                    Some(format) => ssap.import_format = Some(format),
+                    None => return Err(SsapError::InvalidImportFormat),
+                }
+            }
+            "--map" => {
+                let mapping = args.next().ok_or(SsapError::InvalidCsvMap)?;
+                ssap.csv_map = Some(parse_mapping(&mapping)?);
+            }
+            "--to" => {
+                let format = args.next();
+                match format.and_then(|f| ExportFormat::from_name(&f)) {
+                    Some(format) => ssap.export_format = Some(format),
+                    None => return Err(SsapError::InvalidExportFormat),
+                }
+            }
+            "--otp" => {
+                if let Some(secret) = args.next() {
+                    ssap.otp_secret = Some(secret);
+                } else {
+                    return Err(SsapError::MissingOtp);
+                }
+            }
+            "--otp-algorithm" => {
+                if let Some(algorithm) = args.next() {
+                    ssap.otp_algorithm =
+                        Some(OtpAlgorithm::from_name(&algorithm)?);
+                } else {
+                    return Err(SsapError::InvalidOtp);
+                }
+            }
+            "--otp-digits" => {
+                ssap.otp_digits = Some(parse_otp_number(args.next())?);
+            }
+            "--otp-period" => {
+                ssap.otp_period = Some(parse_otp_number(args.next())?);
+            }
+            "--otp-counter" => {
+                ssap.otp_counter = Some(parse_otp_number(args.next())?);
+            }
+            _ => return Err(SsapError::InvalidFlag),
+        }
+    }
+
+    if let Some(format) = format {
+        set_format(&mut ssap, &format)?;
+    }
+
+    // Each command only accepts its own flags and arguments
+    if let Some(command) = ssap.command {
+        if seen.iter().any(|long| !accepts(command, long)) {
+            return Err(SsapError::InvalidFlag);
+        }
+        let given = ssap
+            .action
+            .iter()
+            .chain(&ssap.input)
+            .chain(&ssap.destination)
+            .chain(&ssap.arguments)
+            .count();
+        if given > spec(command).max_args {
+            return Err(SsapError::InvalidCommand);
+        }
+    }
+
+    Ok(ssap)
+}
+
+/// Set the format given with `--format`: the format of the file for
+/// `export`, same as `--to`, and the output format for the other commands
+fn set_format(ssap: &mut Ssap, format: &str) -> Result<(), SsapError> {
+    let output = OutputFormat::from_name(format);
+    let export = ExportFormat::from_name(format);
+    if output.is_none() && export.is_none() {
+        return Err(SsapError::InvalidFormat);
+    }
+    match (ssap.command == Some(Command::Export), output, export) {
+        (true, _, Some(export)) => ssap.export_format = Some(export),
+        (false, Some(output), _) => ssap.format = output,
+        // A valid format, but not for this command
+        _ => return Err(SsapError::InvalidFlag),
+    }
+    Ok(())
+}
+
+/// Store a positional argument as the action of commands taking one, then
+/// as the input, then as the destination, then in the other arguments
+fn positional(ssap: &mut Ssap, arg: String) -> Result<(), SsapError> {
+    let actions = ssap.command.map(|c| spec(c).actions).unwrap_or_default();
+    if !actions.is_empty() && ssap.action.is_none() {
+        if !actions.contains(&arg.as_str()) {
+            return Err(SsapError::InvalidCommand);
+        }
+        ssap.action = Some(arg);
+    } else if ssap.input.is_none() {
+        ssap.input = Some(arg);
+    } else if ssap.destination.is_none() {
+        ssap.destination = Some(arg);
+    } else {
+        ssap.arguments.push(arg);
+    }
+    Ok(())
+}
+
+fn parse_otp_number<T: std::str::FromStr>(
+    value: Option<String>,
+) -> Result<T, SsapError> {
+    match value.map(|v| v.parse::<T>()) {
+        Some(Ok(n)) => Ok(n),
+        _ => Err(SsapError::InvalidOtp),
+    }
+}
@@ -0,0 +1,1358 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::cli::clipboard::{clear_clipboard, copy_to_clipboard};
+use crate::cli::completion;
+use crate::cli::exec::{gpg_decrypt, run_command};
+use crate::cli::git::{merge_files, Repo};
+use crate::cli::spec;
+use ssap::ssap::archive::{self, ArchiveEntry, ArchiveKdf};
+use ssap::ssap::backup::Backups;
+use ssap::ssap::bitwarden;
+use ssap::ssap::config::{save_value, Config, Value};
+use ssap::ssap::csv;
+use ssap::ssap::entry::Entry;
+use ssap::ssap::error::SsapError;
+use ssap::ssap::folder::{
+    basename, format_tree, in_folder, is_folder, moved_name, normalize,
+};
+use ssap::ssap::format::{
+    format_code, format_entry, format_matches, format_names, format_time,
+    shell_quote,
+};
+use ssap::ssap::import::{import_records, unique_names};
+use ssap::ssap::inject::{references, render, split_reference};
+use ssap::ssap::kdbx::{self, KdbxEntry, KdbxKdf};
+use ssap::ssap::merge::{merge_vaults, modified, Conflict, Resolution};
+use ssap::ssap::name::EntryName;
+use ssap::ssap::otp::{unix_time, Otp, OtpKind};
+use ssap::ssap::pass;
+use ssap::ssap::query::Query;
+use ssap::ssap::search::{Matcher, SEARCHED_FIELDS};
+use ssap::ssap::ssap::{
+    Command, ExportFormat, ImportFormat, OutputFormat, Ssap,
+    MERGE_DRIVER_COMMAND,
+};
+use ssap::ssap::storage::{new_storage, open_storage, write_atomic};
+use ssap::{Record, Vault};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// Run the SSAP application
+///
+/// # Arguments
+/// * `settings` - The SSAP settings
+/// # Returns
+/// * A SsapError if the application fails
+///
+pub fn run(settings: Ssap) -> Result<(), SsapError> {
+    let command = match settings.command {
+        Some(command) => command,
+        None if settings.show_help => {
+            print!("{}", spec::help());
+            return Ok(());
+        }
+        None => {
+            print!("{}", spec::help());
+            return Err(SsapError::InvalidCommand);
+        }
+    };
+    if settings.show_help {
+        print!("{}", spec::command_help(command));
+        return Ok(());
+    }
+    if command == Command::ClearClipboard {
+        return clear_clipboard(
+            settings.clip_timeout,
+            &settings.clipboard_backend,
+        );
+    }
+    if command.changes_vault() && settings.backup {
+        backup_vault(&settings)?;
+    }
+    let change = commit_message(&settings, command);
+    let path = settings.path.clone();
+    match command {
+        Command::Help => help(settings),
+        Command::New => create_new(settings),
+        Command::Get => get_passwd(settings),
+        Command::Delete => delete(settings),
+        Command::List => list(settings),
+        Command::Mv => move_passwd(settings),
+        Command::Find => find(settings),
+        Command::Edit => edit(settings),
+        Command::Otp => otp(settings),
+        Command::Exec => exec(settings),
+        Command::Inject => inject(settings),
+        Command::Completions => completions(settings),
+        Command::Vault => vault(settings),
+        Command::Convert => convert(settings),
+        Command::Import => import(settings),
+        Command::Export => export(settings),
+        Command::Backup => backup(settings),
+        Command::Git => git(settings),
+        Command::Merge => merge(settings),
+        Command::MergeDriver => merge_driver(settings),
+        Command::ClearClipboard => unreachable!(),
+    }?;
+    // Commit the change when the vault is synced with git
+    let repo = Repo::of(&path);
+    if let (Some(message), true) = (change, repo.is_managed()) {
+        repo.commit(&message)?;
+    }
+    Ok(())
+}
+
+/// The message of the git commit of a command changing the vault, with
+/// the password names left out when `redact` is set in the configuration
+fn commit_message(settings: &Ssap, command: Command) -> Option<String> {
+    let input = settings.input.clone().unwrap_or_default();
+    let name = |name: &str| match (settings.git_redact, is_folder(name)) {
+        (false, _) => name.to_owned(),
+        (true, false) => "a password".to_owned(),
+        (true, true) => "a folder".to_owned(),
+    };
+    let message = match command {
+        Command::New => format!("Add {}", name(&input)),
+        Command::Edit => format!("Edit {}", name(&input)),
+        Command::Delete => format!("Delete {}", name(&input)),
+        Command::Mv if settings.git_redact => {
+            format!("Move {}", name(&input))
+        }
+        Command::Mv => format!(
+            "Move {} to {}",
+            input,
+            settings.destination.as_deref().unwrap_or_default()
+        ),
+        Command::Otp => format!("Update the OTP counter of {}", name(&input)),
+        Command::Import => "Import passwords".to_owned(),
+        Command::Merge if settings.destination.is_none() => {
+            format!("Merge {}", input)
+        }
+        Command::Backup if settings.action.as_deref() == Some("restore") => {
+            format!("Restore backup {}", input)
+        }
+        _ => return None,
+    };
+    Some(message)
+}
+
+fn create_new(settings: Ssap) -> Result<(), SsapError> {
+    if settings.input.is_none() {
+        return Err(SsapError::MissingPasswordName);
+    }
+
+    let input = settings.input.clone().unwrap();
+    if is_folder(&input) {
+        return Err(SsapError::InvalidPasswordName);
+    }
+    let input = EntryName::new(&input)?.to_string();
+    let mut vault = Vault::open_or_create(
+        &settings.path,
+        settings.encryption.clone(),
+        settings.kdf_cost,
+        settings.storage,
+    )?;
+    if vault.contains(&input) {
+        return Err(SsapError::PasswordAlreadyRegistered);
+    }
+
+    let otp = build_otp(&settings)?;
+
+    eprintln!("> Creating new password with name: {}", input);
+    let new_passwd: String =
+        generate_password(settings.password_len, &settings.charset);
+    println!("> Generated Password: {}", new_passwd);
+    let mut entry = Entry::new(new_passwd);
+    for (key, value) in &settings.fields {
+        entry.set(key, value.clone());
+    }
+    entry.set_tags(&settings.tags);
+    let now = unix_time().to_string();
+    entry.set("created", now.clone());
+    entry.set("modified", now);
+    if let Some(otp) = otp {
+        eprintln!("> Attaching OTP secret");
+        entry.set("otpauth", otp.to_uri());
+    }
+    unlock(&mut vault)?;
+    vault.insert(&input, entry)?;
+    eprintln!(
+        "> Saving password to file in path: {}",
+        settings.path.display()
+    );
+    vault.save()?;
+    eprintln!("> Password created successfully");
+
+    Ok(())
+}
+
+fn edit(settings: Ssap) -> Result<(), SsapError> {
+    if settings.input.is_none() {
+        return Err(SsapError::MissingPasswordName);
+    }
+
+    let mut vault = open_vault(&settings)?;
+    let name = registered_name(&vault, settings.input.as_ref().unwrap())?;
+    unlock(&mut vault)?;
+    let mut entry = vault.get(&name)?.entry;
+
+    for (key, value) in &settings.fields {
+        // An empty value removes the field
+        if value.is_empty() {
+            entry.remove(key);
+        } else {
+            entry.set(key, value.clone());
+        }
+    }
+    let mut tags = entry.tags();
+    for tag in &settings.tags {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    tags.retain(|t| !settings.untags.contains(t));
+    entry.set_tags(&tags);
+    entry.set("modified", unix_time().to_string());
+
+    vault.insert(&name, entry)?;
+    vault.save()?;
+    eprintln!("> Password updated successfully");
+    Ok(())
+}
+
+fn build_otp(settings: &Ssap) -> Result<Option<Otp>, SsapError> {
+    let secret = match &settings.otp_secret {
+        Some(secret) => secret,
+        None => return Ok(None),
+    };
+
+    let mut otp = if secret.starts_with("otpauth://") {
+        Otp::from_uri(secret)?
+    } else {
+        Otp::from_seed(secret)?
+    };
+    if let Some(algorithm) = settings.otp_algorithm {
+        otp.algorithm = algorithm;
+    }
+    if let Some(digits) = settings.otp_digits {
+        otp.digits = digits;
+    }
+    if let Some(period) = settings.otp_period {
+        otp.period = period;
+    }
+    if let Some(counter) = settings.otp_counter {
+        otp.kind = OtpKind::Hotp;
+        otp.counter = counter;
+    }
+    if otp.label.is_empty() {
+        otp.label = settings.input.clone().unwrap_or_default();
+    }
+    otp.validate()?;
+    Ok(Some(otp))
+}
+
+fn generate_password(size: usize, charset: &str) -> String {
+    let charset: Vec<char> = charset.chars().collect();
+    let mut password = String::new();
+    for _ in 0..(size as i64) {
+        let idx = rand::random::<usize>() % charset.len();
+        password.push(charset[idx]);
+    }
+    password
+}
+
+/// Open the vault selected by the settings
+fn open_vault(settings: &Ssap) -> Result<Vault, SsapError> {
+    Vault::open(&settings.path, settings.encryption.clone())
+}
+
+/// Prompt for the vault password and unlock the vault
+fn unlock(vault: &mut Vault) -> Result<(), SsapError> {
+    vault.unlock(&read_passwd_pompt()?)
+}
+
+/// Validate a name and check that it is registered, before the password
+/// of the vault is asked
+fn registered_name(vault: &Vault, name: &str) -> Result<String, SsapError> {
+    let name = EntryName::new(name)?.to_string();
+    if !vault.contains(&name) {
+        return Err(SsapError::PasswordNameNotFound);
+    }
+    Ok(name)
+}
+
+fn read_passwd_pompt() -> Result<String, SsapError> {
+    std::io::stdout().flush().unwrap();
+    let passwd = rpassword::prompt_password("> Enter vault password: ");
+    if passwd.is_err() {
+        return Err(SsapError::InvalidPassword);
+    }
+    let passwd2 = rpassword::prompt_password("> Re-enter vault password: ");
+    if passwd2.is_err() {
+        return Err(SsapError::InvalidPassword);
+    }
+    let passwd = passwd.unwrap();
+    if passwd != passwd2.unwrap() {
+        return Err(SsapError::PasswordMismatch);
+    }
+    Ok(passwd)
+}
+
+fn get_passwd(settings: Ssap) -> Result<(), SsapError> {
+    if settings.input.is_none() {
+        return Err(SsapError::MissingPasswordName);
+    }
+
+    let mut vault = open_vault(&settings)?;
+    let name = registered_name(&vault, settings.input.as_ref().unwrap())?;
+    unlock(&mut vault)?;
+    let entry = vault.get(&name)?.entry;
+    if !settings.silent {
+        let name = settings.input.as_ref().unwrap();
+        print!("{}", format_entry(name, &entry, &settings.format));
+        std::io::stdout().flush().unwrap();
+    }
+    if settings.copy_to_clipboard {
+        eprintln!("> Copying password to clipboard");
+        copy_to_clipboard(
+            entry.password,
+            settings.clip_timeout,
+            &settings.clipboard_backend,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn otp(settings: Ssap) -> Result<(), SsapError> {
+    if settings.input.is_none() {
+        return Err(SsapError::MissingPasswordName);
+    }
+
+    let mut vault = open_vault(&settings)?;
+    let name = registered_name(&vault, settings.input.as_ref().unwrap())?;
+    unlock(&mut vault)?;
+    let mut entry = vault.get(&name)?.entry;
+    let mut otp = match entry.get("otpauth") {
+        Some(uri) => Otp::from_uri(uri)?,
+        None => return Err(SsapError::OtpNotFound),
+    };
+
+    let code = match otp.kind {
+        OtpKind::Totp => {
+            let now = unix_time();
+            eprintln!("> Expires in {}s", otp.seconds_remaining(now));
+            otp.totp(now)?
+        }
+        OtpKind::Hotp => {
+            // The next counter is saved before the code is shown, so that
+            // no code is ever handed out twice
+            let counter = otp.counter;
+            let code = otp.hotp(counter)?;
+            otp.counter += 1;
+            entry.set("otpauth", otp.to_uri());
+            vault.insert(&name, entry)?;
+            vault.save()?;
+            eprintln!("> Counter {}", counter);
+            code
+        }
+    };
+    if !settings.silent {
+        print!("{}", format_code(&name, &code, &settings.format));
+        std::io::stdout().flush().unwrap();
+    }
+    if settings.copy_to_clipboard {
+        eprintln!("> Copying OTP code to clipboard");
+        copy_to_clipboard(
+            code,
+            settings.clip_timeout,
+            &settings.clipboard_backend,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn exec(settings: Ssap) -> Result<(), SsapError> {
+    if settings.exec_command.is_empty() {
+        return Err(SsapError::MissingCommand);
+    }
+
+    let mut vault = open_vault(&settings)?;
+    let mut names = Vec::new();
+    for (var, name) in &settings.env_vars {
+        names.push((var.clone(), registered_name(&vault, name)?));
+    }
+    unlock(&mut vault)?;
+    let mut envs = Vec::new();
+    for (var, name) in names {
+        envs.push((var, vault.get(&name)?.entry.password));
+    }
+
+    let code =
+        run_command(&settings.exec_command, &envs, settings.mask_output)?;
+    std::process::exit(code);
+}
+
+fn inject(settings: Ssap) -> Result<(), SsapError> {
+    if settings.template.is_none() {
+        return Err(SsapError::MissingTemplate);
+    }
+
+    let template = fs::read_to_string(settings.template.clone().unwrap());
+    if template.is_err() {
+        return Err(SsapError::InvalidPath);
+    }
+    let template = template.unwrap();
+    let mut vault = open_vault(&settings)?;
+    let names = vault.list();
+    let mut wanted: Vec<&str> = Vec::new();
+    let refs = references(&template)?;
+    for reference in &refs {
+        let (name, _) = split_reference(reference, &names)?;
+        if !wanted.contains(&name) {
+            wanted.push(name);
+        }
+    }
+    if let Some(output) = &settings.output {
+        if !settings.force
+            && output.exists()
+            && !is_generated(output, &settings)
+        {
+            return Err(SsapError::FileNotGenerated);
+        }
+    }
+
+    unlock(&mut vault)?;
+    let mut entries = HashMap::new();
+    for name in wanted {
+        entries.insert(name, vault.get(name)?.entry);
+    }
+    let rendered = render(&template, |reference| {
+        let (name, field) = split_reference(reference, &names)?;
+        field_value(&entries[name], field)
+    })?;
+
+    match &settings.output {
+        Some(output) => {
+            write_generated(output, &rendered, &settings)?;
+            eprintln!(
+                "> Resolved {} secrets into {}",
+                refs.len(),
+                output.display()
+            );
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+/// Value of a field of an entry: `password`, `otp` for the current TOTP
+/// code, or any other stored field.
+fn field_value(entry: &Entry, field: &str) -> Result<String, SsapError> {
+    match field {
+        "password" => Ok(entry.password.clone()),
+        "otp" => match entry.get("otpauth") {
+            Some(uri) => Otp::from_uri(uri)?.totp(unix_time()),
+            None => Err(SsapError::OtpNotFound),
+        },
+        _ => match entry.get(field) {
+            Some(value) => Ok(value.to_owned()),
+            None => Err(SsapError::FieldNotFound),
+        },
+    }
+}
+
+/// The files generated by `inject` are recorded next to the vault, so that
+/// they can be regenerated without `--force`.
+fn generated_list_path(settings: &Ssap) -> PathBuf {
+    let mut path = settings.path.as_os_str().to_owned();
+    path.push(".generated");
+    PathBuf::from(path)
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn is_generated(output: &Path, settings: &Ssap) -> bool {
+    let list = fs::read_to_string(generated_list_path(settings));
+    if list.is_err() {
+        return false;
+    }
+    let output = absolute_path(output);
+    list.unwrap().lines().any(|line| Path::new(line) == output)
+}
+
+/// Write a generated file readable only by its owner, and record it as
+/// generated.
+fn write_generated(
+    output: &Path,
+    content: &str,
+    settings: &Ssap,
+) -> Result<(), SsapError> {
+    write_atomic(output, content.as_bytes())?;
+    if !is_generated(output, settings) {
+        let list = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(generated_list_path(settings));
+        if list.is_err() {
+            return Err(SsapError::InvalidPath);
+        }
+        let line = absolute_path(output);
+        if let Err(_e) = writeln!(&mut list.unwrap(), "{}", line.display()) {
+            return Err(SsapError::InvalidWrite);
+        }
+    }
+    Ok(())
+}
+
+fn delete(settings: Ssap) -> Result<(), SsapError> {
+    if settings.input.is_none() {
+        return Err(SsapError::MissingPasswordName);
+    }
+
+    let input = settings.input.clone().unwrap();
+    let mut vault = open_vault(&settings)?;
+    if !is_folder(&input) {
+        let name = registered_name(&vault, &input)?;
+        vault.remove(&name)?;
+        vault.save()?;
+        eprintln!("> Password deleted successfully");
+        return Ok(());
+    }
+
+    let names: Vec<String> = vault
+        .list()
+        .into_iter()
+        .filter(|n| in_folder(n, &input))
+        .collect();
+    if names.is_empty() {
+        return Err(SsapError::PasswordNameNotFound);
+    }
+    for name in &names {
+        vault.remove(name)?;
+    }
+    vault.save()?;
+    for name in &names {
+        eprintln!("> Deleted {}", name);
+    }
+    eprintln!("> Folder deleted successfully");
+    Ok(())
+}
+
+fn move_passwd(settings: Ssap) -> Result<(), SsapError> {
+    if settings.input.is_none() {
+        return Err(SsapError::MissingPasswordName);
+    }
+    if settings.destination.is_none() {
+        return Err(SsapError::MissingDestination);
+    }
+
+    let input = settings.input.clone().unwrap();
+    let src = EntryName::new(&input)?.to_string();
+    let destination = settings.destination.clone().unwrap();
+    let mut vault = open_vault(&settings)?;
+    let names = vault.list();
+    let is_entry = names.contains(&src) && !is_folder(&input);
+    // Moving a password into a folder keeps its name, like mv(1)
+    let dst = if is_entry && is_folder(&destination) {
+        format!("{}/{}", destination, basename(&src))
+    } else {
+        destination
+    };
+    // A folder can be moved to the top level
+    let dst = if !is_entry && normalize(&dst).is_empty() {
+        String::new()
+    } else {
+        EntryName::new(&dst)?.to_string()
+    };
+
+    let mut moves: Vec<(String, String)> = Vec::new();
+    for name in &names {
+        // A password named like the folder only moves when named explicitly
+        if !is_entry && *name == src {
+            continue;
+        }
+        if let Some(new_name) = moved_name(name, &src, &dst) {
+            EntryName::new(&new_name)?;
+            moves.push((name.clone(), new_name));
+        }
+    }
+    if moves.is_empty() {
+        return Err(SsapError::PasswordNameNotFound);
+    }
+
+    vault.rename_all(&moves)?;
+    vault.save()?;
+    for (old, new_name) in &moves {
+        eprintln!("> Moved {} to {}", old, new_name);
+    }
+    Ok(())
+}
+
+fn find(settings: Ssap) -> Result<(), SsapError> {
+    if settings.input.is_none() && settings.query.is_none() {
+        return Err(SsapError::MissingQuery);
+    }
+
+    let matcher =
+        Matcher::new(settings.input.as_deref().unwrap_or(""), settings.regex)?;
+    let query = resolve_query(&settings)?;
+    let needs_entries = settings.unlock
+        || query.as_ref().is_some_and(|query| query.needs_entry());
+
+    // Results of other vaults are prefixed with the name of their vault
+    let mut vaults: Vec<(Option<&str>, Vault)> = Vec::new();
+    if !settings.all_vaults
+        || !settings
+            .vaults
+            .iter()
+            .any(|(_, path)| *path == settings.path)
+    {
+        vaults.push((None, open_vault(&settings)?));
+    }
+    if settings.all_vaults {
+        for (name, path) in &settings.vaults {
+            match Vault::open(path, settings.encryption.clone()) {
+                Ok(vault) => vaults.push((Some(name), vault)),
+                Err(_e) => {
+                    eprintln!("> Skipping vault {}: cannot read it", name)
+                }
+            }
+        }
+    }
+    let passwd = match needs_entries {
+        true => Some(read_passwd_pompt()?),
+        false => None,
+    };
+
+    let mut matches: Vec<(String, i64, String)> = Vec::new();
+    for (label, vault) in &mut vaults {
+        let names = vault.list();
+        let entries = match &passwd {
+            Some(passwd) => decrypt_all(vault, passwd)?,
+            None => HashMap::new(),
+        };
+        for name in &names {
+            let entry = entries.get(name);
+            if let Some(query) = &query {
+                if !query.matches(name, entry, unix_time()) {
+                    continue;
+                }
+            }
+            let entry = entry.filter(|_| settings.unlock);
+            if let Some((score, field)) = best_match(&matcher, name, entry) {
+                let name = match label {
+                    Some(label) => format!("{}:{}", label, name),
+                    None => name.clone(),
+                };
+                matches.push((name, score, field));
+            }
+        }
+    }
+
+    matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    if matches.is_empty() {
+        eprintln!("> No password matches the query");
+    }
+    print!("{}", format_matches(&matches, &settings.format)?);
+    Ok(())
+}
+
+/// Score a password by its name or, when unlocked, by its fields
+fn best_match(
+    matcher: &Matcher,
+    name: &str,
+    entry: Option<&Entry>,
+) -> Option<(i64, String)> {
+    let mut best = matcher.score(name).map(|s| (s, "name".to_owned()));
+    if let Some(entry) = entry {
+        for field in SEARCHED_FIELDS {
+            // Field matches rank below name matches
+            let score = entry
+                .get(field)
+                .and_then(|value| matcher.score(value))
+                .map(|score| score / 2);
+            if score > best.as_ref().map(|(s, _)| *s) {
+                best = Some((score.unwrap(), field.to_string()));
+            }
+        }
+    }
+    best
+}
+
+/// Parse the query given with `--query`, where `@name` refers to a query
+/// saved in the `[queries]` table of the configuration file.
+fn resolve_query(settings: &Ssap) -> Result<Option<Query>, SsapError> {
+    let query = match &settings.query {
+        Some(query) => query,
+        None => return Ok(None),
+    };
+    match query.strip_prefix('@') {
+        Some(saved) => match Config::load()?.query(saved) {
+            Some(query) => Ok(Some(Query::parse(query)?)),
+            None => Err(SsapError::QueryNotFound),
+        },
+        None => Ok(Some(Query::parse(query)?)),
+    }
+}
+
+/// Decrypt every entry of the vault with its password. Entries that cannot
+/// be decrypted with that password are skipped.
+fn decrypt_all(
+    vault: &mut Vault,
+    passwd: &str,
+) -> Result<HashMap<String, Entry>, SsapError> {
+    vault.unlock(passwd)?;
+    let mut entries = HashMap::new();
+    for name in vault.list() {
+        match vault.get(&name) {
+            Ok(record) => {
+                entries.insert(name, record.entry);
+            }
+            Err(_e) => eprintln!("> Skipping {}: cannot decrypt it", name),
+        }
+    }
+    Ok(entries)
+}
+
+fn list(settings: Ssap) -> Result<(), SsapError> {
+    let mut vault = open_vault(&settings)?;
+    let mut names = vault.list();
+    if let Some(folder) = &settings.input {
+        names.retain(|n| in_folder(n, folder));
+        if names.is_empty() {
+            return Err(SsapError::PasswordNameNotFound);
+        }
+    }
+    if let Some(query) = resolve_query(&settings)? {
+        let entries = if query.needs_entry() {
+            decrypt_all(&mut vault, &read_passwd_pompt()?)?
+        } else {
+            HashMap::new()
+        };
+        let now = unix_time();
+        names.retain(|n| query.matches(n, entries.get(n), now));
+    }
+
+    let output = match &settings.input {
+        Some(folder) => {
+            if settings.format == OutputFormat::Text {
+                format_tree(&names, folder)
+            } else {
+                format_names(&names, &settings.format)?
+            }
+        }
+        None => format_names(&names, &settings.format)?,
+    };
+    eprintln!("> List of registered passwords:");
+    print!("{}", output);
+    Ok(())
+}
+
+/// Print the general help, or the help of the command given as input
+fn help(settings: Ssap) -> Result<(), SsapError> {
+    match &settings.input {
+        Some(name) => match Command::from_name(name) {
+            Some(command) if !spec::spec(command).hidden => {
+                print!("{}", spec::command_help(command));
+            }
+            _ => return Err(SsapError::InvalidCommand),
+        },
+        None => print!("{}", spec::help()),
+    }
+    Ok(())
+}
+
+fn completions(settings: Ssap) -> Result<(), SsapError> {
+    let shell = settings.input.as_deref().unwrap_or_default();
+    print!("{}", completion::script(shell)?);
+    Ok(())
+}
+
+/// Register, remove, select or list the vaults of the configuration file
+fn vault(settings: Ssap) -> Result<(), SsapError> {
+    let name = settings.input.as_deref();
+    match settings.action.as_deref() {
+        Some("add") => {
+            let name = name.ok_or(SsapError::InvalidVaultName)?;
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(SsapError::InvalidVaultName);
+            }
+            if settings.vaults.iter().any(|(n, _)| n == name) {
+                return Err(SsapError::VaultAlreadyRegistered);
+            }
+            let path = match &settings.destination {
+                Some(path) => absolute_path(Path::new(path)),
+                None => return Err(SsapError::MissingPath),
+            };
+            let value = Value::String(path.to_string_lossy().into_owned());
+            save_value("vaults", name, Some(value))?;
+            eprintln!("> Registered vault {} at {}", name, path.display());
+        }
+        Some("remove") => {
+            let name = registered_vault(&settings, name)?;
+            save_value("vaults", name, None)?;
+            if Config::load()?.get_str("", "vault") == Some(name) {
+                save_value("", "vault", None)?;
+            }
+            eprintln!("> Removed vault {}, its file is kept", name);
+        }
+        Some("default") => {
+            let name = registered_vault(&settings, name)?;
+            save_value("", "vault", Some(Value::String(name.to_owned())))?;
+            eprintln!("> Default vault set to {}", name);
+        }
+        _ => {
+            let default =
+                Config::load()?.get_str("", "vault").map(str::to_owned);
+            if settings.format != OutputFormat::Text {
+                let names: Vec<String> =
+                    settings.vaults.iter().map(|(n, _)| n.clone()).collect();
+                print!("{}", format_names(&names, &settings.format)?);
+                return Ok(());
+            }
+            eprintln!("> List of registered vaults:");
+            for (name, path) in &settings.vaults {
+                let mark = match default.as_deref() == Some(name) {
+                    true => " (default)",
+                    false => "",
+                };
+                println!(">  - {}: {}{}", name, path.display(), mark);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy the vault to a new vault with the storage given with `--storage`
+fn convert(settings: Ssap) -> Result<(), SsapError> {
+    let destination = match &settings.input {
+        Some(destination) => Path::new(destination),
+        None => return Err(SsapError::MissingDestination),
+    };
+    if open_storage(destination).load()?.is_some() {
+        return Err(SsapError::VaultExists);
+    }
+    let vault = open_vault(&settings)?;
+    vault.save_as(new_storage(destination, settings.storage))?;
+    eprintln!(
+        "> Converted {} passwords into the {} vault {}",
+        vault.list().len(),
+        settings.storage.name(),
+        destination.display()
+    );
+    Ok(())
+}
+
+/// Import the passwords of a file exported by another password manager.
+/// Passwords already registered are reported and left untouched, unless
+/// `--force` is given.
+fn import(settings: Ssap) -> Result<(), SsapError> {
+    let format = settings
+        .import_format
+        .ok_or(SsapError::MissingImportFormat)?;
+    let path = settings.input.as_deref().ok_or(SsapError::MissingPath)?;
+    let read_file = || fs::read(path).map_err(|_| SsapError::InvalidPath);
+    // Previous versions of the passwords of an archive, by record
+    let mut histories = Vec::new();
+    let mut records = match format {
+        ImportFormat::Kdbx => {
+            let passwd =
+                rpassword::prompt_password("> Enter KeePass password: ")
+                    .map_err(|_| SsapError::InvalidPassword)?;
+            kdbx::read(&read_file()?, &passwd)?
+                .iter()
+                .map(|entry| entry.to_record())
+                .collect::<Vec<Record>>()
+        }
+        ImportFormat::Bitwarden => {
+            let text = String::from_utf8(read_file()?)
+                .map_err(|_| SsapError::InvalidBitwarden)?;
+            let passwd = match bitwarden::is_encrypted(&text)? {
+                true => rpassword::prompt_password(
+                    "> Enter Bitwarden export password: ",
+                )
+                .map_err(|_| SsapError::InvalidPassword)?,
+                false => String::new(),
+            };
+            bitwarden::read(&text, &passwd)?
+        }
+        ImportFormat::Csv => {
+            let text = String::from_utf8(read_file()?)
+                .map_err(|_| SsapError::InvalidCsv)?;
+            let mut records = csv::read(&text, settings.csv_map.as_deref())?;
+            let duplicates = csv::dedupe(&mut records);
+            if duplicates > 0 {
+                eprintln!("> Skipped {} duplicated logins", duplicates);
+            }
+            records
+        }
+        ImportFormat::Pass => {
+            let mut records = Vec::new();
+            for (name, file) in pass::list(Path::new(path))? {
+                match gpg_decrypt(&file) {
+                    Ok(plaintext) => records.push(Record {
+                        name,
+                        entry: pass::parse_entry(&plaintext),
+                    }),
+                    Err(SsapError::GpgNotFound) => {
+                        return Err(SsapError::GpgNotFound)
+                    }
+                    Err(_e) => {
+                        eprintln!("> Skipping {}: gpg cannot decrypt it", name)
+                    }
+                }
+            }
+            records
+        }
+        ImportFormat::SsapJson => {
+            let text = String::from_utf8(read_file()?)
+                .map_err(|_| SsapError::InvalidArchive)?;
+            let passwd = match archive::is_encrypted(&text)? {
+                true => {
+                    rpassword::prompt_password("> Enter archive password: ")
+                        .map_err(|_| SsapError::InvalidPassword)?
+                }
+                false => String::new(),
+            };
+            let mut records = Vec::new();
+            for archived in archive::read(&text, &passwd)? {
+                records.push(Record {
+                    name: archived.name,
+                    entry: archived.entry,
+                });
+                histories.push(archived.history);
+            }
+            records
+        }
+    };
+    unique_names(&mut records);
+    let histories: HashMap<String, Vec<(u64, Entry)>> = records
+        .iter()
+        .map(|record| record.name.clone())
+        .zip(histories)
+        .collect();
+
+    let mut vault = Vault::open_or_create(
+        &settings.path,
+        settings.encryption.clone(),
+        settings.kdf_cost,
+        settings.storage,
+    )?;
+    unlock(&mut vault)?;
+    let report = import_records(&mut vault, records, settings.force)?;
+    // Saved with the passwords, so that none is imported without it
+    for name in &report.imported {
+        if let Some(history) = histories.get(name) {
+            vault.add_history(name, history)?;
+        }
+    }
+    vault.save()?;
+    for name in &report.imported {
+        eprintln!("> Imported {}", name);
+    }
+    for name in &report.conflicts {
+        eprintln!("> Skipped {}: a password with this name exists", name);
+    }
+    eprintln!(
+        "> Imported {} passwords, {} conflicts",
+        report.imported.len(),
+        report.conflicts.len()
+    );
+    if !report.conflicts.is_empty() {
+        eprintln!("> Use --force to replace the existing passwords");
+    }
+    Ok(())
+}
+
+/// Export the passwords, with their history when the storage keeps one,
+/// to a file protected by a new password. An ssap-json archive can be
+/// written in clear with `--plaintext`.
+fn export(settings: Ssap) -> Result<(), SsapError> {
+    let format = settings
+        .export_format
+        .ok_or(SsapError::MissingExportFormat)?;
+    match format {
+        ExportFormat::Kdbx if settings.plaintext => {
+            return Err(SsapError::InvalidFlag)
+        }
+        ExportFormat::SsapJson if settings.encrypt == settings.plaintext => {
+            return Err(SsapError::MissingArchiveMode)
+        }
+        _ => {}
+    }
+    let path =
+        Path::new(settings.input.as_deref().ok_or(SsapError::MissingPath)?);
+    if path.exists() && !settings.force {
+        return Err(SsapError::FileExists);
+    }
+    let mut vault = open_vault(&settings)?;
+    unlock(&mut vault)?;
+    let data = match format {
+        ExportFormat::Kdbx => {
+            let mut entries = Vec::new();
+            for name in vault.list() {
+                let entry = vault.get(&name)?.entry;
+                let history = vault.history(&name)?;
+                entries.push(KdbxEntry::from_entry(&name, &entry, &history));
+            }
+            let passwd = read_new_passwd("KeePass")?;
+            kdbx::write(&entries, &passwd, &KdbxKdf::default())?
+        }
+        ExportFormat::SsapJson => {
+            let mut entries = Vec::new();
+            for name in vault.list() {
+                entries.push(ArchiveEntry {
+                    entry: vault.get(&name)?.entry,
+                    history: vault.history(&name)?,
+                    name,
+                });
+            }
+            let passwd = match settings.plaintext {
+                true => {
+                    eprintln!("> WARNING: the passwords will be written UNENCRYPTED to {}", path.display());
+                    eprintln!("> WARNING: anyone reading this file can read every password");
+                    eprintln!("> WARNING: delete it once done, and never commit or upload it");
+                    None
+                }
+                false => Some(read_new_passwd("archive")?),
+            };
+            let text = archive::write(
+                &entries,
+                passwd.as_deref(),
+                &ArchiveKdf::default(),
+            )?;
+            text.into_bytes()
+        }
+    };
+    write_atomic(path, &data)?;
+    eprintln!(
+        "> Exported {} passwords to {}",
+        vault.list().len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Keep a copy of the vault before a command changes it, then delete the
+/// copies not kept by the retention policy
+fn backup_vault(settings: &Ssap) -> Result<(), SsapError> {
+    let backups = Backups::new(&settings.path);
+    let now = unix_time();
+    if backups.create(now)?.is_some() {
+        backups.prune(&settings.backup_retention, now)?;
+    }
+    Ok(())
+}
+
+/// List, restore or prune the backups of the vault
+fn backup(settings: Ssap) -> Result<(), SsapError> {
+    let backups = Backups::new(&settings.path);
+    match settings.action.as_deref() {
+        Some("restore") => {
+            let id = settings.input.as_deref();
+            let backup = backups.get(id.ok_or(SsapError::BackupNotFound)?)?;
+            // The current vault is kept, so that the restore can be undone
+            if let Some(current) = backups.create(unix_time())? {
+                eprintln!("> Saved the current vault as backup {}", current.id);
+            }
+            backups.restore(&backup.id)?;
+            eprintln!(
+                "> Restored backup {} to {}",
+                backup.id,
+                settings.path.display()
+            );
+        }
+        Some("prune") => {
+            let pruned =
+                backups.prune(&settings.backup_retention, unix_time())?;
+            for backup in &pruned {
+                eprintln!("> Deleted backup {}", backup.id);
+            }
+            eprintln!(
+                "> Deleted {} backups, {} kept",
+                pruned.len(),
+                backups.list()?.len()
+            );
+        }
+        _ => {
+            let ids: Vec<String> =
+                backups.list()?.into_iter().map(|b| b.id).collect();
+            if settings.format != OutputFormat::Text {
+                print!("{}", format_names(&ids, &settings.format)?);
+                return Ok(());
+            }
+            eprintln!("> Backups in {}:", backups.dir().display());
+            for id in &ids {
+                println!(">  - {}", id);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sync the vault with a git repository
+fn git(settings: Ssap) -> Result<(), SsapError> {
+    let repo = Repo::of(&settings.path);
+    let action = settings.action.as_deref();
+    if action != Some("init") && !repo.is_managed() {
+        return Err(SsapError::NotAGitVault);
+    }
+    match action {
+        Some("init") => {
+            let exe =
+                std::env::current_exe().map_err(|_| SsapError::GitFailed)?;
+            let driver = format!(
+                "{} {} %O %A %B",
+                shell_quote(&exe.to_string_lossy()),
+                MERGE_DRIVER_COMMAND
+            );
+            repo.init(&driver, settings.input.as_deref())?;
+            eprintln!(
+                "> The vault {} is synced with git",
+                settings.path.display()
+            );
+        }
+        Some("push") => repo.push()?,
+        Some("pull") => {
+            if settings.backup {
+                backup_vault(&settings)?;
+            }
+            repo.pull()?
+        }
+        _ => repo.log()?,
+    }
+    Ok(())
+}
+
+/// Merge the vault changed on two sides, run by git as `<base> <ours>
+/// <theirs>`
+fn merge_driver(settings: Ssap) -> Result<(), SsapError> {
+    let base = settings.input.as_deref().ok_or(SsapError::MissingPath)?;
+    let ours = settings
+        .destination
+        .as_deref()
+        .ok_or(SsapError::MissingPath)?;
+    let theirs = settings.arguments.first().ok_or(SsapError::MissingPath)?;
+    let conflicts =
+        merge_files(Path::new(base), Path::new(ours), Path::new(theirs))?;
+    for conflict in conflicts {
+        match conflict {
+            Conflict::Changed { name, copy } => eprintln!(
+                "> {} was changed on both sides, their version is kept as {}",
+                name, copy
+            ),
+            Conflict::Deleted { name } => eprintln!(
+                "> {} was deleted on one side and changed on the other, \
+                 the changed version is kept",
+                name
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Merge another copy of the vault, password by password: `<other>` into
+/// the vault, or `<theirs>` into `<ours>` given their common ancestor
+/// `<base>`. The result is saved once, when every conflict is resolved.
+fn merge(settings: Ssap) -> Result<(), SsapError> {
+    let input = settings.input.as_deref().ok_or(SsapError::MissingPath)?;
+    let (base, target, other) =
+        match (&settings.destination, settings.arguments.first()) {
+            (None, _) => (None, settings.path.to_path_buf(), input),
+            (Some(ours), Some(theirs)) => {
+                (Some(Path::new(input)), PathBuf::from(ours), theirs.as_str())
+            }
+            (Some(_), None) => return Err(SsapError::MissingPath),
+        };
+    let other = Path::new(other);
+    if base.is_none() && settings.backup {
+        backup_vault(&settings)?;
+    }
+    let mut ours = Vault::open(&target, settings.encryption.clone())?;
+    let mut theirs = Vault::open(other, settings.encryption.clone())?;
+    // An empty ancestor, as given by git for a vault added on both sides
+    let mut base = match base {
+        Some(path) if open_storage(path).load()?.is_some() => {
+            Some((Vault::open(path, settings.encryption.clone())?, path))
+        }
+        _ => None,
+    };
+
+    let passwd = read_passwd_pompt()?;
+    ours.unlock(&passwd)?;
+    unlock_copy(&mut theirs, &passwd, other)?;
+    if let Some((base, path)) = &mut base {
+        unlock_copy(base, &passwd, path)?;
+    }
+    let interactive = !settings.keep_both && std::io::stdin().is_terminal();
+    let (taken, conflicts) = merge_vaults(
+        base.as_ref().map(|(base, _)| base),
+        &mut ours,
+        &theirs,
+        |name, o, t| match interactive {
+            true => ask_resolution(name, o, t),
+            false => Ok(Resolution::Both),
+        },
+    )?;
+    ours.save()?;
+
+    for name in &taken {
+        eprintln!("> Took {} from {}", name, other.display());
+    }
+    for conflict in conflicts {
+        match conflict {
+            Conflict::Changed { name, copy } => eprintln!(
+                "> Kept both versions of {}, the older one as {}",
+                name, copy
+            ),
+            Conflict::Deleted { name } => eprintln!(
+                "> {} was deleted on one side and changed on the other, \
+                 the changed version is kept",
+                name
+            ),
+        }
+    }
+    eprintln!("> Merged {} into {}", other.display(), target.display());
+    Ok(())
+}
+
+/// Unlock another copy of the vault with the vault password, or with its
+/// own password if the vault password does not decrypt it
+fn unlock_copy(
+    vault: &mut Vault,
+    passwd: &str,
+    path: &Path,
+) -> Result<(), SsapError> {
+    let unlocks = |vault: &Vault| match vault.list().first() {
+        Some(name) => vault.get(name).is_ok(),
+        None => true,
+    };
+    vault.unlock(passwd)?;
+    if unlocks(vault) {
+        return Ok(());
+    }
+    let passwd = rpassword::prompt_password(format!(
+        "> Enter password of {}: ",
+        path.display()
+    ))
+    .map_err(|_| SsapError::InvalidPassword)?;
+    vault.unlock(&passwd)?;
+    match unlocks(vault) {
+        true => Ok(()),
+        false => Err(SsapError::InvalidPassword),
+    }
+}
+
+/// Ask which versions of a password changed on both sides to keep,
+/// showing what differs but not the secrets
+fn ask_resolution(
+    name: &str,
+    ours: &Entry,
+    theirs: &Entry,
+) -> Result<Resolution, SsapError> {
+    let mut keys: Vec<&str> = Vec::new();
+    if ours.password != theirs.password {
+        keys.push("password");
+    }
+    for (key, _) in ours.fields.iter().chain(&theirs.fields) {
+        if key != "modified"
+            && ours.get(key) != theirs.get(key)
+            && !keys.contains(&key.as_str())
+        {
+            keys.push(key);
+        }
+    }
+    eprintln!("> {} was changed on both sides: {}", name, keys.join(", "));
+    for (side, entry) in [("ours", ours), ("theirs", theirs)] {
+        match modified(entry) {
+            0 => eprintln!(">   {}: modified at an unknown time", side),
+            time => {
+                eprintln!(">   {}: modified {} UTC", side, format_time(time))
+            }
+        }
+    }
+    loop {
+        eprint!("> Keep [o]urs, [t]heirs or [b]oth? ");
+        let mut answer = String::new();
+        match std::io::stdin().read_line(&mut answer) {
+            Ok(0) | Err(_) => return Ok(Resolution::Both),
+            Ok(_) => {}
+        }
+        match answer.trim() {
+            "o" | "ours" => return Ok(Resolution::Ours),
+            "t" | "theirs" => return Ok(Resolution::Theirs),
+            "b" | "both" => return Ok(Resolution::Both),
+            _ => {}
+        }
+    }
+}
+
+/// Prompt twice for the password protecting an exported file
+fn read_new_passwd(kind: &str) -> Result<String, SsapError> {
+    let passwd =
+        rpassword::prompt_password(format!("> Enter new {} password: ", kind))
+            .map_err(|_| SsapError::InvalidPassword)?;
+    let passwd2 = rpassword::prompt_password(format!(
+        "> Re-enter new {} password: ",
+        kind
+    ))
+    .map_err(|_| SsapError::InvalidPassword)?;
+    if passwd != passwd2 {
+        return Err(SsapError::PasswordMismatch);
+    }
+    Ok(passwd)
+}
+
+fn registered_vault<'a>(
+    settings: &Ssap,
+    name: Option<&'a str>,
+) -> Result<&'a str, SsapError> {
+    match name {
+        Some(name) if settings.vaults.iter().any(|(n, _)| n == name) => {
+            Ok(name)
+        }
+        _ => Err(SsapError::VaultNotFound),
+    }
+}
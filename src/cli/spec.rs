@@ -0,0 +1,817 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use ssap::ssap::ssap::Command;
+
+/// A command line flag
+pub struct Flag {
+    pub short: Option<&'static str>,
+    pub long: &'static str,
+    /// Name of the value taken by the flag, if any
+    pub value: Option<&'static str>,
+    /// Values offered by shell completions
+    pub choices: &'static [&'static str],
+    pub about: &'static [&'static str],
+}
+
+/// What the positional arguments of a command are
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Positional {
+    Nothing,
+    Name,
+    NewName,
+    Query,
+    Command,
+    Shell,
+    Vault,
+    Path,
+}
+
+/// A subcommand, its arguments and its flags
+pub struct Spec {
+    pub command: Command,
+    pub usage: &'static str,
+    pub positional: Positional,
+    /// Actions taken as first argument, like `add` in `vault add`
+    pub actions: &'static [&'static str],
+    pub max_args: usize,
+    pub about: &'static str,
+    /// Long names of the flags accepted besides `GLOBAL_FLAGS`
+    pub flags: &'static [&'static str],
+    pub examples: &'static [&'static str],
+    /// Hidden commands are internal and not documented
+    pub hidden: bool,
+}
+
+/// Flags accepted by every command
+pub const GLOBAL_FLAGS: &[&str] =
+    &["--help", "--path", "--vault", "--encryption"];
+
+pub const FLAGS: &[Flag] = &[
+    Flag {
+        short: Some("-h"),
+        long: "--help",
+        value: None,
+        choices: &[],
+        about: &["Prints help information"],
+    },
+    Flag {
+        short: Some("-p"),
+        long: "--path",
+        value: Some("path"),
+        choices: &[],
+        about: &["Specify the path to the password file"],
+    },
+    Flag {
+        short: None,
+        long: "--vault",
+        value: Some("vault"),
+        choices: &[],
+        about: &["Use a vault registered with 'ssap vault add'"],
+    },
+    Flag {
+        short: Some("-a"),
+        long: "--all-vaults",
+        value: None,
+        choices: &[],
+        about: &["Search every registered vault"],
+    },
+    Flag {
+        short: Some("-e"),
+        long: "--encryption",
+        value: Some("encryption"),
+        choices: &["aes_128_cbc", "aes_256_cbc"],
+        about: &[
+            "Specify the encryption algorithm",
+            "Supported algorithms: aes_128_cbc, aes_256_cbc",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--storage",
+        value: Some("storage"),
+        choices: &["text", "sqlite", "directory"],
+        about: &[
+            "Storage of a new vault: text, sqlite",
+            "or directory, default: text",
+        ],
+    },
+    Flag {
+        short: Some("-l"),
+        long: "--length",
+        value: Some("length"),
+        choices: &[],
+        about: &["Length of the generated password, default: 30"],
+    },
+    Flag {
+        short: Some("-c"),
+        long: "--clipboard",
+        value: None,
+        choices: &[],
+        about: &["Copy the password to clipboard"],
+    },
+    Flag {
+        short: Some("-s"),
+        long: "--silent",
+        value: None,
+        choices: &[],
+        about: &["Do not print the password"],
+    },
+    Flag {
+        short: None,
+        long: "--clip-timeout",
+        value: Some("seconds"),
+        choices: &[],
+        about: &[
+            "Restore the clipboard after the",
+            "timeout, 0 to disable, default: 45",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--clipboard-backend",
+        value: Some("backend"),
+        choices: &["auto", "osc52", "x11", "wayland"],
+        about: &[
+            "Clipboard to copy to",
+            "Supported backends: auto, osc52, x11,",
+            "wayland. default: auto",
+        ],
+    },
+    Flag {
+        short: Some("-f"),
+        long: "--format",
+        value: Some("format"),
+        choices: &["text", "raw", "json", "env", "yaml"],
+        about: &[
+            "Output format",
+            "Supported formats: text, raw, json, env,",
+            "yaml. default: text",
+            "With export, same as --to",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--field",
+        value: Some("KEY=VALUE"),
+        choices: &[],
+        about: &[
+            "Store a field, like username or url, an",
+            "empty value removes the field",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--tag",
+        value: Some("tag"),
+        choices: &[],
+        about: &["Add a tag to the password"],
+    },
+    Flag {
+        short: None,
+        long: "--untag",
+        value: Some("tag"),
+        choices: &[],
+        about: &["Remove a tag from the password"],
+    },
+    Flag {
+        short: Some("-q"),
+        long: "--query",
+        value: Some("query"),
+        choices: &[],
+        about: &[
+            "Filter the passwords with a query, or",
+            "@name for a query saved in the config",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--regex",
+        value: None,
+        choices: &[],
+        about: &["Search with a regular expression"],
+    },
+    Flag {
+        short: None,
+        long: "--unlock",
+        value: None,
+        choices: &[],
+        about: &["Search usernames, urls, tags and notes too"],
+    },
+    Flag {
+        short: None,
+        long: "--otp",
+        value: Some("uri|seed"),
+        choices: &[],
+        about: &["Attach an otpauth:// URI or base32 seed"],
+    },
+    Flag {
+        short: None,
+        long: "--otp-algorithm",
+        value: Some("algorithm"),
+        choices: &["SHA1", "SHA256", "SHA512"],
+        about: &["OTP hash: SHA1, SHA256, SHA512"],
+    },
+    Flag {
+        short: None,
+        long: "--otp-digits",
+        value: Some("digits"),
+        choices: &[],
+        about: &["Number of digits of the OTP code"],
+    },
+    Flag {
+        short: None,
+        long: "--otp-period",
+        value: Some("seconds"),
+        choices: &[],
+        about: &["Validity of a TOTP code, default: 30"],
+    },
+    Flag {
+        short: None,
+        long: "--otp-counter",
+        value: Some("counter"),
+        choices: &[],
+        about: &["Use HOTP starting at the counter"],
+    },
+    Flag {
+        short: None,
+        long: "--env",
+        value: Some("VAR=name"),
+        choices: &[],
+        about: &["Set VAR to the password name"],
+    },
+    Flag {
+        short: None,
+        long: "--mask-output",
+        value: None,
+        choices: &[],
+        about: &["Hide the passwords in the output"],
+    },
+    Flag {
+        short: Some("-i"),
+        long: "--input",
+        value: Some("path"),
+        choices: &[],
+        about: &["Template to render"],
+    },
+    Flag {
+        short: Some("-o"),
+        long: "--output",
+        value: Some("path"),
+        choices: &[],
+        about: &["File to write, default: stdout"],
+    },
+    Flag {
+        short: None,
+        long: "--from",
+        value: Some("format"),
+        choices: &["kdbx", "bitwarden", "csv", "pass", "ssap-json"],
+        about: &[
+            "Format of the file to import: kdbx,",
+            "bitwarden, csv, pass or ssap-json",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--map",
+        value: Some("FIELD=COLUMN,..."),
+        choices: &[],
+        about: &[
+            "Columns of a CSV file, read from the",
+            "header for Chrome, Edge and Firefox",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--to",
+        value: Some("format"),
+        choices: &["kdbx", "ssap-json"],
+        about: &["Format of the exported file: kdbx or", "ssap-json"],
+    },
+    Flag {
+        short: None,
+        long: "--keep-both",
+        value: None,
+        choices: &[],
+        about: &[
+            "Keep both versions of a password changed",
+            "on both sides instead of asking",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--encrypt",
+        value: None,
+        choices: &[],
+        about: &["Encrypt an ssap-json export with a password"],
+    },
+    Flag {
+        short: None,
+        long: "--plaintext",
+        value: None,
+        choices: &[],
+        about: &[
+            "Write an ssap-json export unencrypted,",
+            "readable by anyone getting the file",
+        ],
+    },
+    Flag {
+        short: None,
+        long: "--force",
+        value: None,
+        choices: &[],
+        about: &[
+            "Overwrite a file not created by inject,",
+            "the existing passwords on import",
+            "or the file written by export",
+        ],
+    },
+];
+
+pub const COMMANDS: &[Spec] = &[
+    Spec {
+        command: Command::New,
+        usage: "<name>",
+        positional: Positional::NewName,
+        actions: &[],
+        max_args: 1,
+        about: "Create a new password",
+        flags: &[
+            "--length",
+            "--storage",
+            "--field",
+            "--tag",
+            "--otp",
+            "--otp-algorithm",
+            "--otp-digits",
+            "--otp-period",
+            "--otp-counter",
+        ],
+        examples: &[
+            "ssap new my_password",
+            "ssap new github --otp otpauth://totp/github?secret=JBSWY3DP",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Get,
+        usage: "<name>",
+        positional: Positional::Name,
+        actions: &[],
+        max_args: 1,
+        about: "Get an existing password",
+        flags: &[
+            "--clipboard",
+            "--silent",
+            "--clip-timeout",
+            "--clipboard-backend",
+            "--format",
+        ],
+        examples: &["ssap get my_password", "ssap get prod/db -f raw"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Delete,
+        usage: "<name>",
+        positional: Positional::Name,
+        actions: &[],
+        max_args: 1,
+        about: "Delete a password, or a folder ending with '/'",
+        flags: &[],
+        examples: &["ssap delete old-job/"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::List,
+        usage: "[folder/]",
+        positional: Positional::Name,
+        actions: &[],
+        max_args: 1,
+        about: "List all registered passwords, or the tree of a folder",
+        flags: &["--query", "--format"],
+        examples: &[
+            "ssap list work/",
+            "ssap list -q 'tag:prod AND NOT tag:legacy'",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Mv,
+        usage: "<source> <destination>",
+        positional: Positional::Name,
+        actions: &[],
+        max_args: 2,
+        about: "Move or rename a password or a folder",
+        flags: &[],
+        examples: &["ssap mv work/github personal/"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Find,
+        usage: "<query>",
+        positional: Positional::Query,
+        actions: &[],
+        max_args: 1,
+        about: "Search passwords by name, best match first",
+        flags: &["--regex", "--unlock", "--query", "--all-vaults", "--format"],
+        examples: &[
+            "ssap find gh | fzf",
+            "ssap find --regex '^work/'",
+            "ssap find github --all-vaults",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Edit,
+        usage: "<name>",
+        positional: Positional::Name,
+        actions: &[],
+        max_args: 1,
+        about: "Edit the fields and tags of a password",
+        flags: &["--field", "--tag", "--untag"],
+        examples: &["ssap edit prod/db --tag legacy"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Otp,
+        usage: "<name>",
+        positional: Positional::Name,
+        actions: &[],
+        max_args: 1,
+        about: "Print the current OTP code of a password",
+        flags: &[
+            "--clipboard",
+            "--silent",
+            "--clip-timeout",
+            "--clipboard-backend",
+            "--format",
+        ],
+        examples: &["ssap otp github -c"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Exec,
+        usage: "-- <command>...",
+        positional: Positional::Nothing,
+        actions: &[],
+        max_args: 0,
+        about: "Run a command with passwords in its environment",
+        flags: &["--env", "--mask-output"],
+        examples: &["ssap exec --env DB_PASS=prod/db -- ./deploy.sh"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Inject,
+        usage: "",
+        positional: Positional::Nothing,
+        actions: &[],
+        max_args: 0,
+        about: "Render a template referencing passwords",
+        flags: &["--input", "--output", "--force"],
+        examples: &["ssap inject -i app.toml.tpl -o app.toml"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Completions,
+        usage: "<shell>",
+        positional: Positional::Shell,
+        actions: &[],
+        max_args: 1,
+        about: "Print the completion script of bash, zsh or fish",
+        flags: &[],
+        examples: &["ssap completions bash > ~/.local/share/bash-completion/completions/ssap"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Vault,
+        usage: "add <name> <path> | remove <name> | default <name> | list",
+        positional: Positional::Vault,
+        actions: &["add", "remove", "default", "list"],
+        max_args: 3,
+        about: "Register vaults to select them with --vault",
+        flags: &["--format"],
+        examples: &[
+            "ssap vault add team /srv/team.ssap",
+            "ssap vault default team",
+            "ssap get prod/db --vault team",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Convert,
+        usage: "<destination>",
+        positional: Positional::Path,
+        actions: &[],
+        max_args: 1,
+        about: "Copy the vault to a new vault with another storage",
+        flags: &["--storage"],
+        examples: &[
+            "ssap convert ~/vault.db --storage sqlite",
+            "ssap convert ~/vault.ssap --path ~/vault.db",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Import,
+        usage: "--from <format> <path>",
+        positional: Positional::Path,
+        actions: &[],
+        max_args: 1,
+        about: "Import the passwords of another password manager",
+        flags: &["--from", "--map", "--force", "--storage"],
+        examples: &[
+            "ssap import --from kdbx ~/Passwords.kdbx",
+            "ssap import --from bitwarden bitwarden_export.json",
+            "ssap import --from csv 'Chrome Passwords.csv'",
+            "ssap import --from csv x.csv --map name=Title,password=Pass",
+            "ssap import --from pass ~/.password-store",
+            "ssap import --from ssap-json backup.json",
+            "ssap import --from kdbx old.kdbx --vault work --force",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Export,
+        usage: "--to <format> <file>",
+        positional: Positional::Path,
+        actions: &[],
+        max_args: 1,
+        about: "Export the passwords for another password manager",
+        flags: &["--to", "--format", "--encrypt", "--plaintext", "--force"],
+        examples: &[
+            "ssap export --to kdbx ~/Passwords.kdbx",
+            "ssap export --format ssap-json --encrypt backup.json",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Backup,
+        usage: "list | restore <backup> | prune",
+        positional: Positional::Nothing,
+        actions: &["list", "restore", "prune"],
+        max_args: 2,
+        about: "List, restore or prune the backups of the vault",
+        flags: &["--format"],
+        examples: &[
+            "ssap backup list",
+            "ssap backup restore 20240229-120000",
+            "ssap backup prune",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Git,
+        usage: "init [remote] | push | pull | log",
+        positional: Positional::Nothing,
+        actions: &["init", "push", "pull", "log"],
+        max_args: 2,
+        about: "Sync the vault with git, committing every change",
+        flags: &[],
+        examples: &[
+            "ssap git init git@example.com:me/vault.git",
+            "ssap git pull",
+            "ssap git push",
+            "ssap git log",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Merge,
+        usage: "<other> | <base> <ours> <theirs>",
+        positional: Positional::Path,
+        actions: &[],
+        max_args: 3,
+        about: "Merge another copy of the vault, password by password",
+        flags: &["--keep-both"],
+        examples: &[
+            "ssap merge vault.sync-conflict-20240229-120000.ssap",
+            "ssap merge base.ssap ours.ssap theirs.ssap --keep-both",
+        ],
+        hidden: false,
+    },
+    Spec {
+        command: Command::Help,
+        usage: "[command]",
+        positional: Positional::Command,
+        actions: &[],
+        max_args: 1,
+        about: "Print the help of ssap or of a command",
+        flags: &[],
+        examples: &["ssap help get"],
+        hidden: false,
+    },
+    Spec {
+        command: Command::ClearClipboard,
+        usage: "",
+        positional: Positional::Nothing,
+        actions: &[],
+        max_args: 0,
+        about: "",
+        flags: &["--clip-timeout", "--clipboard-backend"],
+        examples: &[],
+        hidden: true,
+    },
+    Spec {
+        command: Command::MergeDriver,
+        usage: "<base> <ours> <theirs>",
+        positional: Positional::Nothing,
+        actions: &[],
+        max_args: 3,
+        about: "",
+        flags: &[],
+        examples: &[],
+        hidden: true,
+    },
+];
+
+/// Examples printed by the general help
+const EXAMPLES: &[&str] = &[
+    "ssap new my_password",
+    "ssap get my_password",
+    "ssap new github --otp otpauth://totp/github?secret=JBSWY3DP",
+    "ssap otp github -c",
+    "ssap exec --env DB_PASS=prod/db -- ./deploy.sh",
+    "ssap inject -i app.toml.tpl -o app.toml",
+    "ssap list work/",
+    "ssap mv work/github personal/",
+    "ssap find gh | fzf",
+    "ssap edit prod/db --tag legacy",
+    "ssap list -q 'tag:prod AND NOT tag:legacy'",
+];
+
+const BANNER: &str = r"
+ ________   ________  ________  ________   
+|\   ____\ |\   ____\|\   __  \|\   __  \  
+\ \  \___|_\ \  \___|\ \  \|\  \ \  \|\  \ 
+ \ \_____  \\ \_____  \ \   __  \ \   ____\
+  \|____|\  \\|____|\  \ \  \ \  \ \  \___|
+    ____\_\  \ ____\_\  \ \__\ \__\ \__\   
+   |\_________\\_________\|__|\|__|\|__|   
+   \|_________\|_________|                 
+    ";
+
+/// Find a flag by its short or long name
+///
+/// # Arguments
+/// * `arg` - The flag, like `-c` or `--clipboard`
+/// # Returns
+/// * The flag, if it exists
+///
+pub fn flag(arg: &str) -> Option<&'static Flag> {
+    FLAGS
+        .iter()
+        .find(|f| f.long == arg || f.short.is_some_and(|s| s == arg))
+}
+
+/// Get the specification of a command
+pub fn spec(command: Command) -> &'static Spec {
+    COMMANDS
+        .iter()
+        .find(|s| s.command == command)
+        .expect("every command has a specification")
+}
+
+/// Check if a command accepts a flag
+///
+/// # Arguments
+/// * `command` - The command
+/// * `long` - The long name of the flag
+/// # Returns
+/// * `true` if the flag is global or one of the command's flags
+///
+pub fn accepts(command: Command, long: &str) -> bool {
+    GLOBAL_FLAGS.contains(&long) || spec(command).flags.contains(&long)
+}
+
+/// Format a flag and its description, wrapped like the rest of the help
+fn format_flag(flag: &Flag) -> String {
+    let mut left = match flag.short {
+        Some(short) => format!("{}, {}", short, flag.long),
+        None => flag.long.to_owned(),
+    };
+    if let Some(value) = flag.value {
+        left.push_str(&format!(" <{}>", value));
+    }
+    let mut out = format!("    {:<18} {}\n", left, flag.about[0]);
+    for line in &flag.about[1..] {
+        out.push_str(&format!("{:23}{}\n", "", line));
+    }
+    out
+}
+
+/// The general help: the commands and the global flags
+pub fn help() -> String {
+    let mut out = format!("{}\n", BANNER);
+    out.push_str(&format!("Version: {}\n\n", env!("CARGO_PKG_VERSION")));
+    out.push_str("USAGE:\n    ssap <COMMAND> [FLAGS] [INPUT]\n\n");
+    out.push_str("COMMANDS:\n");
+    for spec in COMMANDS.iter().filter(|s| !s.hidden) {
+        out.push_str(&format!(
+            "    {:<17} {}\n",
+            spec.command.name(),
+            spec.about
+        ));
+    }
+    out.push_str("\nINPUT:\n");
+    out.push_str(
+        "    The name of the password to create or get to query the\n",
+    );
+    out.push_str(
+        "    real encrypted password. Names are organized in folders\n",
+    );
+    out.push_str("    separated by '/', a name ending with '/' is a folder.\n");
+    out.push_str(
+        "    Names are at most 255 bytes, without control characters\n",
+    );
+    out.push_str(
+        "    or '.' and '..' folders. Arguments after '--' are never\n",
+    );
+    out.push_str("    taken for flags.\n");
+    out.push_str("\nGLOBAL FLAGS:\n");
+    for long in GLOBAL_FLAGS {
+        out.push_str(&format_flag(flag(long).unwrap()));
+    }
+    out.push_str(
+        "\n    Run 'ssap help <command>' for the flags of a command.\n",
+    );
+    out.push_str("\nEXAMPLES:\n");
+    for example in EXAMPLES {
+        out.push_str(&format!("    {}\n", example));
+    }
+    out
+}
+
+/// The help of a single command, with its usage and flags
+pub fn command_help(command: Command) -> String {
+    let spec = spec(command);
+    let mut out = format!("{}\n\nUSAGE:\n", spec.about);
+    let usage = format!("ssap {} [FLAGS] {}", command.name(), spec.usage);
+    out.push_str(&format!("    {}\n", usage.trim_end()));
+    out.push_str("\nFLAGS:\n");
+    for long in spec.flags.iter().chain(GLOBAL_FLAGS) {
+        out.push_str(&format_flag(flag(long).unwrap()));
+    }
+    if !spec.examples.is_empty() {
+        out.push_str("\nEXAMPLES:\n");
+        for example in spec.examples {
+            out.push_str(&format!("    {}\n", example));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags() {
+        assert_eq!(flag("-c").unwrap().long, "--clipboard");
+        assert_eq!(flag("--clipboard").unwrap().long, "--clipboard");
+        assert!(flag("--unknown").is_none());
+        for spec in COMMANDS {
+            for long in spec.flags.iter().chain(GLOBAL_FLAGS) {
+                assert!(flag(long).is_some(), "{} is not a flag", long);
+            }
+        }
+        assert!(accepts(Command::Get, "--format"));
+        assert!(accepts(Command::New, "--path"));
+        assert!(!accepts(Command::New, "--clipboard"));
+    }
+
+    #[test]
+    fn test_help() {
+        let help = help();
+        assert!(COMMANDS
+            .iter()
+            .all(|s| s.hidden || help.contains(s.command.name())));
+        assert!(!help.contains(Command::ClearClipboard.name()));
+        let get = command_help(Command::Get);
+        assert!(get.contains("ssap get [FLAGS] <name>"));
+        assert!(get.contains("-c, --clipboard"));
+        assert!(!get.contains("--otp"));
+    }
+}
@@ -23,10 +23,9 @@ all
 * SOFTWARE.
 *
 */
-#[allow(non_camel_case_types)]
-pub mod ssap;
-use ssap::parse::parse;
-use ssap::run::run;
+mod cli;
+use cli::parse::parse;
+use cli::run::run;
 use std::env::Args;
 
 fn main() {
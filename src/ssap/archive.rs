@@ -0,0 +1,433 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! The `ssap-json` archive, a portable copy of a vault.
+//!
+//! The archive is a JSON document naming its format and version, so that
+//! it can be read by later versions of ssap whatever the storage of the
+//! vault. It holds every password with its fields and history:
+//!
+//! ```json
+//! {"format": "ssap-json", "version": 1, "encrypted": false,
+//!  "generator": "ssap 1.0.0", "exported": 1700000000,
+//!  "entries": [{"name": "mail", "password": "...", "fields": {...},
+//!               "history": [{"replaced": 1600000000, "password": "...",
+//!                            "fields": {...}}]}]}
+//! ```
+//!
+//! An encrypted archive wraps this document: its header describes the
+//! Argon2id key derivation and the AES-256-GCM encryption of `data`. The
+//! header is authenticated with the data, so it cannot be tampered with.
+
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::json::Json;
+use crate::ssap::otp::unix_time;
+use argon2::{Algorithm, Argon2, Params, Version};
+use openssl::base64;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use rand::RngCore;
+
+/// Name of the format, in the `format` member of every archive
+pub const FORMAT: &str = "ssap-json";
+
+/// Version of the format written by this version of ssap
+pub const VERSION: u64 = 1;
+
+const KDF_ARGON2ID: &str = "argon2id";
+const CIPHER_AES_256_GCM: &str = "aes-256-gcm";
+const TAG_LEN: usize = 16;
+
+/// Highest Argon2id parameters read from an archive, far above the default
+/// ones, so that a crafted file cannot make the import allocate or run
+/// without bound
+const MAX_KDF_MEMORY: u32 = 4 * 1024 * 1024;
+const MAX_KDF_ITERATIONS: u32 = 64;
+const MAX_KDF_PARALLELISM: u32 = 64;
+
+/// A password of an archive, with its previous versions
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub entry: Entry,
+    /// Previous versions, oldest first, with the unix time they were
+    /// replaced at
+    pub history: Vec<(u64, Entry)>,
+}
+
+/// Argon2id parameters of encrypted archives
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveKdf {
+    /// Memory, in KiB
+    pub memory: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for ArchiveKdf {
+    /// The first recommendation of RFC 9106 with less memory: 64 MiB and
+    /// 3 iterations
+    fn default() -> Self {
+        ArchiveKdf {
+            memory: 64 * 1024,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+}
+
+/// Write an archive
+///
+/// # Arguments
+/// * `entries` - The passwords
+/// * `password` - The password of the archive, `None` to write the
+///   passwords in clear
+/// * `kdf` - The parameters of the key derivation
+/// # Returns
+/// * The JSON document
+/// * A SsapError if the encryption fails
+///
+pub fn write(
+    entries: &[ArchiveEntry],
+    password: Option<&str>,
+    kdf: &ArchiveKdf,
+) -> Result<String, SsapError> {
+    let document = Json::Object(vec![
+        ("format".into(), Json::String(FORMAT.into())),
+        ("version".into(), Json::Number(VERSION as f64)),
+        ("encrypted".into(), Json::Bool(false)),
+        (
+            "generator".into(),
+            Json::String(format!("ssap {}", env!("CARGO_PKG_VERSION"))),
+        ),
+        ("exported".into(), Json::Number(unix_time() as f64)),
+        (
+            "entries".into(),
+            Json::Array(entries.iter().map(entry_to_json).collect()),
+        ),
+    ]);
+    let password = match password {
+        Some(password) => password,
+        None => return Ok(document.to_json()),
+    };
+
+    let salt = random_bytes(16)?;
+    let nonce = random_bytes(12)?;
+    let header = vec![
+        ("format".into(), Json::String(FORMAT.into())),
+        ("version".into(), Json::Number(VERSION as f64)),
+        ("encrypted".into(), Json::Bool(true)),
+        (
+            "kdf".into(),
+            Json::Object(vec![
+                ("algorithm".into(), Json::String(KDF_ARGON2ID.into())),
+                ("memory".into(), Json::Number(kdf.memory.into())),
+                ("iterations".into(), Json::Number(kdf.iterations.into())),
+                ("parallelism".into(), Json::Number(kdf.parallelism.into())),
+                ("salt".into(), Json::String(hex::encode(&salt))),
+            ]),
+        ),
+        (
+            "cipher".into(),
+            Json::Object(vec![
+                ("algorithm".into(), Json::String(CIPHER_AES_256_GCM.into())),
+                ("nonce".into(), Json::String(hex::encode(&nonce))),
+            ]),
+        ),
+    ];
+    let key = derive_key(password, &salt, kdf)?;
+    let aad = Json::Object(header.clone()).to_json();
+    let mut tag = [0u8; TAG_LEN];
+    let mut data = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(&nonce),
+        aad.as_bytes(),
+        document.to_json().as_bytes(),
+        &mut tag,
+    )
+    .map_err(|_| SsapError::InvalidArchive)?;
+    data.extend_from_slice(&tag);
+
+    let mut archive = header;
+    archive.push(("data".into(), Json::String(base64::encode_block(&data))));
+    Ok(Json::Object(archive).to_json())
+}
+
+/// Whether an archive is encrypted
+///
+/// # Returns
+/// * `SsapError::InvalidArchive` if the file is not an archive
+/// * `SsapError::UnsupportedArchive` if it was written by a later version
+///
+pub fn is_encrypted(text: &str) -> Result<bool, SsapError> {
+    let archive = parse(text)?;
+    Ok(archive.get("encrypted").and_then(Json::as_bool) == Some(true))
+}
+
+/// Read an archive
+///
+/// # Arguments
+/// * `text` - The JSON document
+/// * `password` - The password of the archive, unused if not encrypted
+/// # Returns
+/// * The passwords of the archive
+/// * `SsapError::InvalidArchivePassword` if the password is wrong or the
+///   archive was modified
+/// * `SsapError::InvalidArchive` if the archive is malformed
+///
+pub fn read(
+    text: &str,
+    password: &str,
+) -> Result<Vec<ArchiveEntry>, SsapError> {
+    let mut archive = parse(text)?;
+    if is_encrypted(text)? {
+        let Json::Object(mut header) = archive else {
+            return Err(SsapError::InvalidArchive);
+        };
+        let data = match header.pop() {
+            Some((key, Json::String(data))) if key == "data" => data,
+            _ => return Err(SsapError::InvalidArchive),
+        };
+        let data = base64::decode_block(&data)
+            .map_err(|_| SsapError::InvalidArchive)?;
+        let header = Json::Object(header);
+        let (kdf, salt) = read_kdf(&header)?;
+        let cipher = header.get("cipher").ok_or(SsapError::InvalidArchive)?;
+        if cipher.get("algorithm").and_then(Json::as_str)
+            != Some(CIPHER_AES_256_GCM)
+            || data.len() < TAG_LEN
+        {
+            return Err(SsapError::UnsupportedArchive);
+        }
+        let nonce = hex_member(cipher, "nonce")?;
+        let key = derive_key(password, &salt, &kdf)?;
+        let (ciphertext, tag) = data.split_at(data.len() - TAG_LEN);
+        let document = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(&nonce),
+            header.to_json().as_bytes(),
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| SsapError::InvalidArchivePassword)?;
+        let document = String::from_utf8(document)
+            .map_err(|_| SsapError::InvalidArchive)?;
+        archive = parse(&document)?;
+    }
+    archive
+        .get("entries")
+        .ok_or(SsapError::InvalidArchive)?
+        .items()
+        .iter()
+        .map(entry_from_json)
+        .collect()
+}
+
+/// Parse an archive and check its format and version
+fn parse(text: &str) -> Result<Json, SsapError> {
+    let archive = Json::parse(text).map_err(|_| SsapError::InvalidArchive)?;
+    if archive.get("format").and_then(Json::as_str) != Some(FORMAT) {
+        return Err(SsapError::InvalidArchive);
+    }
+    match archive.get("version").and_then(Json::as_u64) {
+        Some(version) if version <= VERSION => Ok(archive),
+        Some(_) => Err(SsapError::UnsupportedArchive),
+        None => Err(SsapError::InvalidArchive),
+    }
+}
+
+fn read_kdf(header: &Json) -> Result<(ArchiveKdf, Vec<u8>), SsapError> {
+    let kdf = header.get("kdf").ok_or(SsapError::InvalidArchive)?;
+    if kdf.get("algorithm").and_then(Json::as_str) != Some(KDF_ARGON2ID) {
+        return Err(SsapError::UnsupportedArchive);
+    }
+    let number = |key: &str| {
+        kdf.get(key)
+            .and_then(Json::as_u64)
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or(SsapError::InvalidArchive)
+    };
+    let params = ArchiveKdf {
+        memory: number("memory")?,
+        iterations: number("iterations")?,
+        parallelism: number("parallelism")?,
+    };
+    if params.memory > MAX_KDF_MEMORY
+        || params.iterations > MAX_KDF_ITERATIONS
+        || params.parallelism > MAX_KDF_PARALLELISM
+    {
+        return Err(SsapError::UnsupportedArchive);
+    }
+    Ok((params, hex_member(kdf, "salt")?))
+}
+
+fn hex_member(json: &Json, key: &str) -> Result<Vec<u8>, SsapError> {
+    let value = json.get(key).and_then(Json::as_str);
+    hex::decode(value.ok_or(SsapError::InvalidArchive)?)
+        .map_err(|_| SsapError::InvalidArchive)
+}
+
+fn derive_key(
+    password: &str,
+    salt: &[u8],
+    kdf: &ArchiveKdf,
+) -> Result<Vec<u8>, SsapError> {
+    let params =
+        Params::new(kdf.memory, kdf.iterations, kdf.parallelism, Some(32))
+            .map_err(|_| SsapError::InvalidArchive)?;
+    let mut key = vec![0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|_| SsapError::InvalidKey)?;
+    Ok(key)
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, SsapError> {
+    let mut bytes = vec![0u8; len];
+    if rand::thread_rng().try_fill_bytes(&mut bytes).is_err() {
+        return Err(SsapError::ErrorGeneratingIV);
+    }
+    Ok(bytes)
+}
+
+fn version_to_json(entry: &Entry, members: &mut Vec<(String, Json)>) {
+    members.push(("password".into(), Json::String(entry.password.clone())));
+    let fields = entry
+        .fields
+        .iter()
+        .map(|(key, value)| (key.clone(), Json::String(value.clone())))
+        .collect();
+    members.push(("fields".into(), Json::Object(fields)));
+}
+
+fn entry_to_json(archived: &ArchiveEntry) -> Json {
+    let mut members =
+        vec![("name".into(), Json::String(archived.name.clone()))];
+    version_to_json(&archived.entry, &mut members);
+    let history = archived
+        .history
+        .iter()
+        .map(|(replaced, entry)| {
+            let mut members =
+                vec![("replaced".into(), Json::Number(*replaced as f64))];
+            version_to_json(entry, &mut members);
+            Json::Object(members)
+        })
+        .collect();
+    members.push(("history".into(), Json::Array(history)));
+    Json::Object(members)
+}
+
+fn version_from_json(json: &Json) -> Result<Entry, SsapError> {
+    let password = json.get("password").and_then(Json::as_str);
+    let mut entry =
+        Entry::new(password.ok_or(SsapError::InvalidArchive)?.to_owned());
+    if let Some(Json::Object(fields)) = json.get("fields") {
+        for (key, value) in fields {
+            let value = value.as_str().ok_or(SsapError::InvalidArchive)?;
+            entry.set(key, value.to_owned());
+        }
+    }
+    Ok(entry)
+}
+
+fn entry_from_json(json: &Json) -> Result<ArchiveEntry, SsapError> {
+    let name = json.get("name").and_then(Json::as_str);
+    let mut history = Vec::new();
+    for version in json.get("history").map(Json::items).unwrap_or_default() {
+        let replaced = version.get("replaced").and_then(Json::as_u64);
+        history.push((
+            replaced.ok_or(SsapError::InvalidArchive)?,
+            version_from_json(version)?,
+        ));
+    }
+    Ok(ArchiveEntry {
+        name: name.ok_or(SsapError::InvalidArchive)?.to_owned(),
+        entry: version_from_json(json)?,
+        history,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KDF: ArchiveKdf = ArchiveKdf {
+        memory: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn entries() -> Vec<ArchiveEntry> {
+        let mut entry = Entry::new("p\"w\n2".to_owned());
+        entry.set("username", "me".to_owned());
+        entry.set("notes", "multi\nline".to_owned());
+        vec![ArchiveEntry {
+            name: "work/mail".to_owned(),
+            entry,
+            history: vec![(1_600_000_000, Entry::new("old".to_owned()))],
+        }]
+    }
+
+    #[test]
+    fn test_plaintext() {
+        let text = write(&entries(), None, &KDF).unwrap();
+        assert!(!is_encrypted(&text).unwrap());
+        assert!(text.contains("\"username\":\"me\""));
+        assert_eq!(read(&text, "").unwrap(), entries());
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let text = write(&entries(), Some("secret"), &KDF).unwrap();
+        assert!(is_encrypted(&text).unwrap());
+        assert!(!text.contains("work/mail"));
+        assert_eq!(read(&text, "secret").unwrap(), entries());
+        assert!(matches!(
+            read(&text, "wrong"),
+            Err(SsapError::InvalidArchivePassword)
+        ));
+        // The header is authenticated
+        let tampered = text.replace("\"iterations\":1", "\"iterations\":2");
+        assert!(matches!(
+            read(&tampered, "secret"),
+            Err(SsapError::InvalidArchivePassword)
+        ));
+        let costly = text.replace("\"memory\":1024", "\"memory\":4294967295");
+        assert!(matches!(
+            read(&costly, "secret"),
+            Err(SsapError::UnsupportedArchive)
+        ));
+        let newer = text.replace("\"version\":1", "\"version\":2");
+        assert!(matches!(
+            read(&newer, "secret"),
+            Err(SsapError::UnsupportedArchive)
+        ));
+        assert!(matches!(read("{}", ""), Err(SsapError::InvalidArchive)));
+    }
+}
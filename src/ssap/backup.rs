@@ -0,0 +1,486 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! Rotating backups of a vault.
+//!
+//! Before a command changes the vault, a copy of the vault, still
+//! encrypted, is kept in the `<vault>.backups` directory next to it. Each
+//! backup is named after the UTC time it was taken at, like
+//! `20240229-120000`, and is a copy of the vault file or directory. Old
+//! backups are pruned with a `Retention` policy.
+
+use crate::ssap::error::SsapError;
+use crate::ssap::format::format_time;
+use crate::ssap::storage::StorageKind;
+use crate::ssap::storage::{new_storage, open_storage, write_atomic};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Which backups `prune` keeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    /// Number of most recent backups kept
+    pub keep: usize,
+    /// Number of days, today included, whose last backup is kept
+    pub days: u64,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention { keep: 10, days: 7 }
+    }
+}
+
+/// A backup of a vault
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    /// The name of the backup, as given to `restore`
+    pub id: String,
+    /// The unix time the backup was taken at
+    pub time: u64,
+    pub path: PathBuf,
+}
+
+/// The backups of a vault
+#[derive(Debug, Clone)]
+pub struct Backups {
+    vault: PathBuf,
+    dir: PathBuf,
+}
+
+impl Backups {
+    /// The backups of the vault at `vault`, kept in `<vault>.backups`
+    pub fn new(vault: &Path) -> Self {
+        let mut dir = vault.as_os_str().to_owned();
+        dir.push(".backups");
+        Backups {
+            vault: vault.to_path_buf(),
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    /// The directory holding the backups
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// List the backups, oldest first
+    ///
+    /// # Returns
+    /// * The backups, none if the directory does not exist
+    /// * `SsapError::InvalidPath` if the directory cannot be read
+    ///
+    pub fn list(&self) -> Result<Vec<Backup>, SsapError> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(_e) => return Err(SsapError::InvalidPath),
+        };
+        let mut backups = Vec::new();
+        for file in dir {
+            let file = file.map_err(|_| SsapError::InvalidPath)?;
+            let id = file.file_name().to_string_lossy().into_owned();
+            // Other files, like a backup being written, are ignored
+            if let Some(time) = parse_id(&id) {
+                backups.push(Backup {
+                    id,
+                    time,
+                    path: file.path(),
+                });
+            }
+        }
+        backups.sort_by_key(|backup| (backup.time, sequence(&backup.id)));
+        Ok(backups)
+    }
+
+    /// Get a backup by its id
+    ///
+    /// # Returns
+    /// * `SsapError::BackupNotFound` if there is no such backup
+    ///
+    pub fn get(&self, id: &str) -> Result<Backup, SsapError> {
+        self.list()?
+            .into_iter()
+            .find(|backup| backup.id == id)
+            .ok_or(SsapError::BackupNotFound)
+    }
+
+    /// Copy the vault into a new backup, unless the latest backup holds
+    /// the same vault
+    ///
+    /// # Arguments
+    /// * `now` - The unix time naming the backup
+    /// # Returns
+    /// * The new backup, `None` if there is no vault or it did not change
+    /// * A SsapError if the vault cannot be read or the copy written
+    ///
+    pub fn create(&self, now: u64) -> Result<Option<Backup>, SsapError> {
+        let files = read_files(&self.vault)?;
+        if files.is_empty() {
+            return Ok(None);
+        }
+        let backups = self.list()?;
+        if let Some(latest) = backups.last() {
+            if read_files(&latest.path)? == files {
+                return Ok(None);
+            }
+        }
+        create_private_dir(&self.dir)?;
+
+        // Backups taken in the same second get a suffix
+        let stamp = format_id(now);
+        let mut id = stamp.clone();
+        let mut n = 1;
+        while backups.iter().any(|backup| backup.id == id) {
+            n += 1;
+            id = format!("{}-{}", stamp, n);
+        }
+        // Written under a temporary name, so that an interrupted copy is
+        // never listed
+        let path = self.dir.join(&id);
+        let tmp = self.dir.join(format!(".{}.tmp", id));
+        remove(&tmp)?;
+        write_files(&tmp, &files)?;
+        fs::rename(&tmp, &path).map_err(|_| SsapError::InvalidWrite)?;
+        Ok(Some(Backup {
+            id,
+            time: now,
+            path,
+        }))
+    }
+
+    /// Replace the vault with a backup
+    ///
+    /// A vault file is replaced atomically. A vault directory is written
+    /// by its storage, so that files it does not own, like a `.git`
+    /// directory, are kept.
+    ///
+    /// # Returns
+    /// * `SsapError::BackupNotFound` if there is no such backup
+    /// * A SsapError if the backup cannot be read or the vault written
+    ///
+    pub fn restore(&self, id: &str) -> Result<(), SsapError> {
+        let backup = self.get(id)?;
+        if backup.path.is_dir() {
+            let contents = open_storage(&backup.path)
+                .load()?
+                .ok_or(SsapError::InvalidVersion)?;
+            if self.vault.is_file() {
+                remove(&self.vault)?;
+            }
+            new_storage(&self.vault, StorageKind::Directory).save(&contents)
+        } else {
+            let data =
+                fs::read(&backup.path).map_err(|_| SsapError::InvalidPath)?;
+            if self.vault.is_dir() {
+                remove(&self.vault)?;
+            }
+            write_atomic(&self.vault, &data)
+        }
+    }
+
+    /// Delete the backups not kept by the retention policy
+    ///
+    /// # Arguments
+    /// * `retention` - The backups to keep
+    /// * `now` - The current unix time
+    /// # Returns
+    /// * The deleted backups
+    /// * `SsapError::InvalidWrite` if a backup cannot be deleted
+    ///
+    pub fn prune(
+        &self,
+        retention: &Retention,
+        now: u64,
+    ) -> Result<Vec<Backup>, SsapError> {
+        let backups = self.list()?;
+        let times: Vec<u64> = backups.iter().map(|b| b.time).collect();
+        let kept = retained(&times, retention, now);
+        let mut pruned = Vec::new();
+        for (backup, kept) in backups.into_iter().zip(kept) {
+            if !kept {
+                remove(&backup.path)?;
+                pruned.push(backup);
+            }
+        }
+        Ok(pruned)
+    }
+}
+
+/// Which backups a retention policy keeps: the `keep` most recent ones,
+/// and the most recent of each of the last `days` days
+///
+/// # Arguments
+/// * `times` - The times of the backups, oldest first
+/// * `retention` - The policy
+/// * `now` - The current unix time
+/// # Returns
+/// * Whether each backup is kept
+///
+pub fn retained(times: &[u64], retention: &Retention, now: u64) -> Vec<bool> {
+    let today = now / SECONDS_PER_DAY;
+    let mut kept = vec![false; times.len()];
+    let mut days_seen = Vec::new();
+    for (rank, i) in (0..times.len()).rev().enumerate() {
+        let day = times[i] / SECONDS_PER_DAY;
+        let recent_day = today.saturating_sub(day) < retention.days;
+        if rank < retention.keep || (recent_day && !days_seen.contains(&day)) {
+            kept[i] = true;
+        }
+        if !days_seen.contains(&day) {
+            days_seen.push(day);
+        }
+    }
+    kept
+}
+
+/// Format a unix time as a backup id, `YYYYMMDD-HHMMSS` in UTC
+pub fn format_id(time: u64) -> String {
+    format_time(time).replace(['-', ':'], "").replace(' ', "-")
+}
+
+/// Parse the time of a backup id, ignoring a `-N` suffix
+pub fn parse_id(id: &str) -> Option<u64> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = id.get(range)?;
+        match digits.bytes().all(|b| b.is_ascii_digit()) {
+            true => digits.parse().ok(),
+            false => None,
+        }
+    };
+    if id.get(8..9) != Some("-") {
+        return None;
+    }
+    match id.get(15..) {
+        Some("") => {}
+        Some(suffix) => {
+            number(16..id.len()).filter(|_| suffix.starts_with('-'))?;
+        }
+        None => return None,
+    }
+    let (year, month, day) = (number(0..4)?, number(4..6)?, number(6..8)?);
+    let (hour, minute, second) =
+        (number(9..11)?, number(11..13)?, number(13..15)?);
+    let (y, m) = match month <= 2 {
+        true => (year - 1, month + 9),
+        false => (year, month - 3),
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    (days * 86_400 + hour * 3_600 + minute * 60 + second)
+        .try_into()
+        .ok()
+}
+
+/// The position of a backup among those taken in the same second: the
+/// `N` of a `-N` suffix, 1 without one
+fn sequence(id: &str) -> u64 {
+    id.get(16..).and_then(|n| n.parse().ok()).unwrap_or(1)
+}
+
+/// The files of a vault with their path relative to it: a vault file is a
+/// single file with an empty path. Hidden directories, like `.git`, are
+/// not part of the vault.
+fn read_files(path: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>, SsapError> {
+    let mut files = Vec::new();
+    if path.is_file() {
+        let data = fs::read(path).map_err(|_| SsapError::InvalidPath)?;
+        files.push((PathBuf::new(), data));
+    } else if path.is_dir() {
+        walk(path, Path::new(""), &mut files)?;
+        files.sort();
+    }
+    Ok(files)
+}
+
+fn walk(
+    root: &Path,
+    relative: &Path,
+    files: &mut Vec<(PathBuf, Vec<u8>)>,
+) -> Result<(), SsapError> {
+    let dir = fs::read_dir(root.join(relative))
+        .map_err(|_| SsapError::InvalidPath)?;
+    for file in dir {
+        let file = file.map_err(|_| SsapError::InvalidPath)?;
+        let relative = relative.join(file.file_name());
+        let path = file.path();
+        if path.is_dir() {
+            if !file.file_name().to_string_lossy().starts_with('.') {
+                walk(root, &relative, files)?;
+            }
+        } else {
+            let data = fs::read(&path).map_err(|_| SsapError::InvalidPath)?;
+            files.push((relative, data));
+        }
+    }
+    Ok(())
+}
+
+fn write_files(
+    path: &Path,
+    files: &[(PathBuf, Vec<u8>)],
+) -> Result<(), SsapError> {
+    if let [(relative, data)] = files {
+        if relative.as_os_str().is_empty() {
+            return write_atomic(path, data);
+        }
+    }
+    for (relative, data) in files {
+        let file = path.join(relative);
+        if let Some(parent) = file.parent() {
+            create_private_dir(parent)?;
+        }
+        write_atomic(&file, data)?;
+    }
+    Ok(())
+}
+
+/// Create a directory and its parents, readable only by the user
+fn create_private_dir(path: &Path) -> Result<(), SsapError> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path).map_err(|_| SsapError::InvalidPath)
+}
+
+/// Remove a file or a directory, if it exists
+fn remove(path: &Path) -> Result<(), SsapError> {
+    let removed = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_metadata) => fs::remove_file(path),
+        Err(_e) => return Ok(()),
+    };
+    removed.map_err(|_| SsapError::InvalidWrite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = SECONDS_PER_DAY;
+
+    #[test]
+    fn test_id() {
+        assert_eq!(format_id(0), "19700101-000000");
+        assert_eq!(format_id(1_709_208_000), "20240229-120000");
+        assert_eq!(parse_id("20240229-120000"), Some(1_709_208_000));
+        assert_eq!(parse_id("20240229-120000-2"), Some(1_709_208_000));
+        assert_eq!(parse_id("20240229-120000x"), None);
+        assert_eq!(parse_id(".20240229-120000.tmp"), None);
+        for time in [951_782_400, 1_709_251_199, 4_102_444_800] {
+            assert_eq!(parse_id(&format_id(time)), Some(time));
+        }
+    }
+
+    #[test]
+    fn test_retained() {
+        let now = 100 * DAY + 50;
+        let retention = Retention { keep: 2, days: 3 };
+        // Two backups today, two yesterday, one 2 and 5 days ago
+        let times = [
+            95 * DAY,
+            98 * DAY + 10,
+            99 * DAY + 10,
+            99 * DAY + 20,
+            100 * DAY + 10,
+            100 * DAY + 20,
+        ];
+        assert_eq!(
+            retained(&times, &retention, now),
+            [false, true, false, true, true, true]
+        );
+        let retention = Retention { keep: 0, days: 0 };
+        assert_eq!(retained(&times, &retention, now), [false; 6]);
+    }
+
+    #[test]
+    fn test_backups() {
+        let dir = std::env::temp_dir().join("ssap_test_backups");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let vault = dir.join("vault.ssap");
+        let backups = Backups::new(&vault);
+        assert_eq!(backups.create(DAY).unwrap(), None);
+
+        fs::write(&vault, "one").unwrap();
+        let first = backups.create(DAY).unwrap().unwrap();
+        assert_eq!(first.id, "19700102-000000");
+        // Nothing changed since the last backup
+        assert_eq!(backups.create(DAY).unwrap(), None);
+        fs::write(&vault, "two").unwrap();
+        let second = backups.create(DAY).unwrap().unwrap();
+        assert_eq!(second.id, "19700102-000000-2");
+        assert_eq!(backups.list().unwrap(), [first.clone(), second]);
+
+        backups.restore(&first.id).unwrap();
+        assert_eq!(fs::read_to_string(&vault).unwrap(), "one");
+        assert!(matches!(
+            backups.restore("19700101-000000"),
+            Err(SsapError::BackupNotFound)
+        ));
+
+        let retention = Retention { keep: 1, days: 0 };
+        let pruned = backups.prune(&retention, DAY).unwrap();
+        assert_eq!(pruned, [first]);
+        assert_eq!(backups.list().unwrap().len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_backups_same_second() {
+        let dir = std::env::temp_dir().join("ssap_test_backups_same_second");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let vault = dir.join("vault.ssap");
+        let backups = Backups::new(&vault);
+        for n in 1..=10 {
+            fs::write(&vault, n.to_string()).unwrap();
+            backups.create(DAY).unwrap().unwrap();
+        }
+        let ids: Vec<String> =
+            backups.list().unwrap().into_iter().map(|b| b.id).collect();
+        assert_eq!(ids[1], "19700102-000000-2");
+        assert_eq!(ids[9], "19700102-000000-10");
+        // The latest backup is the one with the highest suffix
+        assert_eq!(backups.create(DAY).unwrap(), None);
+        let retention = Retention { keep: 1, days: 0 };
+        backups.prune(&retention, DAY).unwrap();
+        assert_eq!(backups.list().unwrap()[0].id, "19700102-000000-10");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,432 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! Reader of Bitwarden JSON exports.
+//!
+//! An export is either plain JSON or encrypted. Password protected exports
+//! hold the plain JSON in `data`, encrypted with a key derived from the
+//! export password. Account restricted exports are encrypted with the key
+//! of the Bitwarden account, which never leaves Bitwarden, so they cannot
+//! be read.
+
+use crate::ssap::crypto::hmac_sha256;
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::import::{field_key, name_part};
+use crate::ssap::json::Json;
+use crate::ssap::otp::Otp;
+use crate::ssap::vault::Record;
+use argon2::{Algorithm, Argon2, Params, Version};
+use openssl::base64;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::symm::{decrypt, Cipher};
+use std::collections::HashMap;
+
+const KDF_PBKDF2: u64 = 0;
+const KDF_ARGON2ID: u64 = 1;
+
+/// Highest key derivation parameters allowed by the Bitwarden clients, so
+/// that a crafted export cannot make the import run or allocate without
+/// bound
+const MAX_PBKDF2_ITERATIONS: u64 = 2_000_000;
+const MAX_ARGON2_MEMORY_MIB: u64 = 1024;
+const MAX_ARGON2_ITERATIONS: u64 = 10;
+const MAX_ARGON2_PARALLELISM: u64 = 16;
+
+/// Item types of Bitwarden
+const TYPE_LOGIN: u64 = 1;
+const TYPE_CARD: u64 = 3;
+
+/// Whether an export is protected by a password
+///
+/// # Arguments
+/// * `text` - The content of the export
+/// # Returns
+/// * `true` for password protected exports
+/// * `SsapError::AccountRestrictedExport` for account restricted exports
+/// * `SsapError::InvalidBitwarden` if the file is not a Bitwarden export
+///
+pub fn is_encrypted(text: &str) -> Result<bool, SsapError> {
+    let export = parse(text)?;
+    let encrypted = export.get("encrypted").and_then(Json::as_bool);
+    let protected = export.get("passwordProtected").and_then(Json::as_bool);
+    match (encrypted, protected) {
+        (Some(true), Some(true)) => Ok(true),
+        (Some(true), _) => Err(SsapError::AccountRestrictedExport),
+        _ => Ok(false),
+    }
+}
+
+/// Read the items of an export
+///
+/// # Arguments
+/// * `text` - The content of the export
+/// * `password` - The password of the export, unused if not encrypted
+/// # Returns
+/// * The items converted into ssap records, named after their folder
+/// * `SsapError::InvalidBitwardenPassword` if the password is wrong
+/// * `SsapError::InvalidBitwarden` if the file is corrupted
+///
+pub fn read(text: &str, password: &str) -> Result<Vec<Record>, SsapError> {
+    let mut export = parse(text)?;
+    if is_encrypted(text)? {
+        let keys = Keys::derive(&export, password)?;
+        let validation = export
+            .get("encKeyValidation_DO_NOT_EDIT")
+            .and_then(Json::as_str)
+            .ok_or(SsapError::InvalidBitwarden)?;
+        keys.decrypt(validation)
+            .map_err(|_| SsapError::InvalidBitwardenPassword)?;
+        let data = export
+            .get("data")
+            .and_then(Json::as_str)
+            .ok_or(SsapError::InvalidBitwarden)?;
+        let data = String::from_utf8(keys.decrypt(data)?)
+            .map_err(|_| SsapError::InvalidBitwarden)?;
+        export = parse(&data)?;
+    }
+
+    let folders: HashMap<&str, &str> = export
+        .get("folders")
+        .map(Json::items)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|f| {
+            Some((f.get("id")?.as_str()?, f.get("name")?.as_str()?))
+        })
+        .collect();
+    let items = export
+        .get("items")
+        .ok_or(SsapError::InvalidBitwarden)?
+        .items();
+    Ok(items
+        .iter()
+        .filter(|item| item.get("deletedDate").is_none())
+        .map(|item| to_record(item, &folders))
+        .collect())
+}
+
+fn parse(text: &str) -> Result<Json, SsapError> {
+    match Json::parse(text) {
+        Ok(export @ Json::Object(_)) => Ok(export),
+        _ => Err(SsapError::InvalidBitwarden),
+    }
+}
+
+/// Convert an item into an ssap record. Logins keep their password, cards
+/// their number, the other fields of the item become fields of the entry.
+fn to_record(item: &Json, folders: &HashMap<&str, &str>) -> Record {
+    let text = |json: &Json, key: &str| {
+        json.get(key)
+            .and_then(Json::as_str)
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned)
+    };
+    let mut entry = Entry::new(String::new());
+    let title = text(item, "name").unwrap_or_default();
+    match item.get("type").and_then(Json::as_u64) {
+        Some(TYPE_LOGIN) => {
+            let login = item.get("login").unwrap_or(&Json::Null);
+            entry.password = text(login, "password").unwrap_or_default();
+            if let Some(username) = text(login, "username") {
+                entry.set("username", username);
+            }
+            let uris = login.get("uris").map(Json::items).unwrap_or_default();
+            let uris = uris.iter().filter_map(|uri| text(uri, "uri"));
+            for (i, uri) in uris.enumerate() {
+                match i {
+                    0 => entry.set("url", uri),
+                    _ => entry.set(&format!("url{}", i + 1), uri),
+                }
+            }
+            if let Some(totp) = text(login, "totp") {
+                entry.set("otpauth", otpauth(&totp, &title));
+            }
+        }
+        Some(TYPE_CARD) => {
+            let card = item.get("card").unwrap_or(&Json::Null);
+            entry.password = text(card, "number").unwrap_or_default();
+            for (key, field) in [
+                ("cardholderName", "cardholder"),
+                ("brand", "brand"),
+                ("code", "code"),
+            ] {
+                if let Some(value) = text(card, key) {
+                    entry.set(field, value);
+                }
+            }
+            if let (Some(month), Some(year)) =
+                (text(card, "expMonth"), text(card, "expYear"))
+            {
+                entry.set("expiry", format!("{:0>2}/{}", month, year));
+            }
+        }
+        // Identities and SSH keys are kept as fields
+        _ => {
+            for kind in ["identity", "sshKey"] {
+                if let Some(Json::Object(members)) = item.get(kind) {
+                    for (key, value) in members {
+                        if let Some(value) = value.as_str() {
+                            entry.set(&field_key(key), value.to_owned());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(notes) = text(item, "notes") {
+        entry.set("notes", notes);
+    }
+    for field in item.get("fields").map(Json::items).unwrap_or_default() {
+        let key = text(field, "name").map(|key| field_key(&key));
+        match (key, text(field, "value")) {
+            (Some(key), Some(value)) if entry.get(&key).is_none() => {
+                entry.set(&key, value)
+            }
+            _ => {}
+        }
+    }
+    if item.get("favorite").and_then(Json::as_bool) == Some(true) {
+        entry.set_tags(&["favorite".to_owned()]);
+    }
+    for (key, field) in
+        [("creationDate", "created"), ("revisionDate", "modified")]
+    {
+        if let Some(time) = text(item, key).and_then(|t| parse_time(&t)) {
+            entry.set(field, time.to_string());
+        }
+    }
+
+    let folder = item
+        .get("folderId")
+        .and_then(Json::as_str)
+        .and_then(|id| folders.get(id));
+    let mut name: Vec<String> = match folder {
+        Some(folder) => folder.split('/').map(name_part).collect(),
+        None => Vec::new(),
+    };
+    name.push(name_part(&title));
+    Record {
+        name: name.join("/"),
+        entry,
+    }
+}
+
+/// Bitwarden stores either an `otpauth` URI or a base32 seed
+fn otpauth(totp: &str, title: &str) -> String {
+    if totp.starts_with("otpauth://") {
+        return totp.to_owned();
+    }
+    match Otp::from_seed(&totp.replace(' ', "")) {
+        Ok(mut otp) => {
+            otp.label = title.to_owned();
+            otp.to_uri()
+        }
+        Err(_) => totp.to_owned(),
+    }
+}
+
+/// Parse an ISO 8601 UTC time such as `2024-02-29T12:00:00.000Z`
+fn parse_time(text: &str) -> Option<u64> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        text.get(range)?.parse().ok()
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) =
+        (number(11..13)?, number(14..16)?, number(17..19)?);
+    // Days since 1970-01-01 of the proleptic Gregorian calendar
+    let (y, m) = match month <= 2 {
+        true => (year - 1, month + 9),
+        false => (year, month - 3),
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    (days * 86_400 + hour * 3_600 + minute * 60 + second)
+        .try_into()
+        .ok()
+}
+
+/// Keys of a password protected export
+struct Keys {
+    encryption: Vec<u8>,
+    mac: Vec<u8>,
+}
+
+impl Keys {
+    /// Derive the keys from the export password, then stretch them with
+    /// HKDF as Bitwarden does
+    fn derive(export: &Json, password: &str) -> Result<Keys, SsapError> {
+        let number = |key: &str| {
+            export
+                .get(key)
+                .and_then(Json::as_u64)
+                .ok_or(SsapError::InvalidBitwarden)
+        };
+        let salt = export
+            .get("salt")
+            .and_then(Json::as_str)
+            .ok_or(SsapError::InvalidBitwarden)?;
+        let iterations = number("kdfIterations")?;
+        let mut key = vec![0u8; 32];
+        match number("kdfType")? {
+            KDF_PBKDF2 if iterations > MAX_PBKDF2_ITERATIONS => {
+                return Err(SsapError::InvalidBitwarden)
+            }
+            KDF_PBKDF2 => pbkdf2_hmac(
+                password.as_bytes(),
+                salt.as_bytes(),
+                iterations as usize,
+                MessageDigest::sha256(),
+                &mut key,
+            )
+            .map_err(|_| SsapError::InvalidKey)?,
+            KDF_ARGON2ID => {
+                let salt = hash(MessageDigest::sha256(), salt.as_bytes())
+                    .map_err(|_| SsapError::InvalidKey)?;
+                let memory = number("kdfMemory")?;
+                let parallelism = number("kdfParallelism")?;
+                if memory > MAX_ARGON2_MEMORY_MIB
+                    || iterations > MAX_ARGON2_ITERATIONS
+                    || parallelism > MAX_ARGON2_PARALLELISM
+                {
+                    return Err(SsapError::InvalidBitwarden);
+                }
+                let params = Params::new(
+                    memory as u32 * 1024,
+                    iterations as u32,
+                    parallelism as u32,
+                    Some(32),
+                )
+                .map_err(|_| SsapError::InvalidBitwarden)?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password.as_bytes(), &salt, &mut key)
+                    .map_err(|_| SsapError::InvalidBitwarden)?;
+            }
+            _ => return Err(SsapError::InvalidBitwarden),
+        }
+        Ok(Keys {
+            encryption: hmac_sha256(&key, &[b"enc\x01"])?,
+            mac: hmac_sha256(&key, &[b"mac\x01"])?,
+        })
+    }
+
+    /// Decrypt an encrypted string `2.<iv>|<ciphertext>|<mac>`, the only
+    /// type used by exports: AES-256-CBC with an HMAC-SHA256
+    fn decrypt(&self, encrypted: &str) -> Result<Vec<u8>, SsapError> {
+        let parts: Vec<Vec<u8>> = encrypted
+            .strip_prefix("2.")
+            .ok_or(SsapError::InvalidBitwarden)?
+            .split('|')
+            .map(base64::decode_block)
+            .collect::<Result<_, _>>()
+            .map_err(|_| SsapError::InvalidBitwarden)?;
+        let [iv, ciphertext, mac] = parts.as_slice() else {
+            return Err(SsapError::InvalidBitwarden);
+        };
+        if hmac_sha256(&self.mac, &[iv, ciphertext])? != *mac {
+            return Err(SsapError::InvalidBitwarden);
+        }
+        decrypt(
+            Cipher::aes_256_cbc(),
+            &self.encryption,
+            Some(iv),
+            ciphertext,
+        )
+        .map_err(|_| SsapError::InvalidBitwarden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: &str = include_str!("testdata/bitwarden.json");
+    const PBKDF2: &str = include_str!("testdata/bitwarden-pbkdf2.json");
+    const ARGON2: &str = include_str!("testdata/bitwarden-argon2.json");
+
+    #[test]
+    fn test_read() {
+        assert!(!is_encrypted(PLAIN).unwrap());
+        let records = read(PLAIN, "").unwrap();
+        let names: Vec<&str> =
+            records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["Work/Dev/GitHub", "Bank/Visa", "Wifi", "Wifi"]);
+
+        let github = &records[0].entry;
+        assert_eq!(github.password, "gh-pass");
+        assert_eq!(github.get("username"), Some("octo"));
+        assert_eq!(github.get("url"), Some("https://github.com"));
+        assert_eq!(github.get("url2"), Some("https://gist.github.com"));
+        assert_eq!(github.get("recovery:code"), Some("abcd"));
+        assert_eq!(github.get("linked"), None);
+        assert_eq!(github.tags(), ["favorite"]);
+        assert_eq!(github.get("created"), Some("1672628645"));
+        assert_eq!(github.get("modified"), Some("1709208000"));
+        let otp = Otp::from_uri(github.get("otpauth").unwrap()).unwrap();
+        assert_eq!(otp.label, "GitHub");
+
+        let card = &records[1].entry;
+        assert_eq!(card.password, "4111111111111111");
+        assert_eq!(card.get("expiry"), Some("07/2030"));
+        assert_eq!(card.get("code"), Some("123"));
+        assert_eq!(records[2].entry.get("notes"), Some("ssid: home\npass: x"));
+    }
+
+    #[test]
+    fn test_read_encrypted() {
+        for export in [PBKDF2, ARGON2] {
+            assert!(is_encrypted(export).unwrap());
+            assert_eq!(
+                read(export, "hunter2").unwrap(),
+                read(PLAIN, "").unwrap()
+            );
+            assert!(matches!(
+                read(export, "wrong"),
+                Err(SsapError::InvalidBitwardenPassword)
+            ));
+        }
+        let restricted = r#"{"encrypted": true, "encKeyValidation_DO_NOT_EDIT":
+            "2.AA==|AA==|AA==", "folders": [], "items": []}"#;
+        assert!(matches!(
+            read(restricted, ""),
+            Err(SsapError::AccountRestrictedExport)
+        ));
+        assert!(matches!(read("[]", ""), Err(SsapError::InvalidBitwarden)));
+
+        // Key derivation beyond what Bitwarden allows is not attempted
+        let costly =
+            ARGON2.replace("\"kdfMemory\": 1", "\"kdfMemory\": 4194304");
+        assert!(matches!(
+            read(&costly, "hunter2"),
+            Err(SsapError::InvalidBitwarden)
+        ));
+    }
+}
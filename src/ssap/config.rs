@@ -0,0 +1,685 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::crypto::MAX_KDF_COST;
+use crate::ssap::error::SsapError;
+use crate::ssap::ssap::{ClipboardBackend, Encryption, Ssap};
+use crate::ssap::storage::StorageKind;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the path of the configuration file
+pub const CONFIG_ENV: &str = "SSAP_CONFIG";
+/// Environment variable overriding the vault path of the configuration
+pub const VAULT_ENV: &str = "SSAP_VAULT";
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!@#$%^&()={}[]?";
+
+/// A value of the configuration file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl Value {
+    /// Format the value as TOML
+    pub fn to_toml(&self) -> String {
+        match self {
+            Value::String(s) => escape(s),
+            Value::Integer(n) => n.to_string(),
+            Value::Boolean(b) => b.to_string(),
+        }
+    }
+}
+
+/// The ssap configuration file.
+///
+/// The file is written in a subset of TOML: `[tables]` holding
+/// `key = value` pairs, where values are strings, integers or booleans.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub tables: Vec<(String, Vec<(String, Value)>)>,
+}
+
+impl Config {
+    /// Load the configuration file, an empty configuration if it does not
+    /// exist
+    ///
+    /// # Returns
+    /// * The configuration
+    /// * An error if the file cannot be read or parsed
+    ///
+    pub fn load() -> Result<Config, SsapError> {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        match fs::read_to_string(path) {
+            Ok(content) => Config::parse(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Config::default())
+            }
+            Err(_e) => Err(SsapError::InvalidConfig),
+        }
+    }
+
+    /// Parse the content of a configuration file
+    pub fn parse(content: &str) -> Result<Config, SsapError> {
+        let mut config = Config::default();
+        config.tables.push((String::new(), Vec::new()));
+        for line in content.lines() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(table) = line.strip_prefix('[') {
+                let table = table.strip_suffix(']');
+                if table.is_none() {
+                    return Err(SsapError::InvalidConfig);
+                }
+                config.tables.push((parse_key(table.unwrap())?, Vec::new()));
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (parse_key(key)?, parse_value(value)?),
+                None => return Err(SsapError::InvalidConfig),
+            };
+            config.tables.last_mut().unwrap().1.push((key, value));
+        }
+        Ok(config)
+    }
+
+    /// Get a value, `table` is the empty string for top level keys
+    pub fn get(&self, table: &str, key: &str) -> Option<&Value> {
+        self.table(table)?
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Get a string value
+    pub fn get_str(&self, table: &str, key: &str) -> Option<&str> {
+        match self.get(table, key) {
+            Some(Value::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get all the pairs of a table, in order
+    pub fn table(&self, table: &str) -> Option<&[(String, Value)]> {
+        self.tables
+            .iter()
+            .rev()
+            .find(|(name, _)| name == table)
+            .map(|(_, pairs)| pairs.as_slice())
+    }
+
+    /// Get a query saved in the `[queries]` table
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.get_str("queries", name)
+    }
+
+    /// Apply the configuration to the settings. Command line flags are
+    /// parsed afterwards and override it.
+    ///
+    /// The `[vaults]` table maps names to vault paths. Top level keys are
+    /// `vault`, the name or the path of the default vault, `encryption`,
+    /// `kdf_cost`, `storage`, the backend of new vaults,
+    /// `clip_timeout` and `clipboard_backend`, while the `[generator]`
+    /// table holds the `length` of generated passwords and whether they
+    /// use `lowercase`, `uppercase`, `digits` and `symbols`, or a custom
+    /// `charset`. The `[backup]` table holds whether backups are
+    /// `enabled`, the number of backups to `keep` and the number of `days`
+    /// whose last backup is kept, and the `[git]` table whether commit
+    /// messages `redact` the password names.
+    ///
+    /// # Arguments
+    /// * `ssap` - The settings to update
+    /// # Returns
+    /// * `SsapError::InvalidConfig` if a value has the wrong type or is not
+    ///   valid
+    ///
+    pub fn apply(&self, ssap: &mut Ssap) -> Result<(), SsapError> {
+        for (name, value) in self.table("vaults").unwrap_or_default() {
+            match value {
+                Value::String(path) => {
+                    ssap.vaults.push((name.clone(), expand_home(path).into()))
+                }
+                _ => return Err(SsapError::InvalidConfig),
+            }
+        }
+        if let Some(vault) = self.string("", "vault")? {
+            ssap.path = resolve_vault(&ssap.vaults, vault).into();
+        }
+        if let Some(encryption) = self.string("", "encryption")? {
+            ssap.encryption = Encryption::from_name(encryption)
+                .ok_or(SsapError::InvalidConfig)?;
+        }
+        if let Some(cost) = self.integer("", "kdf_cost")? {
+            if cost == 0 || cost > MAX_KDF_COST {
+                return Err(SsapError::InvalidConfig);
+            }
+            ssap.kdf_cost = cost;
+        }
+        if let Some(storage) = self.string("", "storage")? {
+            ssap.storage = StorageKind::from_name(storage)
+                .ok_or(SsapError::InvalidConfig)?;
+        }
+        if let Some(timeout) = self.integer("", "clip_timeout")? {
+            ssap.clip_timeout = timeout;
+        }
+        if let Some(backend) = self.string("", "clipboard_backend")? {
+            ssap.clipboard_backend = ClipboardBackend::from_name(backend)
+                .ok_or(SsapError::InvalidConfig)?;
+        }
+        if let Some(length) = self.integer("generator", "length")? {
+            ssap.password_len = length;
+        }
+        if let Some(charset) = self.string("generator", "charset")? {
+            ssap.charset = charset.to_owned();
+        } else {
+            let mut charset = String::new();
+            for (key, chars) in [
+                ("lowercase", LOWERCASE),
+                ("uppercase", UPPERCASE),
+                ("digits", DIGITS),
+                ("symbols", SYMBOLS),
+            ] {
+                if self.boolean("generator", key)?.unwrap_or(true) {
+                    charset.push_str(chars);
+                }
+            }
+            ssap.charset = charset;
+        }
+        if ssap.charset.is_empty() {
+            return Err(SsapError::InvalidConfig);
+        }
+        if let Some(enabled) = self.boolean("backup", "enabled")? {
+            ssap.backup = enabled;
+        }
+        if let Some(keep) = self.integer("backup", "keep")? {
+            ssap.backup_retention.keep = keep;
+        }
+        if let Some(days) = self.integer("backup", "days")? {
+            ssap.backup_retention.days = days;
+        }
+        if let Some(redact) = self.boolean("git", "redact")? {
+            ssap.git_redact = redact;
+        }
+        Ok(())
+    }
+
+    fn string(
+        &self,
+        table: &str,
+        key: &str,
+    ) -> Result<Option<&str>, SsapError> {
+        match self.get(table, key) {
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(SsapError::InvalidConfig),
+            None => Ok(None),
+        }
+    }
+
+    fn integer<T: TryFrom<i64>>(
+        &self,
+        table: &str,
+        key: &str,
+    ) -> Result<Option<T>, SsapError> {
+        match self.get(table, key) {
+            Some(Value::Integer(n)) => match T::try_from(*n) {
+                Ok(n) => Ok(Some(n)),
+                Err(_e) => Err(SsapError::InvalidConfig),
+            },
+            Some(_) => Err(SsapError::InvalidConfig),
+            None => Ok(None),
+        }
+    }
+
+    fn boolean(
+        &self,
+        table: &str,
+        key: &str,
+    ) -> Result<Option<bool>, SsapError> {
+        match self.get(table, key) {
+            Some(Value::Boolean(b)) => Ok(Some(*b)),
+            Some(_) => Err(SsapError::InvalidConfig),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Load the settings: the defaults, overridden by the configuration file,
+/// then by the `SSAP_VAULT` environment variable. Command line flags are
+/// applied last by `parse`.
+pub fn load_settings() -> Result<Ssap, SsapError> {
+    let mut ssap = Ssap::default();
+    Config::load()?.apply(&mut ssap)?;
+    match std::env::var_os(VAULT_ENV) {
+        Some(vault) if !vault.is_empty() => {
+            let vault = vault.to_string_lossy();
+            ssap.path = resolve_vault(&ssap.vaults, &vault).into();
+        }
+        _ => {}
+    }
+    Ok(ssap)
+}
+
+/// Resolve the name of a registered vault to its path, anything else is
+/// a path
+fn resolve_vault(vaults: &[(String, Box<Path>)], vault: &str) -> PathBuf {
+    match vaults.iter().find(|(name, _)| name == vault) {
+        Some((_, path)) => path.to_path_buf(),
+        None => expand_home(vault),
+    }
+}
+
+/// Set or remove, with a `None` value, a key of the configuration file,
+/// keeping the rest of the file as it is
+///
+/// # Arguments
+/// * `table` - The table of the key, the empty string for top level keys
+/// * `key` - The key
+/// * `value` - The new value, or `None` to remove the key
+/// # Returns
+/// * An error if the file cannot be read, parsed or written
+///
+pub fn save_value(
+    table: &str,
+    key: &str,
+    value: Option<Value>,
+) -> Result<(), SsapError> {
+    let path = config_path().ok_or(SsapError::InvalidConfig)?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(_e) => return Err(SsapError::InvalidConfig),
+    };
+    let content = update(&content, table, key, value.as_ref())?;
+    if let Some(parent) = path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            return Err(SsapError::InvalidPath);
+        }
+    }
+    if fs::write(&path, content).is_err() {
+        return Err(SsapError::InvalidWrite);
+    }
+    Ok(())
+}
+
+/// Set or remove a key in the content of a configuration file. Comments
+/// and the other lines are kept, a new key goes at the end of its table.
+pub fn update(
+    content: &str,
+    table: &str,
+    key: &str,
+    value: Option<&Value>,
+) -> Result<String, SsapError> {
+    // Check that the file is valid before changing it
+    Config::parse(content)?;
+    let new_line =
+        value.map(|v| format!("{} = {}", format_key(key), v.to_toml()));
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    // Where a new key of the table goes, after its last key
+    let mut insert_at = if table.is_empty() { Some(0) } else { None };
+    let mut done = false;
+    for line in content.lines() {
+        let trimmed = strip_comment(line).trim();
+        if let Some(header) = trimmed.strip_prefix('[') {
+            current = parse_key(header.trim_end_matches(']'))?;
+            lines.push(line.to_owned());
+            if current == table {
+                insert_at = Some(lines.len());
+            }
+            continue;
+        }
+        let line_key = match trimmed.split_once('=') {
+            Some((k, _)) => Some(parse_key(k)?),
+            None => None,
+        };
+        if current == table && line_key.as_deref() == Some(key) {
+            if let Some(new_line) = &new_line {
+                if !done {
+                    lines.push(new_line.clone());
+                }
+            }
+            done = true;
+            continue;
+        }
+        lines.push(line.to_owned());
+        if current == table && line_key.is_some() {
+            insert_at = Some(lines.len());
+        }
+    }
+    if let (false, Some(new_line)) = (done, new_line) {
+        match insert_at {
+            Some(i) => lines.insert(i, new_line),
+            None => {
+                if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                    lines.push(String::new());
+                }
+                lines.push(format!("[{}]", format_key(table)));
+                lines.push(new_line);
+            }
+        }
+    }
+    let mut out = lines.join("\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Path of the default vault: `$XDG_DATA_HOME/ssap/vault.ssap`, falling
+/// back to `~/.local/share/ssap/vault.ssap`, or `./.vault.ssap` without a
+/// home directory.
+pub fn default_vault_path() -> PathBuf {
+    let base = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local").join("share"),
+            None => return PathBuf::from("./.vault.ssap"),
+        },
+    };
+    base.join("ssap").join("vault.ssap")
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Path of the configuration file: `$SSAP_CONFIG` if set, otherwise
+/// `$XDG_CONFIG_HOME/ssap/config.toml`, falling back to
+/// `~/.config/ssap/config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    match std::env::var_os(CONFIG_ENV) {
+        Some(path) if !path.is_empty() => return Some(PathBuf::from(path)),
+        _ => {}
+    }
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("ssap").join("config.toml"))
+}
+
+/// Remove a `#` comment, unless it is inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn parse_key(key: &str) -> Result<String, SsapError> {
+    let key = key.trim();
+    if key.starts_with('"') || key.starts_with('\'') {
+        return match parse_value(key)? {
+            Value::String(s) => Ok(s),
+            _ => Err(SsapError::InvalidConfig),
+        };
+    }
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(SsapError::InvalidConfig);
+    }
+    Ok(key.to_owned())
+}
+
+fn parse_value(value: &str) -> Result<Value, SsapError> {
+    let value = value.trim();
+    if let Some(literal) = value.strip_prefix('\'') {
+        return match literal.strip_suffix('\'') {
+            Some(s) if !s.contains('\'') => Ok(Value::String(s.to_owned())),
+            _ => Err(SsapError::InvalidConfig),
+        };
+    }
+    if let Some(basic) = value.strip_prefix('"') {
+        let basic = basic.strip_suffix('"').ok_or(SsapError::InvalidConfig)?;
+        return unescape(basic).map(Value::String);
+    }
+    match value {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => match value.replace('_', "").parse::<i64>() {
+            Ok(n) => Ok(Value::Integer(n)),
+            Err(_e) => Err(SsapError::InvalidConfig),
+        },
+    }
+}
+
+/// Format a key, quoted if it is not a bare key
+fn format_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_owned()
+    } else {
+        escape(key)
+    }
+}
+
+/// Quote a string as a TOML basic string
+fn escape(value: &str) -> String {
+    let mut out = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => {
+                out.push_str(&format!("\\u{:04X}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn unescape(value: &str) -> Result<String, SsapError> {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            return Err(SsapError::InvalidConfig);
+        }
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(SsapError::InvalidConfig)?;
+                out.push(c);
+            }
+            _ => return Err(SsapError::InvalidConfig),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssap::backup::Retention;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "# ssap configuration\n\
+             answer = 42\n\
+             \n\
+             [queries]\n\
+             prod = \"tag:prod AND NOT tag:legacy\" # comment\n\
+             \"recent github\" = 'modified<90d url~github.com'\n\
+             hash = \"a # b \\\"c\\\"\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("", "answer"), Some(&Value::Integer(42)));
+        assert_eq!(config.query("prod"), Some("tag:prod AND NOT tag:legacy"));
+        assert_eq!(
+            config.query("recent github"),
+            Some("modified<90d url~github.com")
+        );
+        assert_eq!(config.query("hash"), Some("a # b \"c\""));
+        assert!(Config::parse("[queries\n").is_err());
+        assert!(Config::parse("key = \"unterminated\n").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let config = Config::parse(
+            "vault = \"/srv/team.ssap\"\n\
+             encryption = \"aes_128_cbc\"\n\
+             kdf_cost = 1_000\n\
+             clip_timeout = 10\n\
+             [generator]\n\
+             length = 12\n\
+             symbols = false\n\
+             uppercase = false\n\
+             [backup]\n\
+             keep = 3\n\
+             days = 30\n\
+             [git]\n\
+             redact = true\n",
+        )
+        .unwrap();
+        let mut ssap = Ssap::default();
+        config.apply(&mut ssap).unwrap();
+        assert_eq!(ssap.path.to_str(), Some("/srv/team.ssap"));
+        assert!(matches!(ssap.encryption, Encryption::Aes_128_cbc));
+        assert_eq!(ssap.kdf_cost, 1000);
+        assert_eq!(ssap.clip_timeout, 10);
+        assert_eq!(ssap.password_len, 12);
+        assert_eq!(ssap.charset, format!("{}{}", LOWERCASE, DIGITS));
+        assert!(ssap.backup);
+        assert_eq!(ssap.backup_retention, Retention { keep: 3, days: 30 });
+        assert!(ssap.git_redact);
+
+        let mut ssap = Ssap::default();
+        let wrong = Config::parse("clip_timeout = \"10\"\n").unwrap();
+        assert!(wrong.apply(&mut ssap).is_err());
+        for cost in ["-1", "0", "10_000_001"] {
+            let wrong = Config::parse(&format!("kdf_cost = {}\n", cost));
+            assert!(wrong.unwrap().apply(&mut ssap).is_err());
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let content = "# my config\n\
+                       clip_timeout = 10\n\
+                       \n\
+                       [queries]\n\
+                       prod = \"tag:prod\"\n";
+        let team = Value::String("/srv/team \"ssap\".ssap".to_owned());
+        let updated = update(content, "vaults", "team", Some(&team)).unwrap();
+        assert_eq!(
+            updated,
+            format!(
+                "{}\n[vaults]\nteam = \"/srv/team \\\"ssap\\\".ssap\"\n",
+                content
+            )
+        );
+        assert_eq!(
+            Config::parse(&updated).unwrap().get("vaults", "team"),
+            Some(&team)
+        );
+
+        let vault = Value::String("team".to_owned());
+        let updated = update(&updated, "", "vault", Some(&vault)).unwrap();
+        assert!(updated
+            .starts_with("# my config\nclip_timeout = 10\nvault = \"team\"\n"));
+        let timeout = Value::Integer(5);
+        let updated =
+            update(&updated, "", "clip_timeout", Some(&timeout)).unwrap();
+        assert!(updated.contains("clip_timeout = 5\n"));
+        assert!(!updated.contains("clip_timeout = 10"));
+
+        let updated = update(&updated, "vaults", "team", None).unwrap();
+        assert!(updated.contains("[vaults]\n") && !updated.contains("team ="));
+        assert_eq!(
+            update("", "", "vault", Some(&vault)).unwrap(),
+            "vault = \"team\"\n"
+        );
+    }
+
+    #[test]
+    fn test_vaults() {
+        let config = Config::parse(
+            "vault = \"team\"\n[vaults]\nteam = \"/srv/team.ssap\"\n",
+        )
+        .unwrap();
+        let mut ssap = Ssap::default();
+        config.apply(&mut ssap).unwrap();
+        assert_eq!(ssap.vaults.len(), 1);
+        assert_eq!(ssap.path.to_str(), Some("/srv/team.ssap"));
+        assert_eq!(
+            resolve_vault(&ssap.vaults, "other.ssap"),
+            PathBuf::from("other.ssap")
+        );
+    }
+}
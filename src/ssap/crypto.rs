@@ -27,9 +27,93 @@ all
 use crate::ssap::error::SsapError;
 use crate::ssap::ssap::Encryption;
 use openssl::aes::{aes_ige, AesKey};
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
 use openssl::symm::{decrypt, encrypt, Cipher, Mode};
 use rand::RngCore;
 
+/// Default number of PBKDF2 iterations of new vaults
+pub const DEFAULT_KDF_COST: u32 = 600_000;
+
+/// Highest number of PBKDF2 iterations accepted, from a vault file or the
+/// configuration, so that a crafted vault cannot make unlocking run for
+/// hours
+pub const MAX_KDF_COST: u32 = 10_000_000;
+
+/// Key derivation of a vault, stored after the version in the first line
+/// of the vault file as `pbkdf2-sha256:<iterations>:<salt>`.
+///
+/// Vaults created before key derivation have no `Kdf`: their password is
+/// used as the key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kdf {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+}
+
+impl Kdf {
+    /// Create the key derivation of a new vault, with a random salt
+    pub fn new(iterations: u32) -> Result<Kdf, SsapError> {
+        let mut salt = vec![0u8; 16];
+        if let Err(_e) = rand::thread_rng().try_fill_bytes(&mut salt) {
+            return Err(SsapError::ErrorGeneratingIV);
+        }
+        Ok(Kdf { iterations, salt })
+    }
+
+    /// Parse the key derivation stored in the vault file
+    pub fn from_header(header: &str) -> Result<Kdf, SsapError> {
+        let mut parts = header.trim().split(':');
+        if parts.next() != Some("pbkdf2-sha256") {
+            return Err(SsapError::InvalidVersion);
+        }
+        let iterations = parts.next().and_then(|i| i.parse::<u32>().ok());
+        let salt = parts.next().and_then(|s| hex::decode(s).ok());
+        match (iterations, salt, parts.next()) {
+            (Some(iterations), Some(salt), None)
+                if iterations > 0 && iterations <= MAX_KDF_COST =>
+            {
+                Ok(Kdf { iterations, salt })
+            }
+            _ => Err(SsapError::InvalidVersion),
+        }
+    }
+
+    /// Format the key derivation to be stored in the vault file
+    pub fn to_header(&self) -> String {
+        format!(
+            "pbkdf2-sha256:{}:{}",
+            self.iterations,
+            hex::encode(&self.salt)
+        )
+    }
+
+    /// Derive the key of the vault from its password
+    ///
+    /// # Arguments
+    /// * `password` - The password of the vault
+    /// # Returns
+    /// * A 256 bit key
+    /// * An error if the derivation fails
+    ///
+    pub fn derive(&self, password: &[u8]) -> Result<Vec<u8>, SsapError> {
+        let mut key = vec![0u8; 32];
+        let derived = pbkdf2_hmac(
+            password,
+            &self.salt,
+            self.iterations as usize,
+            MessageDigest::sha256(),
+            &mut key,
+        );
+        if derived.is_err() {
+            return Err(SsapError::InvalidKey);
+        }
+        Ok(key)
+    }
+}
+
 /// Encrypt a password using the provided key and encryption algorithm.
 ///
 /// # Arguments
@@ -45,9 +129,9 @@ pub fn encrypt_password(
     key: Vec<u8>,
     encryption: &Encryption,
 ) -> Result<Vec<u8>, SsapError> {
-    let cipher = get_cipher(&encryption);
+    let cipher = get_cipher(encryption);
     let iv = generate_random_iv()?;
-    let padded_key = pad_key(key, &encryption);
+    let padded_key = pad_key(key, encryption);
     let mut ciphertext =
         encrypt(cipher, &padded_key, Some(&iv), &plaintext).unwrap();
 
@@ -71,12 +155,19 @@ pub fn decrypt_password(
     key: Vec<u8>,
     encryption: &Encryption,
 ) -> Result<String, SsapError> {
-    let cipher = get_cipher(&encryption);
+    if ciphertext.len() < 16 {
+        return Err(SsapError::InvalidCiphertext);
+    }
+    let cipher = get_cipher(encryption);
     let iv = &ciphertext[0..16];
     let ciphertext = &ciphertext[16..];
-    let padded_key = pad_key(key, &encryption);
-    let plaintext =
-        decrypt(cipher, &padded_key, Some(iv), &ciphertext).unwrap();
+    let padded_key = pad_key(key, encryption);
+    // A wrong key shows up as a padding error
+    let plaintext = decrypt(cipher, &padded_key, Some(iv), ciphertext);
+    if plaintext.is_err() {
+        return Err(SsapError::ErrorDecrypting);
+    }
+    let plaintext = plaintext.unwrap();
 
     let out = String::from_utf8(plaintext);
     if out.is_err() {
@@ -85,6 +176,17 @@ pub fn decrypt_password(
     Ok(out.unwrap())
 }
 
+/// Compute the HMAC-SHA256 of the concatenation of `parts`
+pub fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> Result<Vec<u8>, SsapError> {
+    let key = PKey::hmac(key).map_err(|_| SsapError::InvalidKey)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)
+        .map_err(|_| SsapError::InvalidKey)?;
+    for part in parts {
+        signer.update(part).map_err(|_| SsapError::InvalidKey)?;
+    }
+    signer.sign_to_vec().map_err(|_| SsapError::InvalidKey)
+}
+
 fn get_cipher(encryption: &Encryption) -> Cipher {
     match encryption {
         Encryption::Aes_128_cbc => Cipher::aes_128_cbc(),
@@ -100,7 +202,7 @@ fn pad_key(key: Vec<u8>, encryption: &Encryption) -> Vec<u8> {
     let mut padded_key = key.clone();
     let n = n_bits / 8;
     if key.len() != n {
-        padded_key.resize(n, 0 as u8);
+        padded_key.resize(n, 0u8);
     }
     padded_key
 }
@@ -129,11 +231,11 @@ pub fn encrypt_aes128_ige(
     mut key: Vec<u8>,
     mut iv: Vec<u8>,
 ) -> Result<Vec<u8>, SsapError> {
-    if plaintext.len() % 16 != 0 {
-        plaintext.resize(plaintext.len() / 16 * 16 + 16, 0 as u8);
+    if !plaintext.len().is_multiple_of(16) {
+        plaintext.resize(plaintext.len() / 16 * 16 + 16, 0u8);
     }
-    if key.len() % 16 != 0 {
-        key.resize(16, 0 as u8);
+    if !key.len().is_multiple_of(16) {
+        key.resize(16, 0u8);
     }
 
     let mut ciphertext: Vec<u8> = Vec::new();
@@ -143,12 +245,12 @@ pub fn encrypt_aes128_ige(
         if aes_key_r.is_err() {
             return Err(SsapError::InvalidKey);
         }
-        let mut out: &mut [u8] = &mut [0; 16];
-        aes_ige(block, &mut out, &aes_key_r.unwrap(), &mut iv, Mode::Encrypt);
+        let out: &mut [u8] = &mut [0; 16];
+        aes_ige(block, out, &aes_key_r.unwrap(), &mut iv, Mode::Encrypt);
         ciphertext.append(&mut Vec::from(out));
     }
 
-    return Ok(ciphertext);
+    Ok(ciphertext)
 }
 
 /// Decrypt ciphertext into plaintext using the provided key for decryption.
@@ -168,11 +270,11 @@ pub fn decrypt_aes128_ige(
     mut key: Vec<u8>,
     mut start_iv: Vec<u8>,
 ) -> Result<Vec<u8>, SsapError> {
-    if ciphertext.len() % 16 != 0 {
+    if !ciphertext.len().is_multiple_of(16) {
         return Err(SsapError::InvalidCiphertext);
     }
-    if key.len() % 16 != 0 {
-        key.resize(16, 0 as u8);
+    if !key.len().is_multiple_of(16) {
+        key.resize(16, 0u8);
     }
 
     let mut plaintext: Vec<u8> = Vec::new();
@@ -182,10 +284,10 @@ pub fn decrypt_aes128_ige(
         if aes_key_r.is_err() {
             return Err(SsapError::InvalidKey);
         }
-        let mut out: &mut [u8] = &mut [0; 16];
+        let out: &mut [u8] = &mut [0; 16];
         aes_ige(
             block,
-            &mut out,
+            out,
             &aes_key_r.unwrap(),
             &mut start_iv,
             Mode::Decrypt,
@@ -193,13 +295,31 @@ pub fn decrypt_aes128_ige(
         plaintext.append(&mut Vec::from(out));
     }
 
-    return Ok(plaintext);
+    Ok(plaintext)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_kdf() {
+        let kdf = Kdf::new(1000).unwrap();
+        assert_eq!(kdf.salt.len(), 16);
+        assert_eq!(Kdf::from_header(&kdf.to_header()).unwrap(), kdf);
+        assert!(Kdf::from_header("scrypt:1000:00").is_err());
+        assert!(Kdf::from_header("pbkdf2-sha256:0:00").is_err());
+        assert!(Kdf::from_header("pbkdf2-sha256:4000000000:00").is_err());
+
+        // RFC 7914, section 11
+        let kdf = Kdf {
+            iterations: 1,
+            salt: b"salt".to_vec(),
+        };
+        let key = kdf.derive(b"passwd").unwrap();
+        assert_eq!(hex::encode(&key[..16]), "55ac046e56e3089fec1691c22544b605");
+    }
+
     #[test]
     fn test_pad_key() {
         let key = vec![0x01, 0x02, 0x03, 0x04];
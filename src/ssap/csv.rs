@@ -0,0 +1,280 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! Reader of the password CSV files exported by browsers.
+
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::import::{field_key, name_part};
+use crate::ssap::vault::Record;
+
+/// Columns of Chrome and Edge exports
+const CHROME_COLUMNS: &[(&str, &str)] = &[
+    ("name", "name"),
+    ("url", "url"),
+    ("username", "username"),
+    ("password", "password"),
+    ("notes", "note"),
+];
+
+/// Columns of Firefox exports, times are in milliseconds
+const FIREFOX_COLUMNS: &[(&str, &str)] = &[
+    ("url", "url"),
+    ("username", "username"),
+    ("password", "password"),
+    ("created", "timeCreated"),
+    ("modified", "timePasswordChanged"),
+];
+
+/// Parse the `--map` option, `name=column,...`, into pairs of an ssap
+/// field and a CSV column
+pub fn parse_mapping(
+    mapping: &str,
+) -> Result<Vec<(String, String)>, SsapError> {
+    mapping
+        .split(',')
+        .map(|pair| match pair.split_once('=') {
+            Some((field, column))
+                if !field.is_empty() && !column.is_empty() =>
+            {
+                Ok((field.trim().to_owned(), column.trim().to_owned()))
+            }
+            _ => Err(SsapError::InvalidCsvMap),
+        })
+        .collect()
+}
+
+/// Read the logins of a CSV file
+///
+/// # Arguments
+/// * `text` - The content of the file, starting with a header
+/// * `mapping` - The CSV column of every ssap field, `None` to recognize
+///   the exports of Chrome, Edge and Firefox from their header
+/// # Returns
+/// * A record for every row, named after the `name` column or the host
+///   of the url
+/// * `SsapError::UnknownCsvColumns` if the header is not recognized
+/// * `SsapError::CsvColumnNotFound` if a mapped column is missing
+///
+pub fn read(
+    text: &str,
+    mapping: Option<&[(String, String)]>,
+) -> Result<Vec<Record>, SsapError> {
+    let mut rows = parse(text.trim_start_matches('\u{feff}'))?.into_iter();
+    let header = rows.next().ok_or(SsapError::InvalidCsv)?;
+    let has = |column: &str| header.iter().any(|h| h == column);
+    let builtin = if has("guid") && has("httpRealm") {
+        FIREFOX_COLUMNS
+    } else if has("name") && has("url") && has("password") {
+        CHROME_COLUMNS
+    } else if mapping.is_none() {
+        return Err(SsapError::UnknownCsvColumns);
+    } else {
+        &[]
+    };
+    let mapping: Vec<(String, String)> = match mapping {
+        Some(mapping) => mapping.to_vec(),
+        None => builtin
+            .iter()
+            .map(|(field, column)| (field.to_string(), column.to_string()))
+            .collect(),
+    };
+
+    let mut columns = Vec::new();
+    for (field, column) in &mapping {
+        match header.iter().position(|h| h == column) {
+            Some(index) => columns.push((field.as_str(), index)),
+            // Older exports lack some optional columns
+            None if builtin.iter().any(|(_, c)| c == column) => {}
+            None => return Err(SsapError::CsvColumnNotFound),
+        }
+    }
+
+    let mut records = Vec::new();
+    for row in rows.filter(|row| row.iter().any(|cell| !cell.is_empty())) {
+        let mut name = String::new();
+        let mut entry = Entry::new(String::new());
+        for (field, index) in &columns {
+            let value = row.get(*index).map(String::as_str).unwrap_or("");
+            match *field {
+                _ if value.is_empty() => {}
+                "name" => name = value.to_owned(),
+                "password" => entry.password = value.to_owned(),
+                "tags" => entry.set_tags(
+                    &value
+                        .split(',')
+                        .map(|t| t.trim().to_owned())
+                        .collect::<Vec<_>>(),
+                ),
+                "created" | "modified" => {
+                    if let Ok(time) = value.parse::<u64>() {
+                        // Firefox writes milliseconds
+                        let time = match time > 100_000_000_000 {
+                            true => time / 1000,
+                            false => time,
+                        };
+                        entry.set(field, time.to_string());
+                    }
+                }
+                field => entry.set(&field_key(field), value.to_owned()),
+            }
+        }
+        if name.is_empty() {
+            name = entry.get("url").map(host).unwrap_or_default();
+        }
+        records.push(Record {
+            name: name_part(&name),
+            entry,
+        });
+    }
+    Ok(records)
+}
+
+/// Remove the records with the same url and username as a previous one
+///
+/// # Returns
+/// * The number of removed records
+///
+pub fn dedupe(records: &mut Vec<Record>) -> usize {
+    let len = records.len();
+    let mut seen: Vec<(String, String)> = Vec::new();
+    records.retain(|record| {
+        let key = (
+            record.entry.get("url").unwrap_or("").to_owned(),
+            record.entry.get("username").unwrap_or("").to_owned(),
+        );
+        if key.0.is_empty() && key.1.is_empty() {
+            return true;
+        }
+        let duplicate = seen.contains(&key);
+        seen.push(key);
+        !duplicate
+    });
+    len - records.len()
+}
+
+/// The host of an url, `https://user@www.x.com:443/login` is `www.x.com`
+fn host(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit('@').next().unwrap_or("");
+    host.split(':').next().unwrap_or("").to_owned()
+}
+
+/// Parse CSV rows (RFC 4180): fields are separated by commas and may be
+/// quoted with `"`, holding commas, newlines and doubled `""` quotes
+fn parse(text: &str) -> Result<Vec<Vec<String>>, SsapError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(SsapError::InvalidCsv);
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let rows = parse("a,\"b,\"\"c\"\"\",\r\n\"multi\nline\",x\n").unwrap();
+        assert_eq!(rows, [vec!["a", "b,\"c\"", ""], vec!["multi\nline", "x"]]);
+        assert!(parse("\"open").is_err());
+    }
+
+    #[test]
+    fn test_browsers() {
+        let chrome = "name,url,username,password,note\n\
+                      github.com,https://github.com/login,me,pw1,\n\
+                      github.com,https://github.com/login,me,pw1,\n\
+                      ,https://user@gitlab.com:443/x,me,pw2,a note\n";
+        let mut records = read(chrome, None).unwrap();
+        assert_eq!(dedupe(&mut records), 1);
+        assert_eq!(records[0].name, "github.com");
+        assert_eq!(records[1].name, "gitlab.com");
+        assert_eq!(records[1].entry.get("notes"), Some("a note"));
+
+        let firefox = "\"url\",\"username\",\"password\",\"httpRealm\",\
+                       \"formActionOrigin\",\"guid\",\"timeCreated\",\
+                       \"timeLastUsed\",\"timePasswordChanged\"\n\
+                       \"https://x.com\",\"me\",\"p,w\",,\"\",\"{1}\",\
+                       \"1700000000000\",\"1700000000000\",\"1710000000000\"\n";
+        let records = read(firefox, None).unwrap();
+        assert_eq!(records[0].name, "x.com");
+        assert_eq!(records[0].entry.password, "p,w");
+        assert_eq!(records[0].entry.get("created"), Some("1700000000"));
+        assert_eq!(records[0].entry.get("modified"), Some("1710000000"));
+    }
+
+    #[test]
+    fn test_mapping() {
+        let text = "Title,Secret,Login,Group\nbank,pw,me,personal\n";
+        assert!(matches!(
+            read(text, None),
+            Err(SsapError::UnknownCsvColumns)
+        ));
+        let mapping = parse_mapping(
+            "name=Title,password=Secret,username=Login,group=Group",
+        )
+        .unwrap();
+        let records = read(text, Some(&mapping)).unwrap();
+        assert_eq!(records[0].name, "bank");
+        assert_eq!(records[0].entry.password, "pw");
+        assert_eq!(records[0].entry.get("group"), Some("personal"));
+        let mapping = parse_mapping("password=Missing").unwrap();
+        assert!(matches!(
+            read(text, Some(&mapping)),
+            Err(SsapError::CsvColumnNotFound)
+        ));
+        assert!(parse_mapping("name").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::error::SsapError;
+
+/// A decrypted vault entry.
+///
+/// The plaintext of an entry holds the password on the first line and one
+/// `key: value` field on every following line. Entries written before fields
+/// existed only contain the password, so they are read back as entries
+/// without fields.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub password: String,
+    pub fields: Vec<(String, String)>,
+}
+
+impl Entry {
+    /// Create a new entry holding only a password
+    pub fn new(password: String) -> Self {
+        Entry {
+            password,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Get the value of a field, if present
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set the value of a field, replacing any previous value
+    pub fn set(&mut self, key: &str, value: String) {
+        if let Some(field) = self.fields.iter_mut().find(|(k, _)| k == key) {
+            field.1 = value;
+        } else {
+            self.fields.push((key.to_owned(), value));
+        }
+    }
+
+    /// Remove a field, returning its previous value
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let idx = self.fields.iter().position(|(k, _)| k == key)?;
+        Some(self.fields.remove(idx).1)
+    }
+
+    /// Tags of the entry, stored comma separated in the `tags` field
+    pub fn tags(&self) -> Vec<String> {
+        match self.get("tags") {
+            Some(tags) => tags
+                .split(',')
+                .map(|t| t.trim())
+                .filter(|t| !t.is_empty())
+                .map(|t| t.to_owned())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Replace the tags of the entry
+    pub fn set_tags(&mut self, tags: &[String]) {
+        if tags.is_empty() {
+            self.remove("tags");
+        } else {
+            self.set("tags", tags.join(","));
+        }
+    }
+
+    /// Serialize the entry into the plaintext that gets encrypted
+    pub fn to_plaintext(&self) -> String {
+        let mut out = escape(&self.password);
+        for (key, value) in &self.fields {
+            out.push('\n');
+            out.push_str(&escape(key));
+            out.push_str(": ");
+            out.push_str(&escape(value));
+        }
+        out
+    }
+
+    /// Parse a decrypted plaintext into an entry
+    ///
+    /// # Arguments
+    /// * `plaintext` - The decrypted entry
+    /// # Returns
+    /// * The parsed entry
+    /// * An error if a field line is malformed
+    ///
+    pub fn from_plaintext(plaintext: &str) -> Result<Entry, SsapError> {
+        let mut lines = plaintext.split('\n');
+        let mut entry = Entry::new(unescape(lines.next().unwrap_or("")));
+        for line in lines {
+            if line.is_empty() {
+                continue;
+            }
+            match line.split_once(": ") {
+                Some((key, value)) => {
+                    entry.fields.push((unescape(key), unescape(value)))
+                }
+                None => return Err(SsapError::ErrorDecrypting),
+            }
+        }
+        Ok(entry)
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_password() {
+        let entry = Entry::from_plaintext("NTp9g5yWgr%TU$f@!hwh").unwrap();
+        assert_eq!(entry.password, "NTp9g5yWgr%TU$f@!hwh");
+        assert!(entry.fields.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut entry = Entry::new("pa\\ss\nword".to_owned());
+        entry.set("otpauth", "otpauth://totp/x?secret=AAAA".to_owned());
+        entry.set("notes", "line one\nline: two".to_owned());
+        let parsed = Entry::from_plaintext(&entry.to_plaintext()).unwrap();
+        assert_eq!(parsed, entry);
+        assert_eq!(parsed.get("notes"), Some("line one\nline: two"));
+    }
+}
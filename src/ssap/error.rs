@@ -35,20 +35,78 @@ pub enum SsapError {
     InvalidPath,
     InvalidPasswordName,
     InvalidEncryptionName,
+    InvalidStorage,
     InvalidCommand,
+    InvalidFlag,
+    InvalidShell,
     InvalidPasswordLength,
     InvalidPassword,
     InvalidVersion,
+    InvalidClipTimeout,
+    InvalidClipboardBackend,
+    InvalidFormat,
+    UnsupportedFormat,
     PasswordMismatch,
     PasswordAlreadyRegistered,
     MissingPasswordName,
     MissingPasswordLength,
     MissingPath,
+    MissingDestination,
+    MissingQuery,
+    InvalidRegex,
+    InvalidQuery,
+    InvalidTag,
+    QueryNotFound,
+    InvalidConfig,
+    InvalidVaultName,
+    VaultNotFound,
+    VaultLocked,
+    VaultExists,
+    InvalidKdbx,
+    InvalidKdbxPassword,
+    UnsupportedKdbx,
+    KdbxKdfTooExpensive,
+    InvalidImportFormat,
+    MissingImportFormat,
+    InvalidExportFormat,
+    MissingExportFormat,
+    FileExists,
+    InvalidJson,
+    InvalidBitwarden,
+    InvalidBitwardenPassword,
+    AccountRestrictedExport,
+    InvalidCsv,
+    InvalidCsvMap,
+    UnknownCsvColumns,
+    CsvColumnNotFound,
+    GpgNotFound,
+    InvalidArchive,
+    InvalidArchivePassword,
+    UnsupportedArchive,
+    MissingArchiveMode,
+    BackupNotFound,
+    IncompatibleVaults,
+    GitNotFound,
+    GitFailed,
+    NotAGitVault,
+    VaultAlreadyRegistered,
+    InvalidField,
+    MissingCommand,
+    MissingTemplate,
+    InvalidTemplate,
+    FieldNotFound,
+    FileNotGenerated,
+    InvalidEnv,
     ErrorDecrypting,
     ErrorSavingClipboard,
+    ErrorClearingClipboard,
     ErrorGeneratingPassword,
     ErrorGeneratingIV,
+    ErrorRunningCommand,
     PasswordNameNotFound,
+    InvalidOtp,
+    MissingOtp,
+    OtpNotFound,
 }
 
 impl Display for SsapError {
@@ -61,15 +119,38 @@ impl Display for SsapError {
             SsapError::InvalidPasswordName => {
                 write!(f, "Invalid password name")
             }
+            SsapError::InvalidStorage => write!(
+                f,
+                "Storage not supported, use text, sqlite or directory"
+            ),
             SsapError::InvalidEncryptionName => {
                 write!(f, "Invalid encryption name")
             }
             SsapError::InvalidCommand => write!(f, "Invalid command"),
+            SsapError::InvalidFlag => write!(
+                f,
+                "Flag not supported by this command, see ssap help <command>"
+            ),
+            SsapError::InvalidShell => {
+                write!(f, "Unsupported shell, expected bash, zsh or fish")
+            }
             SsapError::InvalidPasswordLength => {
                 write!(f, "Invalid password length")
             }
             SsapError::InvalidPassword => write!(f, "Invalid password"),
-            SsapError::InvalidVersion => write!(f, "Invalid version in valut file"),
+            SsapError::InvalidVersion => {
+                write!(f, "Invalid version in valut file")
+            }
+            SsapError::InvalidClipTimeout => {
+                write!(f, "Invalid clipboard timeout")
+            }
+            SsapError::InvalidClipboardBackend => {
+                write!(f, "Invalid clipboard backend")
+            }
+            SsapError::InvalidFormat => write!(f, "Invalid output format"),
+            SsapError::UnsupportedFormat => {
+                write!(f, "Output format not supported by this command")
+            }
             SsapError::PasswordMismatch => write!(f, "Password mismatch"),
             SsapError::PasswordAlreadyRegistered => {
                 write!(f, "Password already registered")
@@ -81,17 +162,149 @@ impl Display for SsapError {
                 write!(f, "Missing password length")
             }
             SsapError::MissingPath => write!(f, "Missing path"),
+            SsapError::MissingDestination => write!(f, "Missing destination"),
+            SsapError::MissingQuery => write!(f, "Missing search query"),
+            SsapError::InvalidRegex => write!(f, "Invalid regular expression"),
+            SsapError::InvalidQuery => write!(f, "Invalid query"),
+            SsapError::InvalidTag => write!(f, "Invalid tag"),
+            SsapError::QueryNotFound => write!(f, "Saved query not found"),
+            SsapError::InvalidConfig => write!(f, "Invalid configuration file"),
+            SsapError::InvalidVaultName => write!(
+                f,
+                "Invalid vault name, use letters, digits, '-' and '_'"
+            ),
+            SsapError::VaultNotFound => write!(f, "Vault not registered"),
+            SsapError::VaultLocked => write!(f, "Vault is locked"),
+            SsapError::InvalidKdbx => {
+                write!(f, "Not a KDBX database, or the database is corrupted")
+            }
+            SsapError::InvalidKdbxPassword => {
+                write!(f, "Wrong password for the KDBX database")
+            }
+            SsapError::UnsupportedKdbx => write!(
+                f,
+                "Only KDBX 4 databases with AES or ChaCha20 are supported"
+            ),
+            SsapError::KdbxKdfTooExpensive => write!(
+                f,
+                "The key derivation of the KDBX database is too expensive"
+            ),
+            SsapError::InvalidImportFormat => {
+                write!(f, "Import format not supported, see ssap help import")
+            }
+            SsapError::MissingImportFormat => {
+                write!(f, "Missing the format of the file, use --from")
+            }
+            SsapError::InvalidExportFormat => {
+                write!(f, "Export format not supported, see ssap help export")
+            }
+            SsapError::MissingExportFormat => {
+                write!(f, "Missing the format of the file, use --to")
+            }
+            SsapError::InvalidJson => write!(f, "Invalid JSON"),
+            SsapError::InvalidBitwarden => {
+                write!(f, "Not a Bitwarden export, or the export is corrupted")
+            }
+            SsapError::InvalidBitwardenPassword => {
+                write!(f, "Wrong password for the Bitwarden export")
+            }
+            SsapError::AccountRestrictedExport => write!(
+                f,
+                "Account restricted Bitwarden exports cannot be decrypted \
+                 outside Bitwarden, export with a password instead"
+            ),
+            SsapError::InvalidCsv => write!(f, "Invalid CSV file"),
+            SsapError::InvalidCsvMap => {
+                write!(f, "Invalid --map, expected field=column,...")
+            }
+            SsapError::UnknownCsvColumns => write!(
+                f,
+                "Unknown CSV columns, describe them with --map field=column,..."
+            ),
+            SsapError::CsvColumnNotFound => {
+                write!(f, "A column given with --map is not in the CSV header")
+            }
+            SsapError::GpgNotFound => {
+                write!(f, "gpg not found, install GnuPG to import from pass")
+            }
+            SsapError::IncompatibleVaults => write!(
+                f,
+                "The vaults have different passwords, they cannot be merged"
+            ),
+            SsapError::GitNotFound => {
+                write!(f, "git not found, install it to sync the vault")
+            }
+            SsapError::GitFailed => write!(f, "git failed, see its output"),
+            SsapError::NotAGitVault => write!(
+                f,
+                "The vault is not in a git repository, see ssap git init"
+            ),
+            SsapError::BackupNotFound => {
+                write!(f, "Backup not found, see ssap backup list")
+            }
+            SsapError::InvalidArchive => {
+                write!(f, "Not an ssap archive, or the archive is corrupted")
+            }
+            SsapError::InvalidArchivePassword => write!(
+                f,
+                "Wrong password for the archive, or the archive was modified"
+            ),
+            SsapError::UnsupportedArchive => {
+                write!(f, "Archive written by a newer version of ssap")
+            }
+            SsapError::MissingArchiveMode => write!(
+                f,
+                "Use --encrypt, or --plaintext to write the passwords in clear"
+            ),
+            SsapError::FileExists => {
+                write!(
+                    f,
+                    "The file already exists, use --force to overwrite it"
+                )
+            }
+            SsapError::VaultExists => {
+                write!(f, "A vault already exists at the destination")
+            }
+            SsapError::VaultAlreadyRegistered => {
+                write!(f, "Vault already registered")
+            }
+            SsapError::InvalidField => {
+                write!(f, "Invalid field, expected KEY=VALUE")
+            }
+            SsapError::MissingCommand => write!(f, "Missing command"),
+            SsapError::MissingTemplate => write!(f, "Missing template path"),
+            SsapError::InvalidTemplate => write!(f, "Invalid template"),
+            SsapError::FieldNotFound => write!(f, "Field not found"),
+            SsapError::FileNotGenerated => write!(
+                f,
+                "Output file was not generated by ssap, use --force to \
+                 overwrite it"
+            ),
+            SsapError::InvalidEnv => {
+                write!(f, "Invalid environment variable, expected KEY=NAME")
+            }
             SsapError::ErrorDecrypting => write!(f, "Error decrypting"),
             SsapError::ErrorSavingClipboard => {
                 write!(f, "Error saving clipboard")
             }
+            SsapError::ErrorClearingClipboard => {
+                write!(f, "Error clearing clipboard")
+            }
             SsapError::ErrorGeneratingPassword => {
                 write!(f, "Error generating password")
             }
             SsapError::ErrorGeneratingIV => write!(f, "Error generating IV"),
+            SsapError::ErrorRunningCommand => {
+                write!(f, "Error running command")
+            }
             SsapError::PasswordNameNotFound => {
                 write!(f, "Password name not found")
             }
+            SsapError::InvalidOtp => write!(f, "Invalid OTP secret"),
+            SsapError::MissingOtp => write!(f, "Missing OTP secret"),
+            SsapError::OtpNotFound => {
+                write!(f, "No OTP secret stored for this password")
+            }
         }
     }
 }
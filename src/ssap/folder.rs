@@ -0,0 +1,205 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+/// Separator between the folders of a password name
+pub const SEPARATOR: char = '/';
+
+/// Normalize a password name or folder: leading, trailing and repeated
+/// separators are removed, so `/work//github/` becomes `work/github`.
+pub fn normalize(name: &str) -> String {
+    name.split(SEPARATOR)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+/// Whether the name designates a folder, i.e. ends with a separator
+pub fn is_folder(name: &str) -> bool {
+    name.ends_with(SEPARATOR)
+}
+
+/// Whether `name` is inside `folder`, at any depth. Every name is inside
+/// the root folder, the empty string.
+pub fn in_folder(name: &str, folder: &str) -> bool {
+    let folder = normalize(folder);
+    folder.is_empty()
+        || name
+            .strip_prefix(&folder)
+            .is_some_and(|rest| rest.starts_with(SEPARATOR))
+}
+
+/// Last segment of a name, `github` for `work/github`
+pub fn basename(name: &str) -> &str {
+    name.rsplit(SEPARATOR).next().unwrap_or(name)
+}
+
+/// Compute the new name of `name` when moving `src` to `dst`. When `src`
+/// is a folder every name inside it keeps its path relative to the folder.
+///
+/// # Arguments
+/// * `name` - The name to move
+/// * `src` - The moved password or folder, normalized
+/// * `dst` - The destination, normalized
+/// # Returns
+/// * The new name, or `None` if `name` is not affected by the move
+///
+pub fn moved_name(name: &str, src: &str, dst: &str) -> Option<String> {
+    if name == src {
+        return Some(dst.to_owned());
+    }
+    let rest = name.strip_prefix(src)?.strip_prefix(SEPARATOR)?;
+    if dst.is_empty() {
+        Some(rest.to_owned())
+    } else {
+        Some(format!("{}/{}", dst, rest))
+    }
+}
+
+/// Render the names inside `folder` as a tree
+///
+/// # Arguments
+/// * `names` - The names registered in the vault
+/// * `folder` - The folder to render, the empty string for the root
+/// # Returns
+/// * The tree, one line per password or folder
+///
+pub fn format_tree(names: &[String], folder: &str) -> String {
+    let folder = normalize(folder);
+    let mut paths: Vec<Vec<&str>> = names
+        .iter()
+        .filter(|n| in_folder(n, &folder))
+        .map(|n| {
+            let rest = n[folder.len()..].trim_start_matches(SEPARATOR);
+            rest.split(SEPARATOR).collect()
+        })
+        .collect();
+    paths.sort();
+
+    let mut out = if folder.is_empty() {
+        ".\n".to_owned()
+    } else {
+        format!("{}/\n", folder)
+    };
+    format_level(&paths, 0, "", &mut out);
+    out
+}
+
+fn format_level(
+    paths: &[Vec<&str>],
+    depth: usize,
+    indent: &str,
+    out: &mut String,
+) {
+    // Children of this level, in order, each with the paths below it
+    let mut children: Vec<(&str, Vec<Vec<&str>>, bool)> = Vec::new();
+    for path in paths.iter().filter(|p| p.len() > depth) {
+        let leaf = path.len() == depth + 1;
+        match children.last_mut() {
+            Some((name, below, is_leaf)) if *name == path[depth] => {
+                if leaf {
+                    *is_leaf = true;
+                } else {
+                    below.push(path.clone());
+                }
+            }
+            _ => {
+                let below = if leaf { vec![] } else { vec![path.clone()] };
+                children.push((path[depth], below, leaf));
+            }
+        }
+    }
+
+    for (i, (name, below, is_leaf)) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let branch = if last { "└── " } else { "├── " };
+        // A password can share its name with a folder
+        let suffix = if below.is_empty() {
+            ""
+        } else if *is_leaf {
+            " (and folder)"
+        } else {
+            "/"
+        };
+        out.push_str(&format!("{}{}{}{}\n", indent, branch, name, suffix));
+        let indent =
+            format!("{}{}", indent, if last { "    " } else { "│   " });
+        format_level(below, depth + 1, &indent, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/work//github/"), "work/github");
+        assert!(in_folder("work/github", "work/"));
+        assert!(!in_folder("workshop/github", "work"));
+        assert!(in_folder("github", ""));
+    }
+
+    #[test]
+    fn test_moved_name() {
+        assert_eq!(
+            moved_name("work/github", "work", "old").unwrap(),
+            "old/github"
+        );
+        assert_eq!(moved_name("work", "work", "job").unwrap(), "job");
+        assert_eq!(moved_name("work/a/b", "work/a", "").unwrap(), "b");
+        assert!(moved_name("workshop", "work", "job").is_none());
+    }
+
+    #[test]
+    fn test_format_tree() {
+        let names: Vec<String> = [
+            "work/github",
+            "personal/github",
+            "work/aws/prod",
+            "work/aws",
+        ]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+        assert_eq!(
+            format_tree(&names, "work/"),
+            "work/\n\
+             ├── aws (and folder)\n\
+             │   └── prod\n\
+             └── github\n"
+        );
+        assert_eq!(
+            format_tree(&names, ""),
+            ".\n\
+             ├── personal/\n\
+             │   └── github\n\
+             └── work/\n    \
+                 ├── aws (and folder)\n    \
+                 │   └── prod\n    \
+                 └── github\n"
+        );
+    }
+}
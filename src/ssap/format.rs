@@ -0,0 +1,332 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::ssap::OutputFormat;
+
+/// Format a decrypted entry for stdout
+///
+/// # Arguments
+/// * `name` - The name of the entry
+/// * `entry` - The decrypted entry
+/// * `format` - The output format
+/// # Returns
+/// * The formatted entry, ending with a newline except for `raw`
+///
+pub fn format_entry(
+    name: &str,
+    entry: &Entry,
+    format: &OutputFormat,
+) -> String {
+    match format {
+        OutputFormat::Text => {
+            format!("> Decrypted Password: {}\n", entry.password)
+        }
+        OutputFormat::Raw => entry.password.clone(),
+        OutputFormat::Json => {
+            let fields: Vec<String> = entry
+                .fields
+                .iter()
+                .map(|(k, v)| format!("{}: {}", json_string(k), json_string(v)))
+                .collect();
+            format!(
+                "{{\"name\": {}, \"password\": {}, \"fields\": {{{}}}}}\n",
+                json_string(name),
+                json_string(&entry.password),
+                fields.join(", ")
+            )
+        }
+        OutputFormat::Env => {
+            let mut out = format!("NAME={}\n", shell_quote(name));
+            out.push_str(&format!(
+                "PASSWORD={}\n",
+                shell_quote(&entry.password)
+            ));
+            for (key, value) in &entry.fields {
+                out.push_str(&format!(
+                    "{}={}\n",
+                    env_key(key),
+                    shell_quote(value)
+                ));
+            }
+            out
+        }
+        OutputFormat::Yaml => {
+            let mut out = format!("name: {}\n", json_string(name));
+            out.push_str(&format!(
+                "password: {}\n",
+                json_string(&entry.password)
+            ));
+            if entry.fields.is_empty() {
+                out.push_str("fields: {}\n");
+            } else {
+                out.push_str("fields:\n");
+            }
+            for (key, value) in &entry.fields {
+                out.push_str(&format!(
+                    "  {}: {}\n",
+                    json_string(key),
+                    json_string(value)
+                ));
+            }
+            out
+        }
+    }
+}
+
+/// Format an OTP code for stdout
+///
+/// # Arguments
+/// * `name` - The name of the password holding the OTP secret
+/// * `code` - The code
+/// * `format` - The output format
+/// # Returns
+/// * The formatted code
+///
+pub fn format_code(name: &str, code: &str, format: &OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => format!("> OTP code: {}\n", code),
+        OutputFormat::Raw => code.to_owned(),
+        OutputFormat::Json => format!(
+            "{{\"name\": {}, \"code\": {}}}\n",
+            json_string(name),
+            json_string(code)
+        ),
+        OutputFormat::Env => {
+            format!("NAME={}\nCODE={}\n", shell_quote(name), shell_quote(code))
+        }
+        OutputFormat::Yaml => format!(
+            "name: {}\ncode: {}\n",
+            json_string(name),
+            json_string(code)
+        ),
+    }
+}
+
+/// Format a list of entry names for stdout
+///
+/// # Arguments
+/// * `names` - The names to list
+/// * `format` - The output format
+/// # Returns
+/// * The formatted list
+/// * An error for formats that cannot represent a list
+///
+pub fn format_names(
+    names: &[String],
+    format: &OutputFormat,
+) -> Result<String, SsapError> {
+    let out = match format {
+        OutputFormat::Text => {
+            names.iter().map(|n| format!(">  - {}\n", n)).collect()
+        }
+        OutputFormat::Raw => names.iter().map(|n| format!("{}\n", n)).collect(),
+        OutputFormat::Json => {
+            let names: Vec<String> =
+                names.iter().map(|n| json_string(n)).collect();
+            format!("[{}]\n", names.join(", "))
+        }
+        OutputFormat::Yaml if names.is_empty() => "[]\n".to_owned(),
+        OutputFormat::Yaml => names
+            .iter()
+            .map(|n| format!("- {}\n", json_string(n)))
+            .collect(),
+        OutputFormat::Env => return Err(SsapError::UnsupportedFormat),
+    };
+    Ok(out)
+}
+
+/// Format the results of `find`, best match first
+///
+/// # Arguments
+/// * `matches` - The name, score and matched field of every result
+/// * `format` - The output format
+/// # Returns
+/// * The formatted results, one name per line for `text` and `raw` so they
+///   can be piped into fzf
+/// * An error for formats that cannot represent a list
+///
+pub fn format_matches(
+    matches: &[(String, i64, String)],
+    format: &OutputFormat,
+) -> Result<String, SsapError> {
+    let out = match format {
+        OutputFormat::Text | OutputFormat::Raw => matches
+            .iter()
+            .map(|(name, _, _)| format!("{}\n", name))
+            .collect(),
+        OutputFormat::Json => {
+            let records: Vec<String> = matches
+                .iter()
+                .map(|(name, score, field)| {
+                    format!(
+                        "{{\"name\": {}, \"score\": {}, \"match\": {}}}",
+                        json_string(name),
+                        score,
+                        json_string(field)
+                    )
+                })
+                .collect();
+            format!("[{}]\n", records.join(", "))
+        }
+        OutputFormat::Yaml if matches.is_empty() => "[]\n".to_owned(),
+        OutputFormat::Yaml => matches
+            .iter()
+            .map(|(name, score, field)| {
+                format!(
+                    "- name: {}\n  score: {}\n  match: {}\n",
+                    json_string(name),
+                    score,
+                    json_string(field)
+                )
+            })
+            .collect(),
+        OutputFormat::Env => return Err(SsapError::UnsupportedFormat),
+    };
+    Ok(out)
+}
+
+/// Quote a string as a JSON string literal. Double quoted JSON strings are
+/// valid YAML scalars as well.
+pub fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quote a string for POSIX shells, so that env output can be `eval`ed
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Format a unix time as `YYYY-MM-DD HH:MM:SS`, in UTC
+pub fn format_time(time: u64) -> String {
+    // Civil date of the days since 1970-01-01, in the proleptic Gregorian
+    // calendar
+    let days = (time / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let m = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * m + 2) / 5 + 1;
+    let month = if m < 10 { m + 3 } else { m - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let seconds = time % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Turn a field name into an environment variable name
+fn env_key(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        let mut entry = Entry::new("it's \"secret\"".to_owned());
+        entry.set("otpauth", "otpauth://totp/x?secret=AA".to_owned());
+        entry
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let entry = entry();
+        assert_eq!(
+            format_entry("github", &entry, &OutputFormat::Raw),
+            "it's \"secret\""
+        );
+        assert_eq!(
+            format_entry("github", &entry, &OutputFormat::Json),
+            "{\"name\": \"github\", \"password\": \"it's \\\"secret\\\"\", \
+             \"fields\": {\"otpauth\": \"otpauth://totp/x?secret=AA\"}}\n"
+        );
+        assert_eq!(
+            format_entry("github", &entry, &OutputFormat::Env),
+            "NAME='github'\nPASSWORD='it'\\''s \"secret\"'\n\
+             OTPAUTH='otpauth://totp/x?secret=AA'\n"
+        );
+    }
+
+    #[test]
+    fn test_format_code() {
+        assert_eq!(format_code("a", "123456", &OutputFormat::Raw), "123456");
+        assert_eq!(
+            format_code("a", "123456", &OutputFormat::Json),
+            "{\"name\": \"a\", \"code\": \"123456\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_names() {
+        let names = vec!["a".to_owned(), "b\"c".to_owned()];
+        assert_eq!(
+            format_names(&names, &OutputFormat::Json).unwrap(),
+            "[\"a\", \"b\\\"c\"]\n"
+        );
+        assert_eq!(
+            format_names(&names, &OutputFormat::Yaml).unwrap(),
+            "- \"a\"\n- \"b\\\"c\"\n"
+        );
+        assert!(format_names(&names, &OutputFormat::Env).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::error::SsapError;
+use crate::ssap::name::EntryName;
+use crate::ssap::vault::{Record, Vault};
+
+/// What happened to the records of an import
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+    /// Names of the imported passwords
+    pub imported: Vec<String>,
+    /// Names already registered in the vault, left untouched
+    pub conflicts: Vec<String>,
+}
+
+/// Turn a folder name or a title into a valid part of an ssap name
+pub fn name_part(part: &str) -> String {
+    let part: String = part
+        .trim()
+        .chars()
+        .map(|c| if c == '/' || c.is_control() { '-' } else { c })
+        .collect();
+    match part.as_str() {
+        "" | "." | ".." => "untitled".to_owned(),
+        _ => part,
+    }
+}
+
+/// Turn the name of a field into a valid ssap field key
+pub fn field_key(key: &str) -> String {
+    key.replace(": ", ":").replace(['\n', '\r'], " ")
+}
+
+/// Give every record a distinct name, appending `-2`, `-3`, ... to the
+/// names repeated in the imported file
+pub fn unique_names(records: &mut [Record]) {
+    let mut seen: Vec<String> = Vec::new();
+    for record in records.iter_mut() {
+        let base = EntryName::new(&record.name)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|_| record.name.clone());
+        let mut name = base.clone();
+        let mut n = 2;
+        while seen.contains(&name) {
+            name = format!("{}-{}", base, n);
+            n += 1;
+        }
+        seen.push(name.clone());
+        record.name = name;
+    }
+}
+
+/// Insert imported records in an unlocked vault
+///
+/// # Arguments
+/// * `vault` - The vault, unlocked
+/// * `records` - The records to import, with distinct names
+/// * `overwrite` - Replace the passwords already registered
+/// # Returns
+/// * The imported names and the conflicting ones, that were skipped
+/// * A SsapError if a name is not valid or the vault is locked
+///
+pub fn import_records(
+    vault: &mut Vault,
+    records: Vec<Record>,
+    overwrite: bool,
+) -> Result<ImportReport, SsapError> {
+    let mut report = ImportReport::default();
+    for record in records {
+        let name = EntryName::new(&record.name)?.to_string();
+        if vault.contains(&name) && !overwrite {
+            report.conflicts.push(name);
+            continue;
+        }
+        vault.insert(&name, record.entry)?;
+        report.imported.push(name);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssap::entry::Entry;
+    use crate::ssap::ssap::Encryption;
+    use crate::ssap::storage::MemoryStorage;
+
+    fn record(name: &str, password: &str) -> Record {
+        Record {
+            name: name.to_owned(),
+            entry: Entry::new(password.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let mut records =
+            vec![record("a", "1"), record("/a/", "2"), record("a", "3")];
+        unique_names(&mut records);
+        let names: Vec<&str> =
+            records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["a", "a-2", "a-3"]);
+    }
+
+    #[test]
+    fn test_import_conflicts() {
+        let storage = Box::new(MemoryStorage::new());
+        let mut vault =
+            Vault::create_with(storage, Encryption::Aes_256_cbc, 1).unwrap();
+        vault.unlock("pw").unwrap();
+        vault.insert("mail", Entry::new("old".into())).unwrap();
+
+        let records = vec![record("mail", "new"), record("bank", "b")];
+        let report = import_records(&mut vault, records, false).unwrap();
+        assert_eq!(report.imported, ["bank"]);
+        assert_eq!(report.conflicts, ["mail"]);
+        assert_eq!(vault.get("mail").unwrap().entry.password, "old");
+
+        let records = vec![record("mail", "new")];
+        let report = import_records(&mut vault, records, true).unwrap();
+        assert_eq!(report.imported, ["mail"]);
+        assert_eq!(vault.get("mail").unwrap().entry.password, "new");
+    }
+}
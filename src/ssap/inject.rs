@@ -0,0 +1,193 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::error::SsapError;
+
+/// Scheme of the secret references found in templates
+pub const REFERENCE_SCHEME: &str = "ssap://";
+
+/// Characters ending a bare `ssap://` reference
+const REFERENCE_END: &[char] =
+    &['"', '\'', '`', '<', '>', '(', ')', '[', ']', '{', '}', ','];
+
+/// Render a template, replacing every secret reference with the value
+/// returned by `resolve`.
+///
+/// References are either template tags like `{{ ssap "prod/db" }}` or bare
+/// URLs like `ssap://prod/db/password`, which end at the first whitespace,
+/// quote or bracket. The reference passed to `resolve` never contains the
+/// `ssap://` scheme. Other `{{ ... }}` tags are left untouched.
+///
+/// # Arguments
+/// * `template` - The template text
+/// * `resolve` - Maps a reference to its secret
+/// # Returns
+/// * The rendered text
+/// * The first error returned by `resolve`
+///
+pub fn render<F>(template: &str, mut resolve: F) -> Result<String, SsapError>
+where
+    F: FnMut(&str) -> Result<String, SsapError>,
+{
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while !rest.is_empty() {
+        let tag = rest.find("{{");
+        let bare = rest.find(REFERENCE_SCHEME);
+        match (tag, bare) {
+            (Some(t), b) if b.is_none_or(|b| t < b) => {
+                out.push_str(&rest[..t]);
+                rest = &rest[t..];
+                let end = match rest.find("}}") {
+                    Some(end) => end + 2,
+                    None => return Err(SsapError::InvalidTemplate),
+                };
+                match tag_reference(&rest[2..end - 2]) {
+                    Some(reference) => out.push_str(&resolve(reference)?),
+                    None => out.push_str(&rest[..end]),
+                }
+                rest = &rest[end..];
+            }
+            (_, Some(b)) => {
+                out.push_str(&rest[..b]);
+                rest = &rest[b + REFERENCE_SCHEME.len()..];
+                let end = rest
+                    .find(|c: char| {
+                        c.is_whitespace() || REFERENCE_END.contains(&c)
+                    })
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(SsapError::InvalidTemplate);
+                }
+                out.push_str(&resolve(&rest[..end])?);
+                rest = &rest[end..];
+            }
+            _ => {
+                out.push_str(rest);
+                rest = "";
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Collect the references of a template, in order of appearance
+pub fn references(template: &str) -> Result<Vec<String>, SsapError> {
+    let mut refs = Vec::new();
+    render(template, |reference| {
+        refs.push(reference.to_owned());
+        Ok(String::new())
+    })?;
+    Ok(refs)
+}
+
+/// Extract the reference of a `{{ ssap "reference" }}` tag
+fn tag_reference(tag: &str) -> Option<&str> {
+    let arg = tag.trim().strip_prefix("ssap")?;
+    if !arg.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let arg = arg.trim();
+    let quote = arg.chars().next()?;
+    if quote != '"' && quote != '\'' {
+        return None;
+    }
+    let reference = arg.strip_prefix(quote)?.strip_suffix(quote)?;
+    if reference.is_empty() {
+        return None;
+    }
+    Some(
+        reference
+            .strip_prefix(REFERENCE_SCHEME)
+            .unwrap_or(reference),
+    )
+}
+
+/// Split a reference into the entry name and the requested field, given
+/// the names registered in the vault. A reference naming an entry resolves
+/// to its password, otherwise its last path segment is the field.
+///
+/// # Arguments
+/// * `reference` - The reference, without the `ssap://` scheme
+/// * `names` - The names registered in the vault
+/// # Returns
+/// * The entry name and the field
+/// * An error if no entry matches the reference
+///
+pub fn split_reference<'a>(
+    reference: &'a str,
+    names: &[String],
+) -> Result<(&'a str, &'a str), SsapError> {
+    if names.iter().any(|n| n == reference) {
+        return Ok((reference, "password"));
+    }
+    if let Some((name, field)) = reference.rsplit_once('/') {
+        if names.iter().any(|n| n == name) {
+            return Ok((name, field));
+        }
+    }
+    Err(SsapError::PasswordNameNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = "user = \"{{ ssap \"prod/db/username\" }}\"\n\
+                        password = {{ssap 'prod/db'}}\n\
+                        url = \"ssap://stripe/url\"\n\
+                        other = {{ env \"HOME\" }}\n";
+        let rendered = render(template, |r| Ok(format!("<{}>", r))).unwrap();
+        assert_eq!(
+            rendered,
+            "user = \"<prod/db/username>\"\n\
+             password = <prod/db>\n\
+             url = \"<stripe/url>\"\n\
+             other = {{ env \"HOME\" }}\n"
+        );
+        assert_eq!(
+            references(template).unwrap(),
+            vec!["prod/db/username", "prod/db", "stripe/url"]
+        );
+        assert!(render("{{ ssap \"x\"", |_| Ok(String::new())).is_err());
+    }
+
+    #[test]
+    fn test_split_reference() {
+        let names = vec!["prod/db".to_owned(), "stripe".to_owned()];
+        assert_eq!(
+            split_reference("prod/db", &names).unwrap(),
+            ("prod/db", "password")
+        );
+        assert_eq!(
+            split_reference("prod/db/username", &names).unwrap(),
+            ("prod/db", "username")
+        );
+        assert!(split_reference("prod", &names).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::error::SsapError;
+use crate::ssap::format::json_string;
+
+/// A JSON value, as read from the files of other password managers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members of an object, in the order they appear in the file
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parse a JSON document
+    ///
+    /// # Arguments
+    /// * `text` - The document
+    /// # Returns
+    /// * The parsed value
+    /// * `SsapError::InvalidJson` if the document is malformed
+    ///
+    pub fn parse(text: &str) -> Result<Json, SsapError> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != parser.chars.len() {
+            return Err(SsapError::InvalidJson);
+        }
+        Ok(value)
+    }
+
+    /// Get a member of an object, `None` if missing or null
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v)
+                .filter(|v| **v != Json::Null),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    /// The items of an array, none for any other value
+    pub fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// Serialize the value, without whitespace
+    pub fn to_json(&self) -> String {
+        match self {
+            Json::Null => "null".to_owned(),
+            Json::Bool(b) => b.to_string(),
+            Json::Number(n) => n.to_string(),
+            Json::String(s) => json_string(s),
+            Json::Array(items) => {
+                let items: Vec<String> =
+                    items.iter().map(|item| item.to_json()).collect();
+                format!("[{}]", items.join(","))
+            }
+            Json::Object(members) => {
+                let members: Vec<String> = members
+                    .iter()
+                    .map(|(k, v)| format!("{}:{}", json_string(k), v.to_json()))
+                    .collect();
+                format!("{{{}}}", members.join(","))
+            }
+        }
+    }
+}
+
+/// Maximum nesting of arrays and objects
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char, SsapError> {
+        let c = self.peek().ok_or(SsapError::InvalidJson)?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SsapError> {
+        match self.next()? == expected {
+            true => Ok(()),
+            false => Err(SsapError::InvalidJson),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, SsapError> {
+        if depth > MAX_DEPTH {
+            return Err(SsapError::InvalidJson);
+        }
+        self.skip_whitespace();
+        match self.peek().ok_or(SsapError::InvalidJson)? {
+            '{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    self.expect('"')?;
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(':')?;
+                    members.push((key, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        '}' => return Ok(Json::Object(members)),
+                        _ => return Err(SsapError::InvalidJson),
+                    }
+                }
+            }
+            '[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        ']' => return Ok(Json::Array(items)),
+                        _ => return Err(SsapError::InvalidJson),
+                    }
+                }
+            }
+            '"' => {
+                self.pos += 1;
+                Ok(Json::String(self.string()?))
+            }
+            't' => self.literal("true", Json::Bool(true)),
+            'f' => self.literal("false", Json::Bool(false)),
+            'n' => self.literal("null", Json::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, SsapError> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Json, SsapError> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some('0'..='9' | '-' | '+' | '.' | 'e' | 'E')
+        ) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse()
+            .map(Json::Number)
+            .map_err(|_| SsapError::InvalidJson)
+    }
+
+    /// Parse a string, after its opening quote
+    fn string(&mut self) -> Result<String, SsapError> {
+        let mut out = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(out),
+                '\\' => match self.next()? {
+                    '"' => out.push('"'),
+                    '\\' => out.push('\\'),
+                    '/' => out.push('/'),
+                    'b' => out.push('\u{8}'),
+                    'f' => out.push('\u{c}'),
+                    'n' => out.push('\n'),
+                    'r' => out.push('\r'),
+                    't' => out.push('\t'),
+                    'u' => {
+                        let high = self.hex4()?;
+                        let c = if (0xd800..0xdc00).contains(&high) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex4()?;
+                            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                        } else {
+                            high
+                        };
+                        out.push(
+                            char::from_u32(c).ok_or(SsapError::InvalidJson)?,
+                        );
+                    }
+                    _ => return Err(SsapError::InvalidJson),
+                },
+                c if (c as u32) < 0x20 => return Err(SsapError::InvalidJson),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, SsapError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = self.next()?.to_digit(16);
+            value = value * 16 + digit.ok_or(SsapError::InvalidJson)?;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json = Json::parse(
+            r#" {"name": "a\"b\u00e9\ud83d\ude00", "n": [1, -2.5e1, true],
+                "none": null, "o": {}} "#,
+        )
+        .unwrap();
+        assert_eq!(json.get("name").unwrap().as_str(), Some("a\"bé😀"));
+        let numbers = json.get("n").unwrap().items();
+        assert_eq!(numbers[0].as_u64(), Some(1));
+        assert_eq!(numbers[1], Json::Number(-25.0));
+        assert_eq!(numbers[2].as_bool(), Some(true));
+        assert_eq!(json.get("none"), None);
+        assert_eq!(json.get("o"), Some(&Json::Object(vec![])));
+        assert_eq!(Json::parse(&json.to_json()).unwrap(), json);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in ["", "{", "[1,]", "{\"a\" 1}", "\"\\x\"", "1 2", "nul"] {
+            assert!(Json::parse(text).is_err(), "{}", text);
+        }
+        assert!(Json::parse(&"[".repeat(100)).is_err());
+    }
+}
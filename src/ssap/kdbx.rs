@@ -0,0 +1,1099 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! Reader and writer of KeePass KDBX 4 databases.
+//!
+//! A KDBX 4 file is made of an unencrypted header, holding the cipher and
+//! the key derivation parameters, followed by the encrypted XML document
+//! split into HMAC authenticated blocks. Inside the XML, protected values
+//! such as passwords are encrypted once more with an inner stream cipher.
+
+use crate::ssap::crypto::hmac_sha256;
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::folder::basename;
+use crate::ssap::import::{field_key, name_part};
+use crate::ssap::otp::{unix_time, Otp};
+use crate::ssap::vault::Record;
+use argon2::{Algorithm, Argon2, Params, Version};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use openssl::base64;
+use openssl::hash::{hash, MessageDigest};
+use openssl::symm::{decrypt, encrypt, Cipher, Crypter, Mode};
+use rand::RngCore;
+use roxmltree::{Document, Node, NodeId};
+use salsa20::cipher::{KeyIvInit, StreamCipher};
+use salsa20::Salsa20;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// First eight bytes of every KDBX file
+const SIGNATURE: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5];
+
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21,
+    0x6a, 0xfc, 0x5a, 0xff,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a,
+    0x31, 0xdb, 0xb5, 0x9a,
+];
+const KDF_AES: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08,
+    0xc1, 0x8a, 0x4f, 0xea,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4,
+    0x03, 0xe3, 0x0a, 0x0c,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e,
+    0xc6, 0xf0, 0xa1, 0xe6,
+];
+
+/// Inner stream ciphers protecting values inside the XML document
+const STREAM_SALSA20: u32 = 2;
+const STREAM_CHACHA20: u32 = 3;
+const SALSA20_NONCE: [u8; 8] = [0xe8, 0x30, 0x09, 0x4b, 0x97, 0x20, 0x5d, 0x2a];
+
+/// Types of the values of a variant dictionary
+const VARIANT_U32: u8 = 0x04;
+const VARIANT_U64: u8 = 0x05;
+const VARIANT_BYTES: u8 = 0x42;
+
+/// Limits on the key derivation parameters read from a database, so that
+/// a crafted file cannot make the import allocate or run without bound
+const MAX_AES_ROUNDS: u64 = 300_000_000;
+const MAX_ARGON2_MEMORY: u64 = 2 * 1024 * 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u64 = 1_000;
+const MAX_ARGON2_PARALLELISM: u32 = 256;
+
+/// Size of the HMAC authenticated blocks of written databases
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Fields of ssap entries with a KeePass equivalent
+const MAPPED_FIELDS: &[(&str, &str)] =
+    &[("UserName", "username"), ("URL", "url"), ("Notes", "notes")];
+
+/// Seconds between 0001-01-01, the KDBX epoch, and 1970-01-01
+const UNIX_EPOCH_OFFSET: i64 = 62_135_596_800;
+
+/// An entry of a KDBX database
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KdbxEntry {
+    /// Names of the groups holding the entry, without the root group
+    pub group: Vec<String>,
+    /// String fields, such as `Title`, `UserName` and `Password`
+    pub fields: Vec<(String, String)>,
+    pub tags: Vec<String>,
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+    /// Previous versions of the entry, oldest first
+    pub history: Vec<KdbxEntry>,
+}
+
+impl KdbxEntry {
+    /// Get the value of a string field, if present
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The ssap name of the entry: its groups followed by its title
+    pub fn name(&self) -> String {
+        let title = self.get("Title").unwrap_or("");
+        self.group
+            .iter()
+            .map(|group| name_part(group))
+            .chain(std::iter::once(name_part(title)))
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+
+    /// Convert the entry into an ssap entry. Standard fields are mapped to
+    /// the ssap ones, TOTP settings to an `otpauth` URI and the other
+    /// string fields are kept as they are.
+    pub fn to_entry(&self) -> Entry {
+        let mut entry = Entry::new(self.get("Password").unwrap_or("").into());
+        for (key, field) in MAPPED_FIELDS {
+            if let Some(value) = self.get(key).filter(|v| !v.is_empty()) {
+                entry.set(field, value.to_owned());
+            }
+        }
+        let otp = self.otpauth();
+        if let Some(uri) = &otp {
+            entry.set("otpauth", uri.clone());
+        }
+        for (key, value) in &self.fields {
+            let standard = matches!(
+                key.as_str(),
+                "Title" | "UserName" | "Password" | "URL" | "Notes"
+            );
+            let totp = otp.is_some()
+                && matches!(
+                    key.as_str(),
+                    "otp" | "TOTP Seed" | "TOTP Settings"
+                );
+            let key = field_key(key);
+            if standard || totp || value.is_empty() || entry.get(&key).is_some()
+            {
+                continue;
+            }
+            entry.set(&key, value.clone());
+        }
+        entry.set_tags(&self.tags);
+        if let Some(created) = self.created {
+            entry.set("created", created.to_string());
+        }
+        if let Some(modified) = self.modified {
+            entry.set("modified", modified.to_string());
+        }
+        entry
+    }
+
+    /// Convert an ssap entry into a KDBX entry, the inverse of `to_entry`
+    ///
+    /// # Arguments
+    /// * `name` - The name of the password, its folders become groups
+    /// * `entry` - The password and its fields
+    /// * `history` - The previous versions of the password, oldest first,
+    ///   with the unix time they were replaced at
+    /// # Returns
+    /// * The KDBX entry
+    ///
+    pub fn from_entry(
+        name: &str,
+        entry: &Entry,
+        history: &[(u64, Entry)],
+    ) -> KdbxEntry {
+        let mut kdbx = KdbxEntry::from_version(name, entry);
+        kdbx.history = history
+            .iter()
+            .map(|(replaced, old)| {
+                let mut version = KdbxEntry::from_version(name, old);
+                version.modified = version.modified.or(Some(*replaced));
+                version
+            })
+            .collect();
+        kdbx
+    }
+
+    fn from_version(name: &str, entry: &Entry) -> KdbxEntry {
+        let title = basename(name);
+        let folder = name[..name.len() - title.len()].trim_end_matches('/');
+        let mut kdbx = KdbxEntry {
+            group: folder
+                .split('/')
+                .filter(|g| !g.is_empty())
+                .map(|g| g.to_owned())
+                .collect(),
+            tags: entry.tags(),
+            created: entry.get("created").and_then(|t| t.parse().ok()),
+            modified: entry.get("modified").and_then(|t| t.parse().ok()),
+            ..Default::default()
+        };
+        kdbx.fields.push(("Title".into(), title.to_owned()));
+        kdbx.fields
+            .push(("Password".into(), entry.password.clone()));
+        for (key, field) in MAPPED_FIELDS {
+            let value = entry.get(field).unwrap_or("");
+            kdbx.fields.push((key.to_string(), value.to_owned()));
+        }
+        if let Some(uri) = entry.get("otpauth") {
+            kdbx.fields.push(("otp".into(), uri.to_owned()));
+        }
+        for (key, value) in &entry.fields {
+            let mapped = MAPPED_FIELDS.iter().any(|(_, field)| field == key);
+            let known = matches!(
+                key.as_str(),
+                "otpauth" | "tags" | "created" | "modified"
+            );
+            if !mapped && !known && kdbx.get(key).is_none() {
+                kdbx.fields.push((key.clone(), value.clone()));
+            }
+        }
+        kdbx
+    }
+
+    /// Convert the entry into an ssap record, see `name` and `to_entry`
+    pub fn to_record(&self) -> Record {
+        Record {
+            name: self.name(),
+            entry: self.to_entry(),
+        }
+    }
+
+    /// The TOTP of the entry as an `otpauth` URI. KeePassXC stores it in
+    /// the `otp` field, older plugins in `TOTP Seed` and `TOTP Settings`.
+    fn otpauth(&self) -> Option<String> {
+        if let Some(uri) = self.get("otp").filter(|v| v.starts_with("otpauth"))
+        {
+            return Some(uri.to_owned());
+        }
+        let mut otp = Otp::from_seed(self.get("TOTP Seed")?).ok()?;
+        if let Some(settings) = self.get("TOTP Settings") {
+            let mut parts = settings.split(';');
+            otp.period = parts.next()?.parse().ok()?;
+            otp.digits = parts.next()?.parse().ok()?;
+        }
+        otp.label = self.get("Title").unwrap_or("").to_owned();
+        otp.validate().ok()?;
+        Some(otp.to_uri())
+    }
+}
+
+/// Read all the entries of a KDBX 4 database
+///
+/// # Arguments
+/// * `data` - The content of the database file
+/// * `password` - The master password of the database
+/// # Returns
+/// * The entries, without the ones in the recycle bin
+/// * `SsapError::InvalidKdbxPassword` if the password is wrong
+/// * `SsapError::UnsupportedKdbx` for older versions or unknown ciphers
+/// * `SsapError::KdbxKdfTooExpensive` if deriving the key would take too
+///   much memory or time
+/// * `SsapError::InvalidKdbx` if the file is corrupted
+///
+pub fn read(data: &[u8], password: &str) -> Result<Vec<KdbxEntry>, SsapError> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.take(8)? != SIGNATURE {
+        return Err(SsapError::InvalidKdbx);
+    }
+    let _minor = reader.u16()?;
+    if reader.u16()? != 4 {
+        return Err(SsapError::UnsupportedKdbx);
+    }
+    let header = OuterHeader::read(&mut reader)?;
+    let header_len = reader.pos;
+    let header_hash = reader.take(32)?;
+    let header_hmac = reader.take(32)?;
+    if sha256(&data[..header_len])? != header_hash {
+        return Err(SsapError::InvalidKdbx);
+    }
+
+    let keys = Keys::derive(password, &header.master_seed, &header.kdf)?;
+    let hmac = hmac_sha256(&keys.block_key(u64::MAX)?, &[&data[..header_len]])?;
+    if hmac != header_hmac {
+        return Err(SsapError::InvalidKdbxPassword);
+    }
+
+    let mut encrypted = Vec::new();
+    for index in 0.. {
+        let block_hmac = reader.take(32)?.to_vec();
+        let size = reader.u32()?;
+        let block = reader.take(size as usize)?;
+        let expected = hmac_sha256(
+            &keys.block_key(index)?,
+            &[&index.to_le_bytes(), &size.to_le_bytes(), block],
+        )?;
+        if expected != block_hmac {
+            return Err(SsapError::InvalidKdbx);
+        }
+        if size == 0 {
+            break;
+        }
+        encrypted.extend_from_slice(block);
+    }
+
+    let cipher = match header.cipher {
+        CIPHER_AES256 => Cipher::aes_256_cbc(),
+        CIPHER_CHACHA20 => Cipher::chacha20(),
+        _ => return Err(SsapError::UnsupportedKdbx),
+    };
+    let iv = match header.cipher {
+        CIPHER_CHACHA20 => [[0u8; 4].as_slice(), &header.iv].concat(),
+        _ => header.iv.clone(),
+    };
+    let mut payload = decrypt(cipher, &keys.cipher, Some(&iv), &encrypted)
+        .map_err(invalid)?;
+    if header.compressed {
+        let mut inflated = Vec::new();
+        GzDecoder::new(payload.as_slice())
+            .read_to_end(&mut inflated)
+            .map_err(invalid)?;
+        payload = inflated;
+    }
+
+    let mut inner = Reader {
+        data: &payload,
+        pos: 0,
+    };
+    let mut stream_id = 0;
+    let mut stream_key = Vec::new();
+    loop {
+        let id = inner.u8()?;
+        let len = inner.u32()? as usize;
+        let value = inner.take(len)?;
+        match id {
+            0 => break,
+            1 => stream_id = u32_le(value)?,
+            2 => stream_key = value.to_vec(),
+            _ => {}
+        }
+    }
+    let mut stream = InnerStream::new(stream_id, &stream_key)?;
+    let xml = std::str::from_utf8(&payload[inner.pos..]).map_err(invalid)?;
+    parse_xml(xml, &mut stream)
+}
+
+/// Argon2id parameters of the databases written by ssap
+#[derive(Debug, Clone, PartialEq)]
+pub struct KdbxKdf {
+    /// Memory, in bytes
+    pub memory: u64,
+    pub iterations: u64,
+    pub parallelism: u32,
+}
+
+impl Default for KdbxKdf {
+    /// The defaults of KeePassXC: 64 MiB, 10 iterations and 2 lanes
+    fn default() -> Self {
+        KdbxKdf {
+            memory: 64 * 1024 * 1024,
+            iterations: 10,
+            parallelism: 2,
+        }
+    }
+}
+
+/// Write entries to a new KDBX 4 database, encrypted with ChaCha20 and a
+/// key derived with Argon2id
+///
+/// # Arguments
+/// * `entries` - The entries, their groups are created as needed
+/// * `password` - The master password of the database
+/// * `kdf` - The parameters of the key derivation
+/// # Returns
+/// * The content of the database file
+/// * A SsapError if the encryption fails
+///
+pub fn write(
+    entries: &[KdbxEntry],
+    password: &str,
+    kdf: &KdbxKdf,
+) -> Result<Vec<u8>, SsapError> {
+    let master_seed = random_bytes(32)?;
+    let iv = random_bytes(12)?;
+    let kdf_params = write_variant_dictionary(&[
+        ("$UUID", VARIANT_BYTES, KDF_ARGON2ID.to_vec()),
+        ("S", VARIANT_BYTES, random_bytes(32)?),
+        ("P", VARIANT_U32, kdf.parallelism.to_le_bytes().to_vec()),
+        ("M", VARIANT_U64, kdf.memory.to_le_bytes().to_vec()),
+        ("I", VARIANT_U64, kdf.iterations.to_le_bytes().to_vec()),
+        ("V", VARIANT_U32, 0x13u32.to_le_bytes().to_vec()),
+    ]);
+    let mut out = SIGNATURE.to_vec();
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&4u16.to_le_bytes());
+    push_field(&mut out, 2, &CIPHER_CHACHA20);
+    push_field(&mut out, 3, &1u32.to_le_bytes());
+    push_field(&mut out, 4, &master_seed);
+    push_field(&mut out, 7, &iv);
+    push_field(&mut out, 11, &kdf_params);
+    push_field(&mut out, 0, b"\r\n\r\n");
+    let keys = Keys::derive(
+        password,
+        &master_seed,
+        &read_variant_dictionary(&kdf_params)?,
+    )?;
+    let header_hmac = hmac_sha256(&keys.block_key(u64::MAX)?, &[&out])?;
+    out.extend(sha256(&out)?);
+    out.extend(header_hmac);
+
+    let stream_key = random_bytes(64)?;
+    let mut payload = Vec::new();
+    push_field(&mut payload, 1, &STREAM_CHACHA20.to_le_bytes());
+    push_field(&mut payload, 2, &stream_key);
+    push_field(&mut payload, 0, &[]);
+    let mut stream = InnerStream::new(STREAM_CHACHA20, &stream_key)?;
+    payload.extend(write_xml(entries, &mut stream)?.into_bytes());
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&payload).map_err(invalid)?;
+    let compressed = encoder.finish().map_err(invalid)?;
+    let iv = [&[0u8; 4][..], &iv].concat();
+    let encrypted =
+        encrypt(Cipher::chacha20(), &keys.cipher, Some(&iv), &compressed)
+            .map_err(invalid)?;
+
+    let blocks = encrypted.chunks(BLOCK_SIZE).chain(std::iter::once(&[][..]));
+    for (index, block) in (0u64..).zip(blocks) {
+        let size = (block.len() as u32).to_le_bytes();
+        out.extend(hmac_sha256(
+            &keys.block_key(index)?,
+            &[&index.to_le_bytes(), &size, block],
+        )?);
+        out.extend_from_slice(&size);
+        out.extend_from_slice(block);
+    }
+    Ok(out)
+}
+
+fn push_field(out: &mut Vec<u8>, id: u8, value: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn write_variant_dictionary(items: &[(&str, u8, Vec<u8>)]) -> Vec<u8> {
+    let mut out = 0x0100u16.to_le_bytes().to_vec();
+    for (key, kind, value) in items {
+        out.push(*kind);
+        out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        out.extend_from_slice(key.as_bytes());
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        out.extend_from_slice(value);
+    }
+    out.push(0);
+    out
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, SsapError> {
+    let mut bytes = vec![0u8; len];
+    if rand::thread_rng().try_fill_bytes(&mut bytes).is_err() {
+        return Err(SsapError::ErrorGeneratingIV);
+    }
+    Ok(bytes)
+}
+
+/// Outer header of a KDBX 4 file
+struct OuterHeader {
+    cipher: [u8; 16],
+    compressed: bool,
+    master_seed: Vec<u8>,
+    iv: Vec<u8>,
+    kdf: HashMap<String, Vec<u8>>,
+}
+
+impl OuterHeader {
+    fn read(reader: &mut Reader) -> Result<OuterHeader, SsapError> {
+        let mut header = OuterHeader {
+            cipher: [0; 16],
+            compressed: false,
+            master_seed: Vec::new(),
+            iv: Vec::new(),
+            kdf: HashMap::new(),
+        };
+        loop {
+            let id = reader.u8()?;
+            let len = reader.u32()? as usize;
+            let value = reader.take(len)?;
+            match id {
+                0 => break,
+                2 => header.cipher = value.try_into().map_err(invalid)?,
+                3 => header.compressed = u32_le(value)? == 1,
+                4 => header.master_seed = value.to_vec(),
+                7 => header.iv = value.to_vec(),
+                11 => header.kdf = read_variant_dictionary(value)?,
+                _ => {}
+            }
+        }
+        if header.master_seed.len() != 32 || header.iv.is_empty() {
+            return Err(SsapError::InvalidKdbx);
+        }
+        Ok(header)
+    }
+}
+
+/// Read a KDBX variant dictionary, keeping the raw bytes of the values
+fn read_variant_dictionary(
+    data: &[u8],
+) -> Result<HashMap<String, Vec<u8>>, SsapError> {
+    let mut reader = Reader { data, pos: 0 };
+    let _version = reader.u16()?;
+    let mut dict = HashMap::new();
+    loop {
+        let kind = reader.u8()?;
+        if kind == 0 {
+            break;
+        }
+        let key_len = reader.u32()? as usize;
+        let key = String::from_utf8_lossy(reader.take(key_len)?).into_owned();
+        let value_len = reader.u32()? as usize;
+        dict.insert(key, reader.take(value_len)?.to_vec());
+    }
+    Ok(dict)
+}
+
+/// Keys of a database, derived from the password and the master seed
+struct Keys {
+    cipher: Vec<u8>,
+    hmac: Vec<u8>,
+}
+
+impl Keys {
+    fn derive(
+        password: &str,
+        master_seed: &[u8],
+        kdf: &HashMap<String, Vec<u8>>,
+    ) -> Result<Keys, SsapError> {
+        let composite = sha256(&sha256(password.as_bytes())?)?;
+        let transformed = transform_key(&composite, kdf)?;
+        let seeded = [master_seed, &transformed].concat();
+        Ok(Keys {
+            cipher: sha256(&seeded)?,
+            hmac: sha512(&[&seeded[..], &[1]].concat())?,
+        })
+    }
+
+    /// HMAC key of a block, `u64::MAX` being the header
+    fn block_key(&self, index: u64) -> Result<Vec<u8>, SsapError> {
+        let data = [&index.to_le_bytes()[..], &self.hmac].concat();
+        sha512(&data)
+    }
+}
+
+/// Run the key derivation function of the database on the composite key
+fn transform_key(
+    composite: &[u8],
+    kdf: &HashMap<String, Vec<u8>>,
+) -> Result<Vec<u8>, SsapError> {
+    let param = |key: &str| kdf.get(key).ok_or(SsapError::InvalidKdbx);
+    let uuid: [u8; 16] =
+        param("$UUID")?.as_slice().try_into().map_err(invalid)?;
+    if uuid == KDF_AES {
+        let rounds = u64_le(param("R")?)?;
+        if rounds > MAX_AES_ROUNDS {
+            return Err(SsapError::KdbxKdfTooExpensive);
+        }
+        let mut crypter = Crypter::new(
+            Cipher::aes_256_ecb(),
+            Mode::Encrypt,
+            param("S")?,
+            None,
+        )
+        .map_err(invalid)?;
+        crypter.pad(false);
+        let mut key = composite.to_vec();
+        let mut out = vec![0; key.len() + 16];
+        for _ in 0..rounds {
+            crypter.update(&key, &mut out).map_err(invalid)?;
+            key.copy_from_slice(&out[..32]);
+        }
+        return sha256(&key);
+    }
+    let algorithm = match uuid {
+        KDF_ARGON2D => Algorithm::Argon2d,
+        KDF_ARGON2ID => Algorithm::Argon2id,
+        _ => return Err(SsapError::UnsupportedKdbx),
+    };
+    let version = match u32_le(param("V")?)? {
+        0x10 => Version::V0x10,
+        _ => Version::V0x13,
+    };
+    let memory = u64_le(param("M")?)?;
+    let iterations = u64_le(param("I")?)?;
+    let parallelism = u32_le(param("P")?)?;
+    if memory > MAX_ARGON2_MEMORY
+        || iterations > MAX_ARGON2_ITERATIONS
+        || parallelism > MAX_ARGON2_PARALLELISM
+    {
+        return Err(SsapError::KdbxKdfTooExpensive);
+    }
+    let params = Params::new(
+        (memory / 1024) as u32,
+        iterations as u32,
+        parallelism,
+        Some(32),
+    )
+    .map_err(invalid)?;
+    let mut key = vec![0; 32];
+    Argon2::new(algorithm, version, params)
+        .hash_password_into(composite, param("S")?, &mut key)
+        .map_err(invalid)?;
+    Ok(key)
+}
+
+fn sha256(data: &[u8]) -> Result<Vec<u8>, SsapError> {
+    Ok(hash(MessageDigest::sha256(), data)
+        .map_err(invalid)?
+        .to_vec())
+}
+
+fn sha512(data: &[u8]) -> Result<Vec<u8>, SsapError> {
+    Ok(hash(MessageDigest::sha512(), data)
+        .map_err(invalid)?
+        .to_vec())
+}
+
+fn invalid<E>(_: E) -> SsapError {
+    SsapError::InvalidKdbx
+}
+
+/// Cipher of the values marked as protected in the XML document. The
+/// values share a single key stream, in document order.
+enum InnerStream {
+    Salsa20(Box<Salsa20>),
+    ChaCha20(Box<Crypter>),
+}
+
+impl InnerStream {
+    fn new(id: u32, key: &[u8]) -> Result<InnerStream, SsapError> {
+        match id {
+            STREAM_SALSA20 => {
+                let key = sha256(key)?;
+                Ok(InnerStream::Salsa20(Box::new(Salsa20::new(
+                    key.as_slice().into(),
+                    &SALSA20_NONCE.into(),
+                ))))
+            }
+            STREAM_CHACHA20 => {
+                let key = sha512(key)?;
+                let iv = [&[0u8; 4][..], &key[32..44]].concat();
+                let crypter = Crypter::new(
+                    Cipher::chacha20(),
+                    Mode::Encrypt,
+                    &key[..32],
+                    Some(&iv),
+                )
+                .map_err(invalid)?;
+                Ok(InnerStream::ChaCha20(Box::new(crypter)))
+            }
+            _ => Err(SsapError::UnsupportedKdbx),
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) -> Result<(), SsapError> {
+        match self {
+            InnerStream::Salsa20(cipher) => cipher.apply_keystream(data),
+            InnerStream::ChaCha20(crypter) => {
+                let mut out = vec![0; data.len()];
+                crypter.update(data, &mut out).map_err(invalid)?;
+                data.copy_from_slice(&out);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse the XML document, decrypting the protected values
+fn parse_xml(
+    xml: &str,
+    stream: &mut InnerStream,
+) -> Result<Vec<KdbxEntry>, SsapError> {
+    let doc = Document::parse(xml).map_err(invalid)?;
+    let mut values = HashMap::new();
+    for node in doc.descendants().filter(|n| {
+        n.has_tag_name("Value") && n.attribute("Protected") == Some("True")
+    }) {
+        let mut value = match node.text() {
+            Some(text) if !text.is_empty() => {
+                base64::decode_block(text).map_err(invalid)?
+            }
+            _ => Vec::new(),
+        };
+        stream.apply(&mut value)?;
+        values.insert(node.id(), String::from_utf8_lossy(&value).into_owned());
+    }
+
+    let root = doc.root_element();
+    let recycle_bin = child(root, "Meta")
+        .and_then(|meta| child(meta, "RecycleBinUUID"))
+        .and_then(|node| node.text());
+    let group = child(root, "Root")
+        .and_then(|node| child(node, "Group"))
+        .ok_or(SsapError::InvalidKdbx)?;
+    let mut entries = Vec::new();
+    read_group(group, &[], recycle_bin, &values, &mut entries);
+    Ok(entries)
+}
+
+/// Write the XML document, encrypting the protected values
+fn write_xml(
+    entries: &[KdbxEntry],
+    stream: &mut InnerStream,
+) -> Result<String, SsapError> {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n\
+         <KeePassFile>\n<Meta>\n<Generator>ssap</Generator>\n\
+         <RecycleBinEnabled>False</RecycleBinEnabled>\n</Meta>\n<Root>\n",
+    );
+    write_group(&mut xml, "Root", &[], entries, stream)?;
+    xml.push_str("</Root>\n</KeePassFile>\n");
+    Ok(xml)
+}
+
+fn write_group(
+    xml: &mut String,
+    name: &str,
+    path: &[String],
+    entries: &[KdbxEntry],
+    stream: &mut InnerStream,
+) -> Result<(), SsapError> {
+    let uuid = base64::encode_block(&random_bytes(16)?);
+    xml.push_str(&format!(
+        "<Group>\n<UUID>{}</UUID>\n<Name>{}</Name>\n",
+        uuid,
+        escape(name)
+    ));
+    let mut groups: Vec<&String> = Vec::new();
+    for entry in entries {
+        if entry.group == path {
+            let uuid = base64::encode_block(&random_bytes(16)?);
+            write_entry(xml, entry, &uuid, stream)?;
+        } else if entry.group.starts_with(path) {
+            let group = &entry.group[path.len()];
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+    }
+    for group in groups {
+        let mut path = path.to_vec();
+        path.push(group.clone());
+        write_group(xml, group, &path, entries, stream)?;
+    }
+    xml.push_str("</Group>\n");
+    Ok(())
+}
+
+/// Write an entry. Its previous versions share its UUID.
+fn write_entry(
+    xml: &mut String,
+    entry: &KdbxEntry,
+    uuid: &str,
+    stream: &mut InnerStream,
+) -> Result<(), SsapError> {
+    xml.push_str(&format!("<Entry>\n<UUID>{}</UUID>\n", uuid));
+    if !entry.tags.is_empty() {
+        let tags = escape(&entry.tags.join(";"));
+        xml.push_str(&format!("<Tags>{}</Tags>\n", tags));
+    }
+    let modified = entry.modified.or(entry.created).unwrap_or_else(unix_time);
+    let created = format_time(entry.created.unwrap_or(modified));
+    let modified = format_time(modified);
+    xml.push_str(&format!(
+        "<Times>\n<CreationTime>{}</CreationTime>\n\
+         <LastModificationTime>{}</LastModificationTime>\n\
+         <LastAccessTime>{}</LastAccessTime>\n\
+         <ExpiryTime>{}</ExpiryTime>\n<Expires>False</Expires>\n\
+         <UsageCount>0</UsageCount>\n\
+         <LocationChanged>{}</LocationChanged>\n</Times>\n",
+        created, modified, modified, modified, modified
+    ));
+    for (key, value) in &entry.fields {
+        xml.push_str(&format!("<String>\n<Key>{}</Key>\n", escape(key)));
+        if key == "Password" || key == "otp" {
+            let mut value = value.as_bytes().to_vec();
+            stream.apply(&mut value)?;
+            xml.push_str(&format!(
+                "<Value Protected=\"True\">{}</Value>\n",
+                base64::encode_block(&value)
+            ));
+        } else {
+            xml.push_str(&format!("<Value>{}</Value>\n", escape(value)));
+        }
+        xml.push_str("</String>\n");
+    }
+    if !entry.history.is_empty() {
+        xml.push_str("<History>\n");
+        for version in &entry.history {
+            write_entry(xml, version, uuid, stream)?;
+        }
+        xml.push_str("</History>\n");
+    }
+    xml.push_str("</Entry>\n");
+    Ok(())
+}
+
+/// Escape a text for XML, dropping the characters XML cannot hold
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn read_group(
+    group: Node,
+    path: &[String],
+    recycle_bin: Option<&str>,
+    values: &HashMap<NodeId, String>,
+    entries: &mut Vec<KdbxEntry>,
+) {
+    for node in group.children().filter(|n| n.is_element()) {
+        if node.has_tag_name("Entry") {
+            entries.push(read_entry(node, path, values));
+        } else if node.has_tag_name("Group") {
+            let uuid = child(node, "UUID").and_then(|n| n.text());
+            if uuid.is_some() && uuid == recycle_bin {
+                continue;
+            }
+            let name = child(node, "Name").and_then(|n| n.text());
+            let mut path = path.to_vec();
+            path.push(name.unwrap_or("").to_owned());
+            read_group(node, &path, recycle_bin, values, entries);
+        }
+    }
+}
+
+fn read_entry(
+    node: Node,
+    group: &[String],
+    values: &HashMap<NodeId, String>,
+) -> KdbxEntry {
+    let mut entry = KdbxEntry {
+        group: group.to_vec(),
+        ..Default::default()
+    };
+    for field in node.children().filter(|n| n.has_tag_name("String")) {
+        let key = child(field, "Key").and_then(|n| n.text()).unwrap_or("");
+        let value = child(field, "Value").map(|n| {
+            values
+                .get(&n.id())
+                .cloned()
+                .unwrap_or_else(|| n.text().unwrap_or("").to_owned())
+        });
+        entry
+            .fields
+            .push((key.to_owned(), value.unwrap_or_default()));
+    }
+    if let Some(tags) = child(node, "Tags").and_then(|n| n.text()) {
+        entry.tags = tags
+            .split([';', ','])
+            .map(|t| t.trim().to_owned())
+            .filter(|t| !t.is_empty())
+            .collect();
+    }
+    if let Some(times) = child(node, "Times") {
+        let time =
+            |name| child(times, name).and_then(|n| parse_time(n.text()?));
+        entry.created = time("CreationTime");
+        entry.modified = time("LastModificationTime");
+    }
+    if let Some(history) = child(node, "History") {
+        entry.history = history
+            .children()
+            .filter(|n| n.has_tag_name("Entry"))
+            .map(|n| read_entry(n, group, values))
+            .collect();
+    }
+    entry
+}
+
+fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+/// Parse a KDBX 4 time, the base64 of the seconds since 0001-01-01
+fn parse_time(text: &str) -> Option<u64> {
+    let bytes = base64::decode_block(text).ok()?;
+    let seconds = i64::from_le_bytes(bytes.try_into().ok()?);
+    (seconds - UNIX_EPOCH_OFFSET).try_into().ok()
+}
+
+/// Format a unix time as a KDBX 4 time
+fn format_time(time: u64) -> String {
+    let seconds = time as i64 + UNIX_EPOCH_OFFSET;
+    base64::encode_block(&seconds.to_le_bytes())
+}
+
+fn u32_le(bytes: &[u8]) -> Result<u32, SsapError> {
+    Ok(u32::from_le_bytes(bytes.try_into().map_err(invalid)?))
+}
+
+fn u64_le(bytes: &[u8]) -> Result<u64, SsapError> {
+    Ok(u64::from_le_bytes(bytes.try_into().map_err(invalid)?))
+}
+
+/// Cursor over a byte buffer
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SsapError> {
+        let end = self.pos.checked_add(len).ok_or(SsapError::InvalidKdbx)?;
+        let bytes =
+            self.data.get(self.pos..end).ok_or(SsapError::InvalidKdbx)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SsapError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SsapError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, SsapError> {
+        u32_le(self.take(4)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARGON2_CHACHA20: &[u8] =
+        include_bytes!("testdata/argon2id-chacha20.kdbx");
+    const AES_KDF_AES: &[u8] = include_bytes!("testdata/aes-kdf-aes.kdbx");
+
+    #[test]
+    fn test_read_argon2_chacha20() {
+        let entries = read(ARGON2_CHACHA20, "correct horse").unwrap();
+        let names: Vec<String> = entries.iter().map(|e| e.name()).collect();
+        assert_eq!(
+            names,
+            ["Bank", "Email/Gmail", "Email/Dup", "Email/Dup", "Email/a-b"]
+        );
+
+        let gmail = entries[1].to_entry();
+        assert_eq!(gmail.password, "s3cr3t & <pw>");
+        assert_eq!(gmail.get("username"), Some("me@gmail.com"));
+        assert_eq!(gmail.get("notes"), Some("line one\nline two"));
+        assert_eq!(gmail.get("PIN:code"), Some("1234"));
+        assert_eq!(gmail.tags(), ["mail", "personal"]);
+        assert_eq!(gmail.get("modified"), Some("1700000000"));
+        assert!(gmail.get("otpauth").unwrap().starts_with("otpauth://"));
+        assert_eq!(entries[1].history.len(), 1);
+        assert_eq!(entries[1].history[0].get("Password"), Some("old-pass"));
+
+        let bank = entries[0].to_entry();
+        let otp = Otp::from_uri(bank.get("otpauth").unwrap()).unwrap();
+        assert_eq!(otp.digits, 8);
+        assert_eq!(bank.get("TOTP Seed"), None);
+    }
+
+    #[test]
+    fn test_read_aes() {
+        let entries = read(AES_KDF_AES, "swordfish").unwrap();
+        assert_eq!(entries.len(), 1);
+        let record = entries[0].to_record();
+        assert_eq!(record.name, "Work/VPN");
+        assert_eq!(record.entry.password, "hunter2");
+        assert_eq!(record.entry.get("username"), Some("alice"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let imported = read(ARGON2_CHACHA20, "correct horse").unwrap();
+        let mut records: Vec<Record> =
+            imported.iter().map(|e| e.to_record()).collect();
+        records[4]
+            .entry
+            .set("url", "https://a.com/?a=1&b=<2>".into());
+        let exported: Vec<KdbxEntry> = imported
+            .iter()
+            .zip(&records)
+            .map(|(kdbx, record)| {
+                let history: Vec<(u64, Entry)> = kdbx
+                    .history
+                    .iter()
+                    .map(|old| (old.modified.unwrap(), old.to_entry()))
+                    .collect();
+                KdbxEntry::from_entry(&record.name, &record.entry, &history)
+            })
+            .collect();
+        let kdf = KdbxKdf {
+            memory: 1024 * 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let data = write(&exported, "new password", &kdf).unwrap();
+
+        let reimported = read(&data, "new password").unwrap();
+        let mut again: Vec<Record> =
+            reimported.iter().map(|e| e.to_record()).collect();
+        // KDBX entries always have times, the export uses the current time
+        for (record, original) in again.iter_mut().zip(&records) {
+            if original.entry.get("modified").is_none() {
+                record.entry.remove("created");
+                record.entry.remove("modified");
+            }
+        }
+        assert_eq!(again, records);
+        assert_eq!(reimported[1].history.len(), 1);
+        assert_eq!(reimported[1].history[0].to_entry().password, "old-pass");
+        assert!(read(&data, "correct horse").is_err());
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert!(matches!(
+            read(AES_KDF_AES, "wrong"),
+            Err(SsapError::InvalidKdbxPassword)
+        ));
+        assert!(matches!(
+            read(b"not a database", ""),
+            Err(SsapError::InvalidKdbx)
+        ));
+        let mut corrupted = AES_KDF_AES.to_vec();
+        let last = corrupted.len() - 40;
+        corrupted[last] ^= 1;
+        assert!(matches!(
+            read(&corrupted, "swordfish"),
+            Err(SsapError::InvalidKdbx)
+        ));
+    }
+
+    #[test]
+    fn test_kdf_limits() {
+        let mut kdf = HashMap::from([
+            ("$UUID".to_owned(), KDF_ARGON2ID.to_vec()),
+            ("V".to_owned(), 0x13u32.to_le_bytes().to_vec()),
+            ("M".to_owned(), (1u64 << 42).to_le_bytes().to_vec()),
+            ("I".to_owned(), 2u64.to_le_bytes().to_vec()),
+            ("P".to_owned(), 1u32.to_le_bytes().to_vec()),
+            ("S".to_owned(), vec![0; 32]),
+        ]);
+        assert!(matches!(
+            transform_key(&[0; 32], &kdf),
+            Err(SsapError::KdbxKdfTooExpensive)
+        ));
+        kdf.insert("M".to_owned(), 1024u64.to_le_bytes().to_vec());
+        kdf.insert("I".to_owned(), u64::MAX.to_le_bytes().to_vec());
+        assert!(matches!(
+            transform_key(&[0; 32], &kdf),
+            Err(SsapError::KdbxKdfTooExpensive)
+        ));
+        kdf.insert("$UUID".to_owned(), KDF_AES.to_vec());
+        kdf.insert("R".to_owned(), u64::MAX.to_le_bytes().to_vec());
+        assert!(matches!(
+            transform_key(&[0; 32], &kdf),
+            Err(SsapError::KdbxKdfTooExpensive)
+        ));
+    }
+}
@@ -0,0 +1,480 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! Merge of two copies of a vault that changed independently.
+//!
+//! Passwords are merged one by one: a password changed on a single side
+//! takes that change. `merge` works on the encrypted contents, as done by
+//! the git merge driver, and keeps both versions of a password changed on
+//! both sides. `merge_vaults` decrypts the passwords, so it also follows
+//! their history and lets a callback resolve the conflicts.
+
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::storage::Contents;
+use crate::ssap::vault::Vault;
+
+/// A password changed on both sides
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// Both sides changed the password: ours keeps its name, theirs is
+    /// kept as `copy`
+    Changed { name: String, copy: String },
+    /// One side deleted the password that the other changed, the changed
+    /// version is kept
+    Deleted { name: String },
+}
+
+/// Merge the passwords of two vaults
+///
+/// # Arguments
+/// * `base` - The common ancestor of both vaults, `None` if they were
+///   created separately
+/// * `ours` - Our vault
+/// * `theirs` - Their vault
+/// # Returns
+/// * The merged contents, with the header and the order of our vault, and
+///   the conflicts
+/// * `SsapError::IncompatibleVaults` if the vaults are not protected by
+///   the same key
+///
+pub fn merge(
+    base: Option<&Contents>,
+    ours: &Contents,
+    theirs: &Contents,
+) -> Result<(Contents, Vec<Conflict>), SsapError> {
+    if ours.header.kdf != theirs.header.kdf
+        || base.is_some_and(|base| base.header.kdf != ours.header.kdf)
+    {
+        return Err(SsapError::IncompatibleVaults);
+    }
+    let find = |contents: &Contents, name: &str| -> Option<Vec<u8>> {
+        contents
+            .entries
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, ciphertext)| ciphertext.clone())
+    };
+
+    let mut names: Vec<&String> = ours.entries.iter().map(|(n, _)| n).collect();
+    for (name, _) in &theirs.entries {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    // Deleted on both sides, or on one side and unchanged on the other
+    if let Some(base) = base {
+        for (name, _) in &base.entries {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut copies = Vec::new();
+    let mut conflicts = Vec::new();
+    for name in names {
+        let o = find(ours, name);
+        let t = find(theirs, name);
+        let b = base.and_then(|base| find(base, name));
+        let merged = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else if let (Some(o), Some(t)) = (&o, t.clone()) {
+            copies.push((name.clone(), t));
+            Some(o.clone())
+        } else {
+            conflicts.push(Conflict::Deleted { name: name.clone() });
+            o.or(t)
+        };
+        if let Some(ciphertext) = merged {
+            entries.push((name.clone(), ciphertext));
+        }
+    }
+    for (name, ciphertext) in copies {
+        let names: Vec<&str> =
+            entries.iter().map(|(n, _)| n.as_str()).collect();
+        let copy = conflict_name(&name, &names);
+        entries.push((copy.clone(), ciphertext));
+        conflicts.push(Conflict::Changed { name, copy });
+    }
+
+    let contents = Contents {
+        header: ours.header.clone(),
+        entries,
+    };
+    Ok((contents, conflicts))
+}
+
+/// How a password changed on both sides is resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+    /// Keep both versions, the most recently modified one under the name
+    /// of the password
+    Both,
+}
+
+/// Merge their vault into ours, after both were unlocked
+///
+/// Without a common ancestor, a password found on a single side is kept.
+/// The last modification of the passwords identical on both sides tells
+/// when the vaults were last in sync: a version modified before, while the
+/// other side modified it after, is replaced by the other side. So is a
+/// version found in the history of the other side. Passwords still
+/// different are true conflicts, given to `resolve` with our version and
+/// theirs.
+///
+/// # Arguments
+/// * `base` - The common ancestor of both vaults, if known
+/// * `ours` - Our vault, receiving the result
+/// * `theirs` - Their vault
+/// * `resolve` - Chooses the versions kept on a true conflict
+/// # Returns
+/// * The names of the passwords taken from their vault, and the conflicts
+///   resolved by keeping both versions or the changed version
+/// * A SsapError if a password cannot be decrypted
+///
+pub fn merge_vaults<F>(
+    base: Option<&Vault>,
+    ours: &mut Vault,
+    theirs: &Vault,
+    mut resolve: F,
+) -> Result<(Vec<String>, Vec<Conflict>), SsapError>
+where
+    F: FnMut(&str, &Entry, &Entry) -> Result<Resolution, SsapError>,
+{
+    let get = |vault: &Vault, name: &str| -> Result<Option<Entry>, SsapError> {
+        match vault.contains(name) {
+            true => Ok(Some(vault.get(name)?.entry)),
+            false => Ok(None),
+        }
+    };
+    let mut names = ours.list();
+    for vault in [Some(theirs), base].into_iter().flatten() {
+        for name in vault.list() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    let mut synced = 0;
+    if base.is_none() {
+        for name in &names {
+            match get(ours, name)? {
+                Some(o) if Some(&o) == get(theirs, name)?.as_ref() => {
+                    synced = synced.max(modified(&o))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut taken = Vec::new();
+    let mut conflicts = Vec::new();
+    for name in names {
+        let o = get(ours, &name)?;
+        let t = get(theirs, &name)?;
+        if o == t {
+            continue;
+        }
+        if let Some(base) = base {
+            let b = get(base, &name)?;
+            if t == b {
+                continue;
+            }
+            if o == b {
+                replace(ours, &name, t)?;
+                taken.push(name);
+                continue;
+            }
+        }
+        let (o, t) = match (o, t) {
+            (Some(o), Some(t)) => (o, t),
+            // Added on our side, or deleted on theirs but changed on ours
+            (Some(_o), None) => {
+                if base.is_some() {
+                    conflicts.push(Conflict::Deleted { name });
+                }
+                continue;
+            }
+            (None, t) => {
+                if base.is_some() {
+                    conflicts.push(Conflict::Deleted { name: name.clone() });
+                }
+                replace(ours, &name, t)?;
+                taken.push(name);
+                continue;
+            }
+        };
+        // A side modified since the last sync, or holding the other
+        // version in its history, replaced the other version
+        let (o_synced, t_synced) =
+            (modified(&o) <= synced, modified(&t) <= synced);
+        if (t_synced && !o_synced)
+            || ours.history(&name)?.iter().any(|(_, old)| *old == t)
+        {
+            continue;
+        }
+        if (o_synced && !t_synced)
+            || theirs.history(&name)?.iter().any(|(_, old)| *old == o)
+        {
+            replace(ours, &name, Some(t))?;
+            taken.push(name);
+            continue;
+        }
+        match resolve(&name, &o, &t)? {
+            Resolution::Ours => {}
+            Resolution::Theirs => {
+                replace(ours, &name, Some(t))?;
+                taken.push(name);
+            }
+            Resolution::Both => {
+                let (newer, older) = match modified(&t) > modified(&o) {
+                    true => (t, o),
+                    false => (o, t),
+                };
+                let copy = conflict_name(&name, &ours.list());
+                ours.insert(&name, newer)?;
+                ours.insert(&copy, older)?;
+                conflicts.push(Conflict::Changed { name, copy });
+            }
+        }
+    }
+    Ok((taken, conflicts))
+}
+
+/// The time a password was last modified, 0 if unknown
+pub fn modified(entry: &Entry) -> u64 {
+    entry
+        .get("modified")
+        .and_then(|time| time.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Insert a version of a password, or remove it for `None`
+fn replace(
+    vault: &mut Vault,
+    name: &str,
+    entry: Option<Entry>,
+) -> Result<(), SsapError> {
+    match entry {
+        Some(entry) => vault.insert(name, entry),
+        None => vault.remove(name),
+    }
+}
+
+/// A free name for their version of a conflicting password, like
+/// `mail (conflict)` or `mail (conflict 2)`
+fn conflict_name<T: AsRef<str>>(name: &str, taken: &[T]) -> String {
+    let mut copy = format!("{} (conflict)", name);
+    let mut n = 1;
+    while taken.iter().any(|taken| taken.as_ref() == copy) {
+        n += 1;
+        copy = format!("{} (conflict {})", name, n);
+    }
+    copy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssap::sqlite::SqliteStorage;
+    use crate::ssap::ssap::Encryption;
+    use crate::ssap::storage::{Header, MemoryStorage, VaultStorage};
+
+    fn contents(entries: &[(&str, &str)]) -> Contents {
+        Contents {
+            header: Header::parse("1.0.0").unwrap(),
+            entries: entries
+                .iter()
+                .map(|(name, c)| (name.to_string(), c.as_bytes().to_vec()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = contents(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")]);
+        // We change a, delete b and add e; they change c, move d to f
+        let ours = contents(&[("a", "2"), ("c", "1"), ("d", "1"), ("e", "1")]);
+        let theirs =
+            contents(&[("a", "1"), ("b", "1"), ("c", "2"), ("f", "1")]);
+        let (merged, conflicts) = merge(Some(&base), &ours, &theirs).unwrap();
+        assert_eq!(
+            merged,
+            contents(&[("a", "2"), ("c", "2"), ("e", "1"), ("f", "1")])
+        );
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let base = contents(&[("a", "1"), ("b", "1"), ("c", "1")]);
+        let ours = contents(&[("a", "2"), ("c", "2"), ("a (conflict)", "x")]);
+        let theirs = contents(&[("a", "3"), ("b", "2")]);
+        let (merged, conflicts) = merge(Some(&base), &ours, &theirs).unwrap();
+        assert_eq!(
+            merged,
+            contents(&[
+                ("a", "2"),
+                ("c", "2"),
+                ("a (conflict)", "x"),
+                ("b", "2"),
+                ("a (conflict 2)", "3"),
+            ])
+        );
+        assert_eq!(
+            conflicts,
+            [
+                Conflict::Deleted { name: "c".into() },
+                Conflict::Deleted { name: "b".into() },
+                Conflict::Changed {
+                    name: "a".into(),
+                    copy: "a (conflict 2)".into()
+                },
+            ]
+        );
+
+        // Created separately, a password added on both sides conflicts
+        let (merged, _) = merge(None, &ours, &theirs).unwrap();
+        assert_eq!(merged.entries.len(), 5);
+    }
+
+    fn vault(
+        storage: Box<dyn VaultStorage>,
+        entries: &[(&str, &str, u64)],
+    ) -> Vault {
+        let mut vault =
+            Vault::create_with(storage, Encryption::Aes_256_cbc, 1_000)
+                .unwrap();
+        vault.unlock("secret").unwrap();
+        for (name, password, modified) in entries {
+            vault.insert(name, entry(password, *modified)).unwrap();
+        }
+        vault
+    }
+
+    fn entry(password: &str, modified: u64) -> Entry {
+        let mut entry = Entry::new(password.to_owned());
+        entry.set("modified", modified.to_string());
+        entry
+    }
+
+    fn memory(entries: &[(&str, &str, u64)]) -> Vault {
+        vault(Box::new(MemoryStorage::new()), entries)
+    }
+
+    #[test]
+    fn test_merge_vaults() {
+        let base = memory(&[("a", "1", 1), ("b", "1", 1), ("c", "1", 1)]);
+        let mut ours = memory(&[("a", "2", 2), ("c", "2", 3), ("d", "1", 2)]);
+        let theirs = memory(&[("a", "1", 1), ("b", "2", 2), ("c", "3", 2)]);
+        let mut asked = Vec::new();
+        let (taken, conflicts) =
+            merge_vaults(Some(&base), &mut ours, &theirs, |name, o, t| {
+                asked.push((name.to_owned(), o.clone(), t.clone()));
+                Ok(Resolution::Both)
+            })
+            .unwrap();
+        assert_eq!(asked, [("c".into(), entry("2", 3), entry("3", 2))]);
+        assert_eq!(taken, ["b"]);
+        assert_eq!(
+            conflicts,
+            [
+                Conflict::Changed {
+                    name: "c".into(),
+                    copy: "c (conflict)".into()
+                },
+                Conflict::Deleted { name: "b".into() },
+            ]
+        );
+        assert_eq!(ours.list(), ["a", "c", "d", "c (conflict)", "b"]);
+        assert_eq!(ours.get("c").unwrap().entry, entry("2", 3));
+        assert_eq!(ours.get("c (conflict)").unwrap().entry, entry("3", 2));
+
+        // Without a common ancestor, additions are kept on both sides
+        let mut ours = memory(&[("a", "1", 1), ("b", "2", 2)]);
+        let theirs = memory(&[("b", "3", 3), ("c", "1", 1)]);
+        let (taken, conflicts) =
+            merge_vaults(None, &mut ours, &theirs, |_, _, _| {
+                Ok(Resolution::Theirs)
+            })
+            .unwrap();
+        assert_eq!(taken, ["b", "c"]);
+        assert!(conflicts.is_empty());
+        assert_eq!(ours.get("b").unwrap().entry, entry("3", 3));
+
+        // In sync at 5: a changed by us, b by them, c by both
+        let mut ours = memory(&[
+            ("s", "1", 5),
+            ("a", "2", 6),
+            ("b", "1", 4),
+            ("c", "2", 7),
+        ]);
+        let theirs = memory(&[
+            ("s", "1", 5),
+            ("a", "1", 3),
+            ("b", "2", 8),
+            ("c", "3", 6),
+        ]);
+        let mut asked = Vec::new();
+        let (taken, _) =
+            merge_vaults(None, &mut ours, &theirs, |name, _, _| {
+                asked.push(name.to_owned());
+                Ok(Resolution::Ours)
+            })
+            .unwrap();
+        assert_eq!(taken, ["b"]);
+        assert_eq!(asked, ["c"]);
+        assert_eq!(ours.get("a").unwrap().entry, entry("2", 6));
+    }
+
+    #[test]
+    fn test_merge_history() {
+        let path = std::env::temp_dir().join("ssap_test_merge_history.db");
+        let _ = std::fs::remove_file(&path);
+        let storage = Box::new(SqliteStorage::new(&path));
+        let mut theirs = vault(storage, &[("a", "1", 1)]);
+        theirs.save().unwrap();
+        theirs.insert("a", entry("2", 2)).unwrap();
+        theirs.save().unwrap();
+
+        // Their version replaced ours, no conflict
+        let mut ours = memory(&[("a", "1", 1)]);
+        let (taken, _) = merge_vaults(None, &mut ours, &theirs, |_, _, _| {
+            panic!("not a conflict")
+        })
+        .unwrap();
+        assert_eq!(taken, ["a"]);
+        assert_eq!(ours.get("a").unwrap().entry, entry("2", 2));
+        let _ = std::fs::remove_file(&path);
+    }
+}
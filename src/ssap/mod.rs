@@ -24,8 +24,28 @@ all
 *
 */
 
+pub mod archive;
+pub mod backup;
+pub mod bitwarden;
+pub mod config;
 pub mod crypto;
+pub mod csv;
+pub mod entry;
 pub mod error;
-pub mod parse;
-pub mod run;
+pub mod folder;
+pub mod format;
+pub mod import;
+pub mod inject;
+pub mod json;
+pub mod kdbx;
+pub mod merge;
+pub mod name;
+pub mod otp;
+pub mod pass;
+pub mod query;
+pub mod search;
+pub mod sqlite;
+#[allow(clippy::module_inception)]
 pub mod ssap;
+pub mod storage;
+pub mod vault;
@@ -0,0 +1,187 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::error::SsapError;
+use crate::ssap::folder::normalize;
+use std::fmt::{Display, Error, Formatter};
+
+/// Maximum length of a password name, in bytes
+pub const MAX_NAME_LEN: usize = 255;
+
+/// First version of ssap writing encoded names in the vault file
+pub const NAME_ENCODING_VERSION: &str = "1.1.0";
+
+/// A validated password name.
+///
+/// Names are normalized like folders (`/work//github/` is `work/github`)
+/// and may hold any printable character, but no control character such as
+/// a newline. `.` and `..` are not valid folder names, so that a name can
+/// always be mapped to a path.
+///
+/// In the vault file a name is followed by `: `, so `%` and `:` are
+/// percent-encoded on disk: `a: b` is stored as `a%3A b`. Vault files
+/// written before `NAME_ENCODING_VERSION` hold the names as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryName(String);
+
+impl EntryName {
+    /// Validate and normalize a name
+    ///
+    /// # Arguments
+    /// * `name` - The name, as typed by the user
+    /// # Returns
+    /// * The normalized name
+    /// * `SsapError::InvalidPasswordName` if the name is not valid
+    ///
+    pub fn new(name: &str) -> Result<EntryName, SsapError> {
+        let name = normalize(name);
+        let valid = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && !name.chars().any(|c| c.is_control())
+            && name.split('/').all(|part| part != "." && part != "..");
+        if !valid {
+            return Err(SsapError::InvalidPasswordName);
+        }
+        Ok(EntryName(name))
+    }
+
+    /// Decode a name as stored in the vault file. Vault files are trusted,
+    /// so the name is not validated. Only `%` followed by two hex digits is
+    /// decoded, anything else is kept as it is.
+    pub fn decode(encoded: &str) -> EntryName {
+        let bytes = encoded.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let hex = bytes.get(i + 1..i + 3);
+            match hex.filter(|h| h.iter().all(u8::is_ascii_hexdigit)) {
+                Some(hex) if bytes[i] == b'%' => {
+                    out.push(hex_value(hex[0]) << 4 | hex_value(hex[1]));
+                    i += 3;
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        EntryName(String::from_utf8_lossy(&out).into_owned())
+    }
+
+    /// Encode the name to be stored in the vault file
+    pub fn encode(&self) -> String {
+        encode(&self.0)
+    }
+
+    /// The name as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Encode a name to be stored in the vault file, see `EntryName`
+pub fn encode(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '%' => out.push_str("%25"),
+            ':' => out.push_str("%3A"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The value of an ASCII hex digit
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// Split a line of the vault file in its name and the encrypted password,
+/// or `None` if the line holds no password
+///
+/// # Arguments
+/// * `line` - The line, without the line break
+/// * `encoded` - Whether the vault file encodes names, see `EntryName`
+pub fn split_line(line: &str, encoded: bool) -> Option<(String, &str)> {
+    let (name, passwd) = line.split_once(": ")?;
+    match encoded {
+        true => Some((EntryName::decode(name).0, passwd)),
+        false => Some((name.to_owned(), passwd)),
+    }
+}
+
+/// Format a line of the vault file
+pub fn format_line(name: &str, passwd: &str) -> String {
+    format!("{}: {}", encode(name), passwd)
+}
+
+impl Display for EntryName {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(
+            EntryName::new("/work//github/").unwrap().as_str(),
+            "work/github"
+        );
+        assert!(EntryName::new("").is_err());
+        assert!(EntryName::new("//").is_err());
+        assert!(EntryName::new("a\nb").is_err());
+        assert!(EntryName::new("work/../etc").is_err());
+        assert!(EntryName::new(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+        assert!(EntryName::new("-weird: name 100%").is_ok());
+    }
+
+    #[test]
+    fn test_encoding() {
+        let name = EntryName::new("db: prod 100%").unwrap();
+        assert_eq!(name.encode(), "db%3A prod 100%25");
+        assert_eq!(EntryName::decode(&name.encode()), name);
+        assert_eq!(EntryName::decode("50%off").as_str(), "50%off");
+        assert_eq!(EntryName::decode("a%+1").as_str(), "a%+1");
+        assert_eq!(EntryName::decode("a%7e%7E").as_str(), "a~~");
+        let line = format_line("db: prod", "00ff");
+        assert_eq!(line, "db%3A prod: 00ff");
+        let name = ("db: prod".to_owned(), "00ff");
+        assert_eq!(split_line(&line, true), Some(name));
+        assert_eq!(split_line("garbage", true), None);
+        // Names written before the encoding are read back unchanged
+        let name = ("wifi%20home".to_owned(), "00ff");
+        assert_eq!(split_line("wifi%20home: 00ff", false), Some(name));
+    }
+}
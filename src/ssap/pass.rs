@@ -0,0 +1,184 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+//! Reader of password-store (`pass`) directories.
+//!
+//! Every password is a `.gpg` file, named after the password and placed in
+//! its folders. The decrypted file holds the password on its first line,
+//! usually followed by `key: value` lines and free notes. Decryption is
+//! left to the caller, which runs `gpg`.
+
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::import::{field_key, name_part};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Keys used by pass users and extensions, and the ssap field they map to
+const FIELD_ALIASES: &[(&str, &str)] = &[
+    ("login", "username"),
+    ("user", "username"),
+    ("username", "username"),
+    ("url", "url"),
+    ("website", "url"),
+    ("site", "url"),
+];
+
+/// List the passwords of a password store
+///
+/// # Arguments
+/// * `dir` - The root of the store, usually `~/.password-store`
+/// # Returns
+/// * The ssap name and the path of every `.gpg` file, sorted by name.
+///   Hidden files and directories, such as `.git`, are skipped, and so
+///   are symlinks to directories.
+/// * `SsapError::InvalidPath` if the directory cannot be read
+///
+pub fn list(dir: &Path) -> Result<Vec<(String, PathBuf)>, SsapError> {
+    let mut files = Vec::new();
+    walk(dir, &[], &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn walk(
+    dir: &Path,
+    folders: &[String],
+    files: &mut Vec<(String, PathBuf)>,
+) -> Result<(), SsapError> {
+    let entries = fs::read_dir(dir).map_err(|_| SsapError::InvalidPath)?;
+    for entry in entries {
+        let entry = entry.map_err(|_| SsapError::InvalidPath)?;
+        let file_type =
+            entry.file_type().map_err(|_| SsapError::InvalidPath)?;
+        let path = entry.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if file_name.starts_with('.') {
+            continue;
+        }
+        let mut name = folders.to_vec();
+        // Symlinked directories are not followed, they could form a loop
+        if file_type.is_dir() {
+            name.push(name_part(&file_name));
+            walk(&path, &name, files)?;
+        } else if let Some(stem) = file_name.strip_suffix(".gpg") {
+            name.push(name_part(stem));
+            files.push((name.join("/"), path));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a decrypted pass file into an entry
+///
+/// Lines following the password become fields when they look like
+/// `key: value`, `otpauth://` lines become the OTP secret and any other
+/// line is kept in the notes.
+pub fn parse_entry(plaintext: &str) -> Entry {
+    let mut lines = plaintext.lines();
+    let mut entry = Entry::new(lines.next().unwrap_or("").to_owned());
+    let mut notes: Vec<&str> = Vec::new();
+    for line in lines {
+        if line.starts_with("otpauth://") && entry.get("otpauth").is_none() {
+            entry.set("otpauth", line.to_owned());
+            continue;
+        }
+        let field = line.split_once(':').filter(|(key, value)| {
+            !key.is_empty()
+                && !key.contains(char::is_whitespace)
+                && !value.starts_with("//")
+        });
+        let Some((key, value)) = field else {
+            notes.push(line);
+            continue;
+        };
+        let lower = key.to_lowercase();
+        let key = FIELD_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == lower)
+            .map(|(_, field)| field.to_string())
+            .unwrap_or_else(|| field_key(key));
+        let reserved = matches!(key.as_str(), "tags" | "created" | "modified");
+        if reserved || entry.get(&key).is_some() {
+            notes.push(line);
+        } else {
+            entry.set(&key, value.trim().to_owned());
+        }
+    }
+    while notes.last().is_some_and(|line| line.trim().is_empty()) {
+        notes.pop();
+    }
+    if !notes.is_empty() {
+        entry.set("notes", notes.join("\n"));
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        let entry = parse_entry(
+            "hunter2\nlogin: me\nURL: https://x.com\n\
+             otpauth://totp/x?secret=JBSWY3DPEHPK3PXP\n\
+             free text\npin: 1234\nlogin: again\n\n",
+        );
+        assert_eq!(entry.password, "hunter2");
+        assert_eq!(entry.get("username"), Some("me"));
+        assert_eq!(entry.get("url"), Some("https://x.com"));
+        assert_eq!(entry.get("pin"), Some("1234"));
+        assert!(entry.get("otpauth").is_some());
+        assert_eq!(entry.get("notes"), Some("free text\nlogin: again"));
+        assert_eq!(parse_entry("only").fields, []);
+    }
+
+    #[test]
+    fn test_list() {
+        let dir = std::env::temp_dir()
+            .join(format!("ssap-pass-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("work/aws")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        for file in ["work/aws/prod.gpg", "mail.gpg", ".gpg-id", ".git/x.gpg"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let names: Vec<String> = list(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["mail", "work/aws/prod"]);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, dir.join("work/loop")).unwrap();
+            assert_eq!(list(&dir).unwrap().len(), 2);
+        }
+        assert!(list(&dir.join("missing")).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,331 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+
+/// A filter over the passwords of a vault, used by `list` and `find`.
+///
+/// The language is made of terms combined with `AND`, `OR`, `NOT` and
+/// parentheses, where `AND` is implied between adjacent terms:
+///
+/// * `tag:prod` - the password has the tag
+/// * `field:value` - the field equals the value, ignoring case
+/// * `field~value` - the field contains the value, ignoring case
+/// * `modified<90d`, `created>1y` - age of the password, in `s`, `m`, `h`,
+///   `d`, `w` or `y`
+/// * `word` - the name contains the word, ignoring case
+///
+/// Values with spaces can be quoted: `notes~"two words"`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Tag(String),
+    Equals(String, String),
+    Contains(String, String),
+    YoungerThan(String, u64),
+    OlderThan(String, u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Term(String),
+}
+
+impl Query {
+    /// Parse a query
+    ///
+    /// # Arguments
+    /// * `query` - The query text
+    /// # Returns
+    /// * The parsed query
+    /// * An error if the query is malformed
+    ///
+    pub fn parse(query: &str) -> Result<Query, SsapError> {
+        let tokens = tokenize(query)?;
+        let mut pos = 0;
+        let parsed = parse_or(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(SsapError::InvalidQuery);
+        }
+        Ok(parsed)
+    }
+
+    /// Whether evaluating the query needs the decrypted entries, that is
+    /// when it looks at anything but the names.
+    pub fn needs_entry(&self) -> bool {
+        match self {
+            Query::And(a, b) | Query::Or(a, b) => {
+                a.needs_entry() || b.needs_entry()
+            }
+            Query::Not(q) => q.needs_entry(),
+            Query::Equals(field, _) | Query::Contains(field, _) => {
+                field != "name"
+            }
+            _ => true,
+        }
+    }
+
+    /// Evaluate the query against a password
+    ///
+    /// # Arguments
+    /// * `name` - The name of the password
+    /// * `entry` - The decrypted entry, needed if `needs_entry` is true
+    /// * `now` - The current unix timestamp
+    /// # Returns
+    /// * Whether the password matches
+    ///
+    pub fn matches(&self, name: &str, entry: Option<&Entry>, now: u64) -> bool {
+        let field = |key: &str| -> Option<String> {
+            if key == "name" {
+                return Some(name.to_owned());
+            }
+            entry.and_then(|e| e.get(key)).map(|v| v.to_owned())
+        };
+        let age = |key: &str| -> Option<u64> {
+            let time = field(key)?.parse::<u64>().ok()?;
+            Some(now.saturating_sub(time))
+        };
+        match self {
+            Query::And(a, b) => {
+                a.matches(name, entry, now) && b.matches(name, entry, now)
+            }
+            Query::Or(a, b) => {
+                a.matches(name, entry, now) || b.matches(name, entry, now)
+            }
+            Query::Not(q) => !q.matches(name, entry, now),
+            Query::Tag(tag) => entry.is_some_and(|e| {
+                e.tags().iter().any(|t| t.eq_ignore_ascii_case(tag))
+            }),
+            Query::Equals(key, value) => {
+                field(key).is_some_and(|v| v.eq_ignore_ascii_case(value))
+            }
+            Query::Contains(key, value) => field(key).is_some_and(|v| {
+                v.to_lowercase().contains(&value.to_lowercase())
+            }),
+            Query::YoungerThan(key, seconds) => {
+                age(key).is_some_and(|age| age < *seconds)
+            }
+            Query::OlderThan(key, seconds) => {
+                age(key).is_some_and(|age| age > *seconds)
+            }
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, SsapError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::Open);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::Close);
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                chars.next();
+                if c != '"' {
+                    word.push(c);
+                    continue;
+                }
+                // Quoted part of a term, kept verbatim
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(SsapError::InvalidQuery),
+                    }
+                }
+            }
+            tokens.push(match word.as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => Token::Term(word),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize) -> Result<Query, SsapError> {
+    let mut query = parse_and(tokens, pos)?;
+    while tokens.get(*pos) == Some(&Token::Or) {
+        *pos += 1;
+        let rhs = parse_and(tokens, pos)?;
+        query = Query::Or(Box::new(query), Box::new(rhs));
+    }
+    Ok(query)
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize) -> Result<Query, SsapError> {
+    let mut query = parse_not(tokens, pos)?;
+    loop {
+        match tokens.get(*pos) {
+            Some(Token::And) => *pos += 1,
+            Some(Token::Not) | Some(Token::Open) | Some(Token::Term(_)) => {}
+            _ => break,
+        }
+        let rhs = parse_not(tokens, pos)?;
+        query = Query::And(Box::new(query), Box::new(rhs));
+    }
+    Ok(query)
+}
+
+fn parse_not(tokens: &[Token], pos: &mut usize) -> Result<Query, SsapError> {
+    match tokens.get(*pos) {
+        Some(Token::Not) => {
+            *pos += 1;
+            Ok(Query::Not(Box::new(parse_not(tokens, pos)?)))
+        }
+        Some(Token::Open) => {
+            *pos += 1;
+            let query = parse_or(tokens, pos)?;
+            if tokens.get(*pos) != Some(&Token::Close) {
+                return Err(SsapError::InvalidQuery);
+            }
+            *pos += 1;
+            Ok(query)
+        }
+        Some(Token::Term(term)) => {
+            *pos += 1;
+            parse_term(term)
+        }
+        _ => Err(SsapError::InvalidQuery),
+    }
+}
+
+fn parse_term(term: &str) -> Result<Query, SsapError> {
+    let op = term.find([':', '~', '<', '>']);
+    if op.is_none() {
+        return Ok(Query::Contains("name".to_owned(), term.to_owned()));
+    }
+    let op = op.unwrap();
+    let key = term[..op].to_lowercase();
+    let value = term[op + 1..].to_owned();
+    if key.is_empty() || value.is_empty() {
+        return Err(SsapError::InvalidQuery);
+    }
+    match &term[op..op + 1] {
+        ":" if key == "tag" || key == "tags" => Ok(Query::Tag(value)),
+        ":" => Ok(Query::Equals(key, value)),
+        "~" => Ok(Query::Contains(key, value)),
+        "<" => Ok(Query::YoungerThan(key, parse_duration(&value)?)),
+        _ => Ok(Query::OlderThan(key, parse_duration(&value)?)),
+    }
+}
+
+/// Parse a duration like `90d` into seconds
+fn parse_duration(value: &str) -> Result<u64, SsapError> {
+    let unit = value.chars().last().unwrap_or('s');
+    let (number, multiplier) = match unit {
+        's' => (&value[..value.len() - 1], 1),
+        'm' => (&value[..value.len() - 1], 60),
+        'h' => (&value[..value.len() - 1], 60 * 60),
+        'd' => (&value[..value.len() - 1], 24 * 60 * 60),
+        'w' => (&value[..value.len() - 1], 7 * 24 * 60 * 60),
+        'y' => (&value[..value.len() - 1], 365 * 24 * 60 * 60),
+        _ => (value, 1),
+    };
+    match number.parse::<u64>() {
+        Ok(n) => n.checked_mul(multiplier).ok_or(SsapError::InvalidQuery),
+        Err(_e) => Err(SsapError::InvalidQuery),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn entry(tags: &str, url: &str, modified: u64) -> Entry {
+        let mut entry = Entry::new("secret".to_owned());
+        entry.set("tags", tags.to_owned());
+        entry.set("url", url.to_owned());
+        entry.set("modified", modified.to_string());
+        entry
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Query::parse("tag:prod AND NOT tag:legacy").unwrap(),
+            Query::And(
+                Box::new(Query::Tag("prod".to_owned())),
+                Box::new(Query::Not(Box::new(Query::Tag("legacy".to_owned()))))
+            )
+        );
+        assert_eq!(
+            Query::parse("a OR b c").unwrap(),
+            Query::parse("a OR (b AND c)").unwrap()
+        );
+        assert_eq!(
+            Query::parse("notes~\"two words\"").unwrap(),
+            Query::Contains("notes".to_owned(), "two words".to_owned())
+        );
+        assert!(Query::parse("(tag:prod").is_err());
+        assert!(Query::parse("modified<soon").is_err());
+        assert!(Query::parse("modified<99999999999999999y").is_err());
+        assert!(!Query::parse("github OR name:x").unwrap().needs_entry());
+        assert!(Query::parse("github OR tag:x").unwrap().needs_entry());
+    }
+
+    #[test]
+    fn test_matches() {
+        let now = 1000 * DAY;
+        let fresh = entry("prod,db", "https://github.com", now - 10 * DAY);
+        let old = entry("prod, legacy", "https://gitlab.com", now - 100 * DAY);
+
+        let query = Query::parse("tag:prod AND NOT tag:legacy").unwrap();
+        assert!(query.matches("a", Some(&fresh), now));
+        assert!(!query.matches("b", Some(&old), now));
+
+        let query = Query::parse("modified<90d url~GitHub.com").unwrap();
+        assert!(query.matches("a", Some(&fresh), now));
+        assert!(!query.matches("b", Some(&old), now));
+
+        let query = Query::parse("modified>90d OR work").unwrap();
+        assert!(query.matches("work/a", Some(&fresh), now));
+        assert!(query.matches("b", Some(&old), now));
+        assert!(!query.matches("c", Some(&Entry::default()), now));
+    }
+}
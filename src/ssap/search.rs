@@ -0,0 +1,147 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::error::SsapError;
+use regex::RegexBuilder;
+
+/// Fields of an entry searched by `find` when the vault is unlocked
+pub const SEARCHED_FIELDS: &[&str] = &["username", "url", "tags", "notes"];
+
+/// A search query, either fuzzy or a regular expression
+pub enum Matcher {
+    Fuzzy(String),
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    /// Build a matcher for the query
+    ///
+    /// # Arguments
+    /// * `query` - The query
+    /// * `regex` - Whether the query is a regular expression
+    /// # Returns
+    /// * The matcher
+    /// * An error if the regular expression is invalid
+    ///
+    pub fn new(query: &str, regex: bool) -> Result<Matcher, SsapError> {
+        if !regex {
+            return Ok(Matcher::Fuzzy(query.to_lowercase()));
+        }
+        match RegexBuilder::new(query).case_insensitive(true).build() {
+            Ok(re) => Ok(Matcher::Regex(re)),
+            Err(_e) => Err(SsapError::InvalidRegex),
+        }
+    }
+
+    /// Score how well `text` matches, higher is better
+    pub fn score(&self, text: &str) -> Option<i64> {
+        match self {
+            Matcher::Fuzzy(query) => fuzzy_score(query, text),
+            // Earlier and longer matches rank first
+            Matcher::Regex(re) => re
+                .find(text)
+                .map(|m| (m.end() - m.start()) as i64 * 4 - m.start() as i64),
+        }
+    }
+}
+
+/// Score a fuzzy match of a lowercase `query` against `text`.
+///
+/// Every character of the query must appear in `text`, in order. Matches
+/// are rewarded when they are consecutive, start a word or form a
+/// substring of the text, and penalized for the characters skipped in
+/// between, so `gh` ranks `github` above `aligned-hash`.
+///
+/// # Arguments
+/// * `query` - The lowercase query
+/// * `text` - The text to match
+/// # Returns
+/// * The score of the match, `None` if the query does not match
+///
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let lowercase = text.to_lowercase();
+    let text: Vec<char> = lowercase.chars().collect();
+    let mut query_chars = query.chars().peekable();
+    let mut score: i64 = 0;
+    let mut last_match: Option<usize> = None;
+    for (i, c) in text.iter().enumerate() {
+        if query_chars.peek() != Some(c) {
+            continue;
+        }
+        query_chars.next();
+        score += 10;
+        match last_match {
+            Some(last) if last + 1 == i => score += 15,
+            Some(last) => score -= ((i - last - 1) * 2).min(20) as i64,
+            None => score -= i.min(15) as i64,
+        }
+        let at_word_start =
+            i == 0 || matches!(text[i - 1], '/' | '-' | '_' | '.' | ' ' | '@');
+        if at_word_start {
+            score += 20;
+        }
+        last_match = Some(i);
+        if query_chars.peek().is_none() {
+            if lowercase.contains(query) {
+                score += 30;
+            }
+            // Shorter texts are closer to what was typed
+            return Some(score - (text.len() - i - 1).min(20) as i64 / 2);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("gh", "github").is_some());
+        assert!(fuzzy_score("gh", "work/hg").is_none());
+        assert!(
+            fuzzy_score("gh", "github").unwrap()
+                > fuzzy_score("gh", "aligned-hash").unwrap()
+        );
+        assert!(
+            fuzzy_score("github", "work/github").unwrap()
+                > fuzzy_score("github", "gitlab/hub").unwrap()
+        );
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn test_regex_matcher() {
+        let matcher = Matcher::new("^work/", true).unwrap();
+        assert!(matcher.score("work/github").is_some());
+        assert!(matcher.score("personal/work/github").is_none());
+        assert!(Matcher::new("(", true).is_err());
+    }
+}
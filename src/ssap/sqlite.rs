@@ -0,0 +1,459 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::error::SsapError;
+use crate::ssap::otp::unix_time;
+use crate::ssap::storage::{Change, Contents, Header, VaultStorage, Version};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Version of the database schema, stored in the metadata table
+const SCHEMA_VERSION: &str = "1";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS metadata (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS entries (
+        name TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        ciphertext BLOB NOT NULL,
+        modified INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        ciphertext BLOB NOT NULL,
+        replaced INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS history_name ON history (name);
+";
+
+/// First bytes of every SQLite database
+pub const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+/// A vault stored in an SQLite database, for vaults with many passwords.
+///
+/// The `metadata` table holds the header of the vault, the `entries` table
+/// the encrypted passwords and the `history` table every version of a
+/// password that was replaced or removed, with the time it happened.
+/// The storage is indexed: a password is read on its own, and saving only
+/// touches the passwords that changed, in a single transaction.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    path: PathBuf,
+}
+
+impl SqliteStorage {
+    pub fn new(path: &Path) -> Self {
+        SqliteStorage {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Open the database for writing, creating it if needed
+    fn open(&self) -> Result<Connection, SsapError> {
+        if let Some(parent) = self.path.parent() {
+            if fs::create_dir_all(parent).is_err() {
+                return Err(SsapError::InvalidPath);
+            }
+        }
+        // Created empty and readable only by its owner before SQLite opens
+        // it, which would create it with the default permissions
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        match options.open(&self.path) {
+            Ok(_file) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(_e) => return Err(SsapError::InvalidPath),
+        }
+        match Connection::open(&self.path) {
+            Ok(connection) => Ok(connection),
+            Err(_e) => Err(SsapError::InvalidPath),
+        }
+    }
+
+    /// Open the database read only, or `None` if it does not exist
+    fn read_only(&self) -> Result<Option<Connection>, SsapError> {
+        if !self.path.is_file() {
+            return Ok(None);
+        }
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY;
+        match Connection::open_with_flags(&self.path, flags) {
+            Ok(connection) => Ok(Some(connection)),
+            Err(_e) => Err(SsapError::InvalidPath),
+        }
+    }
+}
+
+impl VaultStorage for SqliteStorage {
+    fn load(&self) -> Result<Option<Contents>, SsapError> {
+        let connection = match self.read_only()? {
+            Some(connection) => connection,
+            None => return Ok(None),
+        };
+        let header = match read_header(&connection)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let entries = connection
+            .prepare("SELECT name, ciphertext FROM entries ORDER BY position")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<Vec<(String, Vec<u8>)>, _>>()
+            });
+        match entries {
+            Ok(entries) => Ok(Some(Contents { header, entries })),
+            Err(_e) => Err(SsapError::InvalidCiphertext),
+        }
+    }
+
+    fn save(&mut self, contents: &Contents) -> Result<(), SsapError> {
+        let mut connection = self.open()?;
+        if write(&mut connection, contents).is_err() {
+            return Err(SsapError::InvalidWrite);
+        }
+        Ok(())
+    }
+
+    fn indexed(&self) -> bool {
+        true
+    }
+
+    fn load_names(&self) -> Result<Option<(Header, Vec<String>)>, SsapError> {
+        let connection = match self.read_only()? {
+            Some(connection) => connection,
+            None => return Ok(None),
+        };
+        let header = match read_header(&connection)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let names = connection
+            .prepare("SELECT name FROM entries ORDER BY position")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<Vec<String>, _>>()
+            });
+        match names {
+            Ok(names) => Ok(Some((header, names))),
+            Err(_e) => Err(SsapError::InvalidCiphertext),
+        }
+    }
+
+    fn entry(&self, name: &str) -> Result<Option<Vec<u8>>, SsapError> {
+        let connection = match self.read_only()? {
+            Some(connection) => connection,
+            None => return Ok(None),
+        };
+        let entry = connection
+            .query_row(
+                "SELECT ciphertext FROM entries WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional();
+        entry.map_err(|_| SsapError::InvalidCiphertext)
+    }
+
+    fn update(
+        &mut self,
+        header: &Header,
+        changes: &[Change],
+        history: &[Version],
+    ) -> Result<(), SsapError> {
+        let mut connection = self.open()?;
+        if update(&mut connection, header, changes, history).is_err() {
+            return Err(SsapError::InvalidWrite);
+        }
+        Ok(())
+    }
+
+    fn history(&self, name: &str) -> Result<Vec<(u64, Vec<u8>)>, SsapError> {
+        let connection = match self.read_only()? {
+            Some(connection) => connection,
+            None => return Ok(Vec::new()),
+        };
+        let history = connection
+            .prepare(
+                "SELECT replaced, ciphertext FROM history WHERE name = ?1 \
+                 ORDER BY replaced, id",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map([name], |row| {
+                        Ok((row.get::<_, i64>(0)? as u64, row.get(1)?))
+                    })?
+                    .collect::<Result<Vec<(u64, Vec<u8>)>, _>>()
+            });
+        match history {
+            Ok(history) => Ok(history),
+            Err(_e) => Err(SsapError::InvalidCiphertext),
+        }
+    }
+}
+
+/// Read the header of the vault, `None` when the database holds no vault
+fn read_header(connection: &Connection) -> Result<Option<Header>, SsapError> {
+    let metadata = read_metadata(connection)?;
+    let version = match metadata.get("version") {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+    let line = match metadata.get("kdf") {
+        Some(kdf) => format!("{} {}", version, kdf),
+        None => version.clone(),
+    };
+    Ok(Some(Header::parse(&line)?))
+}
+
+/// Read the metadata table, empty when the database holds no vault
+fn read_metadata(
+    connection: &Connection,
+) -> Result<HashMap<String, String>, SsapError> {
+    let exists = connection
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE name = 'metadata'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional();
+    match exists {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(HashMap::new()),
+        Err(_e) => return Err(SsapError::InvalidVersion),
+    }
+    let metadata = connection
+        .prepare("SELECT key, value FROM metadata")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<HashMap<String, String>, _>>()
+        });
+    match metadata {
+        Ok(metadata) => Ok(metadata),
+        Err(_e) => Err(SsapError::InvalidVersion),
+    }
+}
+
+/// Write the header to the metadata table
+fn write_header(
+    transaction: &Transaction,
+    header: &Header,
+) -> Result<(), rusqlite::Error> {
+    let mut metadata = vec![
+        ("schema", SCHEMA_VERSION.to_owned()),
+        ("version", header.version.clone()),
+    ];
+    match &header.kdf {
+        Some(kdf) => metadata.push(("kdf", kdf.to_header())),
+        None => {
+            transaction
+                .execute("DELETE FROM metadata WHERE key = 'kdf'", [])?;
+        }
+    }
+    for (key, value) in metadata {
+        transaction.execute(
+            "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+    }
+    Ok(())
+}
+
+/// Add the versions to the history and apply the changes in a single
+/// transaction, moving the replaced and removed versions to the history.
+/// New passwords are added after the others.
+fn update(
+    connection: &mut Connection,
+    header: &Header,
+    changes: &[Change],
+    history: &[Version],
+) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    let now = unix_time() as i64;
+    write_header(&transaction, header)?;
+
+    for (name, replaced, ciphertext) in history {
+        transaction.execute(
+            "INSERT INTO history (name, ciphertext, replaced) \
+             VALUES (?1, ?2, ?3)",
+            params![name, ciphertext, *replaced as i64],
+        )?;
+    }
+
+    for (name, ciphertext) in changes {
+        let old: Option<Vec<u8>> = transaction
+            .query_row(
+                "SELECT ciphertext FROM entries WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if old.is_some() && old.as_ref() != ciphertext.as_ref() {
+            transaction.execute(
+                "INSERT INTO history (name, ciphertext, replaced) \
+                 VALUES (?1, ?2, ?3)",
+                params![name, old, now],
+            )?;
+        }
+        match (old.is_some(), ciphertext) {
+            (true, Some(ciphertext)) => transaction.execute(
+                "UPDATE entries SET ciphertext = ?2, modified = ?3 \
+                 WHERE name = ?1",
+                params![name, ciphertext, now],
+            )?,
+            (false, Some(ciphertext)) => transaction.execute(
+                "INSERT INTO entries (name, position, ciphertext, modified) \
+                 SELECT ?1, COALESCE(MAX(position) + 1, 0), ?2, ?3 \
+                 FROM entries",
+                params![name, ciphertext, now],
+            )?,
+            (_, None) => transaction
+                .execute("DELETE FROM entries WHERE name = ?1", [name])?,
+        };
+    }
+    transaction.commit()
+}
+
+/// Write the contents in a transaction, moving the versions of the
+/// passwords that changed or disappeared to the history
+fn write(
+    connection: &mut Connection,
+    contents: &Contents,
+) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    let now = unix_time() as i64;
+    write_header(&transaction, &contents.header)?;
+
+    let old: HashMap<String, Vec<u8>> = transaction
+        .prepare("SELECT name, ciphertext FROM entries")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    for (name, ciphertext) in &old {
+        let kept = contents.entries.iter().find(|(n, _)| n == name);
+        if kept.is_some_and(|(_, c)| c == ciphertext) {
+            continue;
+        }
+        transaction.execute(
+            "INSERT INTO history (name, ciphertext, replaced) \
+             VALUES (?1, ?2, ?3)",
+            params![name, ciphertext, now],
+        )?;
+        if kept.is_none() {
+            transaction
+                .execute("DELETE FROM entries WHERE name = ?1", [name])?;
+        }
+    }
+    for (position, (name, ciphertext)) in contents.entries.iter().enumerate() {
+        let position = position as i64;
+        if old.get(name) == Some(ciphertext) {
+            transaction.execute(
+                "UPDATE entries SET position = ?2 WHERE name = ?1",
+                params![name, position],
+            )?;
+        } else {
+            transaction.execute(
+                "INSERT OR REPLACE INTO entries \
+                 (name, position, ciphertext, modified) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![name, position, ciphertext, now],
+            )?;
+        }
+    }
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite() {
+        let path = std::env::temp_dir()
+            .join(format!("ssap-sqlite-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut storage = SqliteStorage::new(&path);
+        assert_eq!(storage.load().unwrap(), None);
+
+        let mut contents = Contents {
+            header: Header::parse("1.0.0 pbkdf2-sha256:1000:00112233").unwrap(),
+            entries: vec![
+                ("mail".to_owned(), vec![1, 2, 3]),
+                ("work/a: b".to_owned(), vec![4, 5]),
+            ],
+        };
+        storage.save(&contents).unwrap();
+        assert_eq!(storage.load().unwrap(), Some(contents.clone()));
+        assert!(fs::read(&path).unwrap().starts_with(SQLITE_MAGIC));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        contents.entries[1].1 = vec![6];
+        contents.entries.remove(0);
+        contents.entries.push(("new".to_owned(), vec![7]));
+        storage.save(&contents).unwrap();
+        assert_eq!(storage.load().unwrap(), Some(contents.clone()));
+        let history = storage.history("work/a: b").unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].1, vec![4, 5]);
+        assert_eq!(storage.history("mail").unwrap()[0].1, vec![1, 2, 3]);
+        assert!(storage.history("new").unwrap().is_empty());
+
+        let header = contents.header.clone();
+        let changes = vec![
+            ("work/a: b".to_owned(), None),
+            ("new".to_owned(), Some(vec![8])),
+            ("last".to_owned(), Some(vec![9])),
+        ];
+        storage.update(&header, &changes, &[]).unwrap();
+        let (_, names) = storage.load_names().unwrap().unwrap();
+        assert_eq!(names, vec!["new", "last"]);
+        assert_eq!(storage.entry("new").unwrap(), Some(vec![8]));
+        assert_eq!(storage.entry("work/a: b").unwrap(), None);
+        assert_eq!(storage.history("new").unwrap()[0].1, vec![7]);
+        assert_eq!(storage.history("work/a: b").unwrap().len(), 2);
+        let _ = fs::remove_file(&path);
+    }
+}
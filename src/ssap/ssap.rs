@@ -24,8 +24,19 @@ all
 *
 */
 
+use crate::ssap::backup::Retention;
+use crate::ssap::config::default_vault_path;
+use crate::ssap::crypto::DEFAULT_KDF_COST;
+use crate::ssap::otp::OtpAlgorithm;
+use crate::ssap::storage::StorageKind;
 use std::path::Path;
 
+/// Hidden command used to spawn the process clearing the clipboard
+pub const CLEAR_CLIPBOARD_COMMAND: &str = "__clear-clipboard";
+
+/// Hidden command run by git to merge the vault
+pub const MERGE_DRIVER_COMMAND: &str = "__merge-driver";
+
 /// List of supported encryption algorithms
 #[derive(Debug, Clone)]
 pub enum Encryption {
@@ -33,39 +44,317 @@ pub enum Encryption {
     Aes_256_cbc,
 }
 
+impl Encryption {
+    pub fn from_name(name: &str) -> Option<Encryption> {
+        match name {
+            "aes_128_cbc" => Some(Encryption::Aes_128_cbc),
+            "aes_256_cbc" => Some(Encryption::Aes_256_cbc),
+            _ => None,
+        }
+    }
+}
+
+/// List of supported clipboard backends
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipboardBackend {
+    Auto,
+    Osc52,
+    X11,
+    Wayland,
+}
+
+impl ClipboardBackend {
+    /// Parse a backend name as given on the command line
+    pub fn from_name(name: &str) -> Option<ClipboardBackend> {
+        match name {
+            "auto" => Some(ClipboardBackend::Auto),
+            "osc52" => Some(ClipboardBackend::Osc52),
+            "x11" => Some(ClipboardBackend::X11),
+            "wayland" => Some(ClipboardBackend::Wayland),
+            _ => None,
+        }
+    }
+
+    /// Name of the backend as given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClipboardBackend::Auto => "auto",
+            ClipboardBackend::Osc52 => "osc52",
+            ClipboardBackend::X11 => "x11",
+            ClipboardBackend::Wayland => "wayland",
+        }
+    }
+}
+
+/// List of supported output formats for `get` and `list`
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Raw,
+    Json,
+    Env,
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Parse a format name as given on the command line
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "raw" => Some(OutputFormat::Raw),
+            "json" => Some(OutputFormat::Json),
+            "env" => Some(OutputFormat::Env),
+            "yaml" => Some(OutputFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// List of supported formats for `import`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    Kdbx,
+    Bitwarden,
+    Csv,
+    Pass,
+    SsapJson,
+}
+
+impl ImportFormat {
+    /// Parse a format name as given on the command line
+    pub fn from_name(name: &str) -> Option<ImportFormat> {
+        match name {
+            "kdbx" => Some(ImportFormat::Kdbx),
+            "bitwarden" => Some(ImportFormat::Bitwarden),
+            "csv" => Some(ImportFormat::Csv),
+            "pass" => Some(ImportFormat::Pass),
+            "ssap-json" => Some(ImportFormat::SsapJson),
+            _ => None,
+        }
+    }
+}
+
+/// List of supported formats for `export`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Kdbx,
+    SsapJson,
+}
+
+impl ExportFormat {
+    /// Parse a format name as given on the command line
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "kdbx" => Some(ExportFormat::Kdbx),
+            "ssap-json" => Some(ExportFormat::SsapJson),
+            _ => None,
+        }
+    }
+}
+
+/// The subcommands of ssap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Help,
+    New,
+    Get,
+    Delete,
+    List,
+    Mv,
+    Find,
+    Edit,
+    Otp,
+    Exec,
+    Inject,
+    Completions,
+    Vault,
+    Convert,
+    Import,
+    Export,
+    Backup,
+    Git,
+    Merge,
+    ClearClipboard,
+    MergeDriver,
+}
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "help" => Some(Command::Help),
+            "new" => Some(Command::New),
+            "get" => Some(Command::Get),
+            "delete" => Some(Command::Delete),
+            "list" => Some(Command::List),
+            "mv" => Some(Command::Mv),
+            "find" => Some(Command::Find),
+            "edit" => Some(Command::Edit),
+            "otp" => Some(Command::Otp),
+            "exec" => Some(Command::Exec),
+            "inject" => Some(Command::Inject),
+            "completions" => Some(Command::Completions),
+            "vault" => Some(Command::Vault),
+            "convert" => Some(Command::Convert),
+            "import" => Some(Command::Import),
+            "export" => Some(Command::Export),
+            "backup" => Some(Command::Backup),
+            "git" => Some(Command::Git),
+            "merge" => Some(Command::Merge),
+            CLEAR_CLIPBOARD_COMMAND => Some(Command::ClearClipboard),
+            MERGE_DRIVER_COMMAND => Some(Command::MergeDriver),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Help => "help",
+            Command::New => "new",
+            Command::Get => "get",
+            Command::Delete => "delete",
+            Command::List => "list",
+            Command::Mv => "mv",
+            Command::Find => "find",
+            Command::Edit => "edit",
+            Command::Otp => "otp",
+            Command::Exec => "exec",
+            Command::Inject => "inject",
+            Command::Completions => "completions",
+            Command::Vault => "vault",
+            Command::Convert => "convert",
+            Command::Import => "import",
+            Command::Export => "export",
+            Command::Backup => "backup",
+            Command::Git => "git",
+            Command::Merge => "merge",
+            Command::ClearClipboard => CLEAR_CLIPBOARD_COMMAND,
+            Command::MergeDriver => MERGE_DRIVER_COMMAND,
+        }
+    }
+
+    /// Whether the command may change the vault, so that a backup is
+    /// taken before running it
+    pub fn changes_vault(&self) -> bool {
+        matches!(
+            self,
+            Command::New
+                | Command::Delete
+                | Command::Mv
+                | Command::Edit
+                | Command::Otp
+                | Command::Import
+        )
+    }
+}
+
+/// Characters of the generated passwords
+pub const DEFAULT_CHARSET: &str = "abcdefghijklmnopqrstuvwxyz\
+                                   ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                   0123456789\
+                                   !@#$%^&()={}[]?";
+
 /// Struct holding SSAP settings
 #[derive(Debug, Clone)]
 pub struct Ssap {
-    pub version: Box<String>,
+    pub version: String,
+    pub command: Option<Command>,
     pub show_help: bool,
-    pub create_new: bool,
-    pub get_passwd: bool,
-    pub delete_passwd: bool,
-    pub list: bool,
+    pub regex: bool,
+    pub unlock: bool,
     pub copy_to_clipboard: bool,
+    pub clip_timeout: u64,
+    pub clipboard_backend: ClipboardBackend,
     pub silent: bool,
+    pub format: OutputFormat,
+    pub import_format: Option<ImportFormat>,
+    pub export_format: Option<ExportFormat>,
+    pub csv_map: Option<Vec<(String, String)>>,
+    pub action: Option<String>,
     pub input: Option<String>,
+    pub destination: Option<String>,
+    /// Positional arguments after the destination
+    pub arguments: Vec<String>,
     pub password_len: usize,
+    pub charset: String,
     pub path: Box<Path>,
+    pub vaults: Vec<(String, Box<Path>)>,
+    pub all_vaults: bool,
     pub encryption: Encryption,
+    pub storage: StorageKind,
+    pub kdf_cost: u32,
+    pub fields: Vec<(String, String)>,
+    pub tags: Vec<String>,
+    pub untags: Vec<String>,
+    pub query: Option<String>,
+    pub otp_secret: Option<String>,
+    pub otp_algorithm: Option<OtpAlgorithm>,
+    pub otp_digits: Option<u32>,
+    pub otp_period: Option<u64>,
+    pub otp_counter: Option<u64>,
+    pub env_vars: Vec<(String, String)>,
+    pub mask_output: bool,
+    pub exec_command: Vec<String>,
+    pub template: Option<Box<Path>>,
+    pub output: Option<Box<Path>>,
+    pub force: bool,
+    pub encrypt: bool,
+    pub plaintext: bool,
+    pub backup: bool,
+    pub backup_retention: Retention,
+    pub git_redact: bool,
+    pub keep_both: bool,
 }
 
 impl Ssap {
     /// Create a new Ssap struct
     pub fn new() -> Self {
         Ssap {
-            version: Box::new(env!("CARGO_PKG_VERSION").to_owned()),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            command: None,
             show_help: false,
-            create_new: false,
-            get_passwd: false,
-            delete_passwd: false,
-            list: false,
+            regex: false,
+            unlock: false,
             copy_to_clipboard: false,
+            clip_timeout: 45,
+            clipboard_backend: ClipboardBackend::Auto,
             silent: false,
+            format: OutputFormat::Text,
+            import_format: None,
+            export_format: None,
+            csv_map: None,
+            action: None,
             input: None,
+            destination: None,
+            arguments: Vec::new(),
             password_len: 30,
-            path: Path::new("./.vault.ssap").into(),
+            charset: DEFAULT_CHARSET.to_owned(),
+            path: default_vault_path().into(),
+            vaults: Vec::new(),
+            all_vaults: false,
             encryption: Encryption::Aes_256_cbc,
+            storage: StorageKind::Text,
+            kdf_cost: DEFAULT_KDF_COST,
+            fields: Vec::new(),
+            tags: Vec::new(),
+            untags: Vec::new(),
+            query: None,
+            otp_secret: None,
+            otp_algorithm: None,
+            otp_digits: None,
+            otp_period: None,
+            otp_counter: None,
+            env_vars: Vec::new(),
+            mask_output: false,
+            exec_command: Vec::new(),
+            template: None,
+            output: None,
+            force: false,
+            encrypt: false,
+            plaintext: false,
+            backup: true,
+            backup_retention: Retention::default(),
+            git_redact: false,
+            keep_both: false,
         }
     }
 }
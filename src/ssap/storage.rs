@@ -0,0 +1,704 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::crypto::Kdf;
+use crate::ssap::error::SsapError;
+use crate::ssap::name::{
+    format_line, split_line, EntryName, NAME_ENCODING_VERSION,
+};
+use crate::ssap::sqlite::{SqliteStorage, SQLITE_MAGIC};
+use regex::Regex;
+use std::fmt::Debug;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Extension of the files holding a password in a directory vault
+const ENTRY_EXTENSION: &str = "ssap";
+
+/// File holding the header of a directory vault
+const HEADER_FILE: &str = ".header";
+
+/// The first line of a vault: the version of ssap that created it and the
+/// parameters used to derive its key. Vaults created before key derivation
+/// have no `kdf` and use the raw password as key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub version: String,
+    pub kdf: Option<Kdf>,
+}
+
+impl Header {
+    /// Parse a header line: the version, optionally followed by the key
+    /// derivation parameters
+    pub fn parse(line: &str) -> Result<Header, SsapError> {
+        let (version, kdf) = match line.split_once(' ') {
+            Some((version, kdf)) => (version, Some(Kdf::from_header(kdf)?)),
+            None => (line, None),
+        };
+        let re = Regex::new(r"[0-9]+\.[0-9]+\.[0-9]+").unwrap();
+        if !re.is_match(version) {
+            return Err(SsapError::InvalidVersion);
+        }
+        Ok(Header {
+            version: version.to_owned(),
+            kdf,
+        })
+    }
+
+    /// Whether a vault file with this header encodes names, see
+    /// `EntryName`
+    pub fn encodes_names(&self) -> bool {
+        version_numbers(&self.version) >= version_numbers(NAME_ENCODING_VERSION)
+    }
+
+    /// Format the header as a line, without the line break
+    pub fn to_line(&self) -> String {
+        match &self.kdf {
+            Some(kdf) => format!("{} {}", self.version, kdf.to_header()),
+            None => self.version.clone(),
+        }
+    }
+}
+
+/// The numbers of a version, to compare it with others
+fn version_numbers(version: &str) -> Vec<u64> {
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().unwrap_or(u64::MAX))
+        .collect()
+}
+
+/// Everything a backend stores: the header and the name and ciphertext of
+/// every password, in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contents {
+    pub header: Header,
+    pub entries: Vec<(String, Vec<u8>)>,
+}
+
+/// A password written or removed since the vault was read: its name and
+/// its new ciphertext, `None` if it was removed
+pub type Change = (String, Option<Vec<u8>>);
+
+/// A previous version of a password: its name, the unix time it was
+/// replaced at and its ciphertext
+pub type Version = (String, u64, Vec<u8>);
+
+/// Where a vault is stored.
+///
+/// A backend only moves encrypted passwords around: decryption and the
+/// rules on names are left to `Vault`, so a new backend only has to
+/// implement `load` and `save`. Backends that can read and write a single
+/// password cheaply also implement `indexed` and the methods it enables.
+pub trait VaultStorage: Debug {
+    /// Read the vault
+    ///
+    /// # Returns
+    /// * The contents of the vault, `None` if it does not exist yet, or a
+    ///   SsapError if it cannot be read
+    fn load(&self) -> Result<Option<Contents>, SsapError>;
+
+    /// Replace the contents of the vault
+    ///
+    /// # Arguments
+    /// * `contents` - The new contents of the vault
+    /// # Returns
+    /// * A SsapError if the vault cannot be written
+    fn save(&mut self, contents: &Contents) -> Result<(), SsapError>;
+
+    /// Whether `load_names`, `entry` and `update` are cheaper than reading
+    /// and writing the whole vault, so that `Vault` uses them instead
+    fn indexed(&self) -> bool {
+        false
+    }
+
+    /// Read the header and the names of the passwords, in order, without
+    /// their ciphertext
+    ///
+    /// # Returns
+    /// * The header and the names, `None` if the vault does not exist yet,
+    ///   or a SsapError if it cannot be read
+    fn load_names(&self) -> Result<Option<(Header, Vec<String>)>, SsapError> {
+        Ok(self.load()?.map(|contents| {
+            let names = contents.entries.into_iter().map(|(name, _)| name);
+            (contents.header, names.collect())
+        }))
+    }
+
+    /// Read the ciphertext of a single password
+    ///
+    /// # Arguments
+    /// * `name` - The name of the password
+    /// # Returns
+    /// * The ciphertext, `None` if there is no such password, or a
+    ///   SsapError if the vault cannot be read
+    fn entry(&self, name: &str) -> Result<Option<Vec<u8>>, SsapError> {
+        let entries = self.load()?.map(|contents| contents.entries);
+        let entry = entries
+            .unwrap_or_default()
+            .into_iter()
+            .find(|e| e.0 == name);
+        Ok(entry.map(|(_, ciphertext)| ciphertext))
+    }
+
+    /// Write only the passwords that changed, and add previous versions of
+    /// passwords to their history, as when importing an archive. New
+    /// passwords are added after the others. Backends without history
+    /// ignore the versions.
+    ///
+    /// # Arguments
+    /// * `header` - The header of the vault
+    /// * `changes` - The changes, in the order they were made
+    /// * `history` - The name of a password, the unix time a version of it
+    ///   was replaced at and the encrypted version
+    /// # Returns
+    /// * A SsapError if the vault cannot be written
+    fn update(
+        &mut self,
+        header: &Header,
+        changes: &[Change],
+        _history: &[Version],
+    ) -> Result<(), SsapError> {
+        let mut entries = match self.load()? {
+            Some(contents) => contents.entries,
+            None => Vec::new(),
+        };
+        for (name, ciphertext) in changes {
+            let position = entries.iter().position(|(n, _)| n == name);
+            match (position, ciphertext) {
+                (Some(i), Some(c)) => entries[i].1 = c.clone(),
+                (Some(i), None) => {
+                    entries.remove(i);
+                }
+                (None, Some(c)) => entries.push((name.clone(), c.clone())),
+                (None, None) => {}
+            }
+        }
+        let header = header.clone();
+        self.save(&Contents { header, entries })
+    }
+
+    /// Read the previous versions of a password, oldest first, with the
+    /// unix time they were replaced at. Backends without history have
+    /// none.
+    fn history(&self, _name: &str) -> Result<Vec<(u64, Vec<u8>)>, SsapError> {
+        Ok(Vec::new())
+    }
+}
+
+/// The backends a new vault can be created with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageKind {
+    Text,
+    Sqlite,
+    Directory,
+}
+
+impl StorageKind {
+    pub fn from_name(name: &str) -> Option<StorageKind> {
+        match name {
+            "text" => Some(StorageKind::Text),
+            "sqlite" => Some(StorageKind::Sqlite),
+            "directory" => Some(StorageKind::Directory),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageKind::Text => "text",
+            StorageKind::Sqlite => "sqlite",
+            StorageKind::Directory => "directory",
+        }
+    }
+}
+
+/// Select the backend of the existing vault at `path`: a directory holds a
+/// vault with a file per password, an SQLite database a vault stored in
+/// tables, anything else is a vault file.
+pub fn open_storage(path: &Path) -> Box<dyn VaultStorage> {
+    new_storage(path, storage_kind(path).unwrap_or(StorageKind::Text))
+}
+
+/// The backend of the vault at `path`, or `None` if there is no vault
+pub fn storage_kind(path: &Path) -> Option<StorageKind> {
+    if path.is_dir() {
+        return Some(StorageKind::Directory);
+    }
+    let mut magic = [0; SQLITE_MAGIC.len()];
+    let file = fs::File::open(path);
+    if file.is_err() {
+        return None;
+    }
+    let mut file = file.unwrap();
+    match file.read_exact(&mut magic) {
+        Ok(()) if magic == SQLITE_MAGIC => Some(StorageKind::Sqlite),
+        Ok(()) => Some(StorageKind::Text),
+        // Shorter than the magic, but may hold a version
+        Err(_e) => match fs::metadata(path) {
+            Ok(metadata) if metadata.len() > 0 => Some(StorageKind::Text),
+            _ => None,
+        },
+    }
+}
+
+/// Create the backend of a new vault at `path`
+pub fn new_storage(path: &Path, kind: StorageKind) -> Box<dyn VaultStorage> {
+    match kind {
+        StorageKind::Text => Box::new(TextFileStorage::new(path)),
+        StorageKind::Sqlite => Box::new(SqliteStorage::new(path)),
+        StorageKind::Directory => Box::new(DirectoryStorage::new(path)),
+    }
+}
+
+/// The vault file format: the header on the first line, then a
+/// `name: ciphertext` line per password, with the ciphertext hex encoded.
+#[derive(Debug, Clone)]
+pub struct TextFileStorage {
+    path: PathBuf,
+}
+
+impl TextFileStorage {
+    pub fn new(path: &Path) -> Self {
+        TextFileStorage {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl VaultStorage for TextFileStorage {
+    fn load(&self) -> Result<Option<Contents>, SsapError> {
+        let file = match fs::read_to_string(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(_e) => return Err(SsapError::InvalidPath),
+        };
+        let mut lines = file.lines();
+        let header = match lines.next() {
+            Some(line) => Header::parse(line)?,
+            None => return Ok(None),
+        };
+
+        let encoded = header.encodes_names();
+        let mut entries = Vec::new();
+        for line in lines.filter(|line| !line.is_empty()) {
+            // A line that cannot be read would be dropped by the next save
+            let entry = split_line(line, encoded).and_then(|(name, passwd)| {
+                Some((name, hex::decode(passwd).ok()?))
+            });
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => return Err(SsapError::InvalidCiphertext),
+            }
+        }
+        Ok(Some(Contents { header, entries }))
+    }
+
+    fn save(&mut self, contents: &Contents) -> Result<(), SsapError> {
+        // Names are always written encoded, which older versions of the
+        // vault file did not do
+        let mut header = contents.header.clone();
+        if !header.encodes_names() {
+            header.version = NAME_ENCODING_VERSION.to_owned();
+        }
+        let mut file = format!("{}\n", header.to_line());
+        for (name, passwd) in &contents.entries {
+            file.push_str(&format_line(name, &hex::encode(passwd)));
+            file.push('\n');
+        }
+        // The default vault lives in a directory that may not exist yet
+        if let Some(parent) = self.path.parent() {
+            if fs::create_dir_all(parent).is_err() {
+                return Err(SsapError::InvalidPath);
+            }
+        }
+        write_atomic(&self.path, file.as_bytes())
+    }
+}
+
+/// A vault kept in memory, for tests and tools that do not want to touch
+/// the disk. Clones share the same contents, so a vault can be saved and
+/// opened again through a clone.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    contents: Arc<Mutex<Option<Contents>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+}
+
+impl VaultStorage for MemoryStorage {
+    fn load(&self) -> Result<Option<Contents>, SsapError> {
+        Ok(self.contents.lock().unwrap().clone())
+    }
+
+    fn save(&mut self, contents: &Contents) -> Result<(), SsapError> {
+        *self.contents.lock().unwrap() = Some(contents.clone());
+        Ok(())
+    }
+}
+
+/// A vault stored as a directory with a file per password, so that tools
+/// like rsync or git see which passwords changed. Folders of names are
+/// directories: `work/github` is stored in `work/github.ssap`, which holds
+/// the hex encoded ciphertext. The header is kept in `.header`.
+#[derive(Debug, Clone)]
+pub struct DirectoryStorage {
+    path: PathBuf,
+}
+
+impl DirectoryStorage {
+    pub fn new(path: &Path) -> Self {
+        DirectoryStorage {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Path of the file holding the password `name`
+    fn entry_path(&self, name: &str) -> Result<PathBuf, SsapError> {
+        // Names read from other vaults are not validated yet, and any `..`
+        // would escape the directory
+        let name = EntryName::new(name)?;
+        let mut path = self.path.clone();
+        for part in name.as_str().split('/') {
+            path.push(encode_part(part));
+        }
+        path.set_extension(ENTRY_EXTENSION);
+        Ok(path)
+    }
+
+    /// Collect the names of the passwords under `dir`, prefixed by `prefix`
+    fn walk(
+        &self,
+        dir: &Path,
+        prefix: &str,
+        names: &mut Vec<(String, PathBuf)>,
+    ) -> Result<(), SsapError> {
+        let entries = fs::read_dir(dir);
+        if entries.is_err() {
+            return Err(SsapError::InvalidPath);
+        }
+        for entry in entries.unwrap().flatten() {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if path.is_dir() {
+                if file_name != ".git" {
+                    let name = EntryName::decode(&file_name);
+                    let prefix = format!("{}{}/", prefix, name.as_str());
+                    self.walk(&path, &prefix, names)?;
+                }
+                continue;
+            }
+            let stem = file_name.strip_suffix(&format!(".{}", ENTRY_EXTENSION));
+            if let Some(stem) = stem.filter(|stem| !stem.is_empty()) {
+                let name = EntryName::decode(stem);
+                names.push((format!("{}{}", prefix, name.as_str()), path));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VaultStorage for DirectoryStorage {
+    fn load(&self) -> Result<Option<Contents>, SsapError> {
+        let mut files = Vec::new();
+        if self.path.is_dir() {
+            self.walk(&self.path.clone(), "", &mut files)?;
+        }
+        let header = match fs::read_to_string(self.path.join(HEADER_FILE)) {
+            Ok(header) => Header::parse(header.trim_end())?,
+            Err(_e) if files.is_empty() => return Ok(None),
+            Err(_e) => return Err(SsapError::InvalidVersion),
+        };
+
+        // The order of a directory is arbitrary, names are sorted instead
+        files.sort();
+        let mut entries = Vec::new();
+        for (name, path) in files {
+            let ciphertext = fs::read_to_string(path)
+                .ok()
+                .and_then(|passwd| hex::decode(passwd.trim_end()).ok());
+            if ciphertext.is_none() {
+                return Err(SsapError::InvalidCiphertext);
+            }
+            entries.push((name, ciphertext.unwrap()));
+        }
+        Ok(Some(Contents { header, entries }))
+    }
+
+    fn save(&mut self, contents: &Contents) -> Result<(), SsapError> {
+        if fs::create_dir_all(&self.path).is_err() {
+            return Err(SsapError::InvalidPath);
+        }
+        let header = format!("{}\n", contents.header.to_line());
+        write_if_changed(&self.path.join(HEADER_FILE), header.as_bytes())?;
+
+        let mut kept = Vec::new();
+        for (name, passwd) in &contents.entries {
+            let path = self.entry_path(name)?;
+            if let Some(parent) = path.parent() {
+                if fs::create_dir_all(parent).is_err() {
+                    return Err(SsapError::InvalidPath);
+                }
+            }
+            let passwd = format!("{}\n", hex::encode(passwd));
+            write_if_changed(&path, passwd.as_bytes())?;
+            kept.push(path);
+        }
+
+        let mut files = Vec::new();
+        self.walk(&self.path.clone(), "", &mut files)?;
+        for (_, path) in files {
+            if !kept.contains(&path) {
+                if fs::remove_file(&path).is_err() {
+                    return Err(SsapError::InvalidWrite);
+                }
+                remove_empty_parents(&path, &self.path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encode a folder or file name of a directory vault. Besides the vault
+/// file encoding, a leading `.` is encoded so that no password is hidden or
+/// taken for the header.
+fn encode_part(part: &str) -> String {
+    let encoded = crate::ssap::name::encode(part);
+    match encoded.strip_prefix('.') {
+        Some(rest) => format!("%2E{}", rest),
+        None => encoded,
+    }
+}
+
+/// Remove the directories left empty between `path` and `root`
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(parent) = dir.filter(|d| *d != root) {
+        if fs::remove_dir(parent).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+}
+
+/// Write a file unless it already has `content`, leaving the modification
+/// time of unchanged passwords alone
+fn write_if_changed(path: &Path, content: &[u8]) -> Result<(), SsapError> {
+    match fs::read(path) {
+        Ok(old) if old == content => Ok(()),
+        _ => write_atomic(path, content),
+    }
+}
+
+/// Write a file readable only by its owner. The content is written to a
+/// new temporary file next to `path`, synced and then renamed over `path`,
+/// so a failure or a crash never leaves a half written file behind.
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `content` - The new content of the file
+/// # Returns
+/// * A SsapError if the file cannot be written
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), SsapError> {
+    // Replace the target of a symlink, not the symlink itself
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let (tmp, mut file) = create_temporary(path)?;
+    let written = file.write_all(content).and_then(|_| file.sync_all());
+    if written.is_err() || fs::rename(&tmp, path).is_err() {
+        let _ = fs::remove_file(&tmp);
+        return Err(SsapError::InvalidWrite);
+    }
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let dir = parent.as_os_str().is_empty().then(|| Path::new("."));
+        if let Ok(dir) = fs::File::open(dir.unwrap_or(parent)) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Create a temporary file next to `path`, with a name no other process
+/// uses and readable only by its owner
+fn create_temporary(path: &Path) -> Result<(PathBuf, fs::File), SsapError> {
+    for _ in 0..16 {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(format!(
+            ".{}-{:08x}.ssap-tmp",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        let tmp = PathBuf::from(tmp);
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        match options.open(&tmp) {
+            Ok(file) => return Ok((tmp, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(_e) => return Err(SsapError::InvalidPath),
+        }
+    }
+    Err(SsapError::InvalidPath)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(test: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "ssap-storage-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn contents() -> Contents {
+        Contents {
+            header: Header::parse("1.1.0 pbkdf2-sha256:1000:00112233").unwrap(),
+            entries: vec![
+                ("mail".to_owned(), vec![1, 2, 3]),
+                ("work/a: b".to_owned(), vec![4, 5]),
+                ("work/.env".to_owned(), vec![6]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let header = Header::parse("1.0.0").unwrap();
+        assert_eq!(header.version, "1.0.0");
+        assert!(header.kdf.is_none());
+        assert_eq!(header.to_line(), "1.0.0");
+        let line = "1.0.0 pbkdf2-sha256:1000:00112233";
+        assert_eq!(Header::parse(line).unwrap().to_line(), line);
+        assert!(Header::parse("vault").is_err());
+        assert!(Header::parse("1.0.0 scrypt").is_err());
+    }
+
+    #[test]
+    fn test_text_file() {
+        let path = temp_path("text");
+        let mut storage = TextFileStorage::new(&path);
+        assert_eq!(storage.load().unwrap(), None);
+        storage.save(&contents()).unwrap();
+        assert_eq!(storage.load().unwrap(), Some(contents()));
+        let file = fs::read_to_string(&path).unwrap();
+        assert!(file.contains("work/a%3A b: 0405\n"));
+        assert_eq!(storage_kind(&path), Some(StorageKind::Text));
+
+        // Older vault files hold names as they are, and are upgraded
+        fs::write(&path, "1.0.0\nwifi%20home: 00ff\n").unwrap();
+        let legacy = storage.load().unwrap().unwrap();
+        assert_eq!(legacy.entries[0].0, "wifi%20home");
+        storage.save(&legacy).unwrap();
+        let file = fs::read_to_string(&path).unwrap();
+        assert_eq!(file, "1.1.0\nwifi%2520home: 00ff\n");
+        let upgraded = storage.load().unwrap().unwrap();
+        assert_eq!(upgraded.entries, legacy.entries);
+
+        // A line that cannot be read is an error, not a lost password
+        fs::write(&path, "1.1.0\na: 00ff\ngarbage\n").unwrap();
+        assert!(matches!(storage.load(), Err(SsapError::InvalidCiphertext)));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_symlink() {
+        let target = temp_path("target");
+        let link = temp_path("link");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_atomic(&link, b"new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        let _ = fs::remove_file(&link);
+        let _ = fs::remove_file(&target);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_path("mode");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_atomic(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(storage.load().unwrap(), None);
+        storage.save(&contents()).unwrap();
+        assert_eq!(storage.clone().load().unwrap(), Some(contents()));
+    }
+
+    #[test]
+    fn test_directory() {
+        let path = temp_path("directory");
+        let mut storage = DirectoryStorage::new(&path);
+        assert_eq!(storage.load().unwrap(), None);
+        storage.save(&contents()).unwrap();
+        assert!(path.join("work").join("%2Eenv.ssap").is_file());
+
+        let mut sorted = contents();
+        sorted.entries.sort();
+        assert_eq!(storage.load().unwrap(), Some(sorted.clone()));
+        assert_eq!(storage_kind(&path), Some(StorageKind::Directory));
+
+        sorted.entries.retain(|(name, _)| name == "mail");
+        storage.save(&sorted).unwrap();
+        assert_eq!(storage.load().unwrap(), Some(sorted.clone()));
+        assert!(!path.join("work").exists());
+
+        sorted.entries.push(("../escape".to_owned(), vec![7]));
+        assert!(storage.save(&sorted).is_err());
+        let _ = fs::remove_dir_all(&path);
+    }
+}
@@ -0,0 +1,643 @@
+/*
+* MIT License
+*
+* Copyright (c) 2024 Giovanni Santini
+*
+* Permission is hereby granted, free of charge, to any person obtaining a copy
+* of this software and associated documentation files (the "Software"), to deal
+* in the Software without restriction, including without limitation the rights
+* to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+* copies of the Software, and to permit persons to whom the Software is
+* furnished to do so, subject to the following conditions:
+*
+* The above copyright notice and this permission notice shall be included in
+all
+* copies or substantial portions of the Software.
+*
+* THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+* IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+* FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+* AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+* LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+* OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+* SOFTWARE.
+*
+*/
+
+use crate::ssap::crypto::{decrypt_password, encrypt_password, Kdf};
+use crate::ssap::entry::Entry;
+use crate::ssap::error::SsapError;
+use crate::ssap::folder::normalize;
+use crate::ssap::name::EntryName;
+use crate::ssap::ssap::Encryption;
+use crate::ssap::storage::{
+    new_storage, open_storage, storage_kind, Change, Contents, Header,
+    StorageKind, VaultStorage, Version,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The header of a vault and every password, with its ciphertext if it has
+/// been read
+type Stored = (Header, Vec<(String, Option<Vec<u8>>)>);
+
+/// A decrypted password together with its name in the vault
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub entry: Entry,
+}
+
+/// A vault of passwords.
+///
+/// A vault holds the version of ssap that created it, the parameters used
+/// to derive its key and the name and encrypted entry of every password.
+/// Passwords are kept encrypted in memory and decrypted one at a time by
+/// `get`, so a vault can be listed and reorganized without being unlocked.
+/// Changes are written to its `VaultStorage` only by `save`.
+///
+/// With an indexed storage, such as SQLite, only the names are read when
+/// the vault is opened: a password is read when it is needed, and `save`
+/// only writes the passwords that changed.
+///
+/// ```no_run
+/// use ssap::{Entry, Vault};
+/// use ssap::ssap::ssap::Encryption;
+///
+/// let mut vault = Vault::open("vault.ssap", Encryption::Aes_256_cbc)?;
+/// vault.unlock("correct horse battery staple")?;
+/// vault.insert("work/github", Entry::new("hunter2".to_owned()))?;
+/// vault.save()?;
+/// println!("{}", vault.get("work/github")?.entry.password);
+/// # Ok::<(), ssap::SsapError>(())
+/// ```
+#[derive(Debug)]
+pub struct Vault {
+    storage: Box<dyn VaultStorage>,
+    header: Header,
+    /// Every password, with its ciphertext if it has been read
+    entries: Vec<(String, Option<Vec<u8>>)>,
+    /// The changes not saved yet, for indexed storages
+    changes: Vec<Change>,
+    /// The previous versions to add to the history by `save`
+    history: Vec<Version>,
+    encryption: Encryption,
+    key: Option<Vec<u8>>,
+}
+
+impl Vault {
+    /// Open an existing vault, see `open_storage` for the supported paths
+    ///
+    /// # Arguments
+    /// * `path` - The path of the vault
+    /// * `encryption` - The cipher the passwords are encrypted with
+    /// # Returns
+    /// * The locked vault, or a SsapError if the vault does not exist or
+    ///   cannot be read
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        encryption: Encryption,
+    ) -> Result<Vault, SsapError> {
+        Vault::open_with(open_storage(path.as_ref()), encryption)
+    }
+
+    /// Open an existing vault kept in `storage`
+    pub fn open_with(
+        storage: Box<dyn VaultStorage>,
+        encryption: Encryption,
+    ) -> Result<Vault, SsapError> {
+        match Vault::read(storage.as_ref())? {
+            Some((header, entries)) => {
+                Ok(Vault::new(storage, header, entries, encryption))
+            }
+            None => Err(SsapError::InvalidPath),
+        }
+    }
+
+    /// Create a new, empty vault. Nothing is written until `save`.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the vault
+    /// * `encryption` - The cipher the passwords are encrypted with
+    /// * `kdf_cost` - The PBKDF2 iterations used to derive the key
+    /// # Returns
+    /// * The locked vault, or a SsapError if no salt can be generated
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        encryption: Encryption,
+        kdf_cost: u32,
+    ) -> Result<Vault, SsapError> {
+        Vault::create_with(open_storage(path.as_ref()), encryption, kdf_cost)
+    }
+
+    /// Create a new, empty vault kept in `storage`
+    pub fn create_with(
+        storage: Box<dyn VaultStorage>,
+        encryption: Encryption,
+        kdf_cost: u32,
+    ) -> Result<Vault, SsapError> {
+        let header = Header {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            kdf: Some(Kdf::new(kdf_cost)?),
+        };
+        Ok(Vault::new(storage, header, Vec::new(), encryption))
+    }
+
+    /// Open the vault at `path`, or create it with the `kind` backend if it
+    /// does not exist yet
+    pub fn open_or_create<P: AsRef<Path>>(
+        path: P,
+        encryption: Encryption,
+        kdf_cost: u32,
+        kind: StorageKind,
+    ) -> Result<Vault, SsapError> {
+        let path = path.as_ref();
+        let storage = new_storage(path, storage_kind(path).unwrap_or(kind));
+        match Vault::read(storage.as_ref())? {
+            Some((header, entries)) => {
+                Ok(Vault::new(storage, header, entries, encryption))
+            }
+            None => Vault::create_with(storage, encryption, kdf_cost),
+        }
+    }
+
+    /// Read the header and the passwords of the vault kept in `storage`,
+    /// only their names if it is indexed, or `None` if there is no vault
+    fn read(storage: &dyn VaultStorage) -> Result<Option<Stored>, SsapError> {
+        if storage.indexed() {
+            return Ok(storage.load_names()?.map(|(header, names)| {
+                (header, names.into_iter().map(|n| (n, None)).collect())
+            }));
+        }
+        Ok(storage.load()?.map(|contents| {
+            let entries = contents.entries.into_iter();
+            (
+                contents.header,
+                entries.map(|(n, c)| (n, Some(c))).collect(),
+            )
+        }))
+    }
+
+    fn new(
+        storage: Box<dyn VaultStorage>,
+        header: Header,
+        entries: Vec<(String, Option<Vec<u8>>)>,
+        encryption: Encryption,
+    ) -> Vault {
+        Vault {
+            storage,
+            header,
+            entries,
+            changes: Vec::new(),
+            history: Vec::new(),
+            encryption,
+            key: None,
+        }
+    }
+
+    /// The version of ssap that created the vault
+    pub fn version(&self) -> &str {
+        &self.header.version
+    }
+
+    /// Derive the key of the vault from its password. A wrong password is
+    /// only noticed when a password fails to decrypt.
+    pub fn unlock(&mut self, password: &str) -> Result<(), SsapError> {
+        let key = match &self.header.kdf {
+            Some(kdf) => kdf.derive(password.as_bytes())?,
+            // Vaults created before key derivation use the raw password
+            None => password.as_bytes().to_vec(),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Forget the key of the vault
+    pub fn lock(&mut self) {
+        self.key = None;
+    }
+
+    /// Whether the vault has been unlocked
+    pub fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// The names of the passwords, in the order they were added
+    pub fn list(&self) -> Vec<String> {
+        self.entries.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Whether a password is registered with `name`
+    pub fn contains(&self, name: &str) -> bool {
+        let name = normalize(name);
+        self.entries.iter().any(|(n, _)| *n == name)
+    }
+
+    /// Decrypt a password
+    ///
+    /// # Arguments
+    /// * `name` - The name of the password
+    /// # Returns
+    /// * The decrypted record, or a SsapError if the vault is locked, the
+    ///   name is not registered or the entry cannot be decrypted
+    pub fn get(&self, name: &str) -> Result<Record, SsapError> {
+        let name = EntryName::new(name)?;
+        let key = self.key.clone().ok_or(SsapError::VaultLocked)?;
+        let passwd = self.ciphertext(name.as_str())?;
+        let plaintext = decrypt_password(passwd, key, &self.encryption)?;
+        Ok(Record {
+            name: name.to_string(),
+            entry: Entry::from_plaintext(&plaintext)?,
+        })
+    }
+
+    /// Encrypt a password, replacing the password with the same name if
+    /// there is one
+    ///
+    /// # Arguments
+    /// * `name` - The name of the password
+    /// * `entry` - The password and its fields
+    /// # Returns
+    /// * A SsapError if the vault is locked or the name is not valid
+    pub fn insert(
+        &mut self,
+        name: &str,
+        entry: Entry,
+    ) -> Result<(), SsapError> {
+        let name = EntryName::new(name)?.to_string();
+        let key = self.key.clone().ok_or(SsapError::VaultLocked)?;
+        let ciphertext = encrypt_password(
+            entry.to_plaintext().into(),
+            key,
+            &self.encryption,
+        )?;
+        self.changed(&name, Some(&ciphertext));
+        let entries = &mut self.entries;
+        match entries.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, old)) => *old = Some(ciphertext),
+            None => entries.push((name, Some(ciphertext))),
+        }
+        Ok(())
+    }
+
+    /// Remove a password
+    pub fn remove(&mut self, name: &str) -> Result<(), SsapError> {
+        let name = EntryName::new(name)?;
+        let len = self.entries.len();
+        self.entries.retain(|(n, _)| n != name.as_str());
+        if self.entries.len() == len {
+            return Err(SsapError::PasswordNameNotFound);
+        }
+        self.changed(name.as_str(), None);
+        Ok(())
+    }
+
+    /// Rename a password, keeping its encrypted entry
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), SsapError> {
+        self.rename_all(&[(from.to_owned(), to.to_owned())])
+    }
+
+    /// Rename several passwords at once. A password may take the name of
+    /// another password that is renamed too, so whole folders can be moved.
+    ///
+    /// # Arguments
+    /// * `moves` - The old and the new name of every password
+    /// # Returns
+    /// * A SsapError, leaving the vault untouched, if a name is not
+    ///   registered or a new name is taken
+    pub fn rename_all(
+        &mut self,
+        moves: &[(String, String)],
+    ) -> Result<(), SsapError> {
+        let mut renames = Vec::new();
+        for (from, to) in moves {
+            let from = EntryName::new(from)?.to_string();
+            let to = EntryName::new(to)?.to_string();
+            if !self.contains(&from) {
+                return Err(SsapError::PasswordNameNotFound);
+            }
+            renames.push((from, to));
+        }
+        for (_, to) in &renames {
+            let moved_away = renames.iter().any(|(from, _)| from == to);
+            if self.contains(to) && !moved_away {
+                return Err(SsapError::PasswordAlreadyRegistered);
+            }
+        }
+        // Indexed storages see a rename as a removal and an insertion
+        let mut moved = Vec::new();
+        for (from, to) in &renames {
+            moved.push((to.clone(), self.ciphertext(from)?));
+        }
+        for (from, _) in &renames {
+            self.changed(from, None);
+        }
+        for (to, ciphertext) in &moved {
+            self.changed(to, Some(ciphertext));
+        }
+        for (name, _) in self.entries.iter_mut() {
+            if let Some((_, to)) = renames.iter().find(|(from, _)| from == name)
+            {
+                *name = to.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the vault to its storage
+    pub fn save(&mut self) -> Result<(), SsapError> {
+        if self.storage.indexed() {
+            let (changes, history) = (&self.changes, &self.history);
+            self.storage.update(&self.header, changes, history)?;
+            self.changes.clear();
+            self.history.clear();
+            return Ok(());
+        }
+        let contents = self.contents()?;
+        self.storage.save(&contents)?;
+        self.history.clear();
+        Ok(())
+    }
+
+    /// Write a copy of the vault to another storage. Passwords are copied
+    /// encrypted, so the vault does not need to be unlocked.
+    pub fn save_as(
+        &self,
+        mut storage: Box<dyn VaultStorage>,
+    ) -> Result<(), SsapError> {
+        storage.save(&self.contents()?)
+    }
+
+    /// The ciphertext of a password, read from the storage if needed
+    fn ciphertext(&self, name: &str) -> Result<Vec<u8>, SsapError> {
+        match self.entries.iter().find(|(n, _)| n == name) {
+            Some((_, Some(ciphertext))) => Ok(ciphertext.clone()),
+            Some((_, None)) => match self.storage.entry(name)? {
+                Some(ciphertext) => Ok(ciphertext),
+                None => Err(SsapError::PasswordNameNotFound),
+            },
+            None => Err(SsapError::PasswordNameNotFound),
+        }
+    }
+
+    /// Record a change to be written by `save` to an indexed storage
+    fn changed(&mut self, name: &str, ciphertext: Option<&Vec<u8>>) {
+        if self.storage.indexed() {
+            self.changes.push((name.to_owned(), ciphertext.cloned()));
+        }
+    }
+
+    /// The whole contents of the vault, reading the passwords not read yet
+    fn contents(&self) -> Result<Contents, SsapError> {
+        let mut stored = HashMap::new();
+        if self.entries.iter().any(|(_, c)| c.is_none()) {
+            if let Some(contents) = self.storage.load()? {
+                stored.extend(contents.entries);
+            }
+        }
+        let mut entries = Vec::new();
+        for (name, ciphertext) in &self.entries {
+            let ciphertext = match ciphertext {
+                Some(ciphertext) => ciphertext.clone(),
+                None => match stored.remove(name) {
+                    Some(ciphertext) => ciphertext,
+                    None => return Err(SsapError::PasswordNameNotFound),
+                },
+            };
+            entries.push((name.clone(), ciphertext));
+        }
+        Ok(Contents {
+            header: self.header.clone(),
+            entries,
+        })
+    }
+
+    /// Encrypt previous versions of a password into the history of the
+    /// storage, as when importing an archive. They are written by `save`,
+    /// together with the passwords, and dropped by the backends without
+    /// history.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the password
+    /// * `versions` - The versions, oldest first, with the unix time they
+    ///   were replaced at
+    /// # Returns
+    /// * A SsapError if the vault is locked
+    pub fn add_history(
+        &mut self,
+        name: &str,
+        versions: &[(u64, Entry)],
+    ) -> Result<(), SsapError> {
+        let name = EntryName::new(name)?;
+        let key = self.key.clone().ok_or(SsapError::VaultLocked)?;
+        for (replaced, entry) in versions {
+            let ciphertext = encrypt_password(
+                entry.to_plaintext().into(),
+                key.clone(),
+                &self.encryption,
+            )?;
+            self.history.push((name.to_string(), *replaced, ciphertext));
+        }
+        Ok(())
+    }
+
+    /// Decrypt the previous versions of a password, oldest first, with the
+    /// unix time they were replaced at. Only some backends keep a history,
+    /// the others return none.
+    pub fn history(&self, name: &str) -> Result<Vec<(u64, Entry)>, SsapError> {
+        let name = EntryName::new(name)?;
+        let key = self.key.clone().ok_or(SsapError::VaultLocked)?;
+        let mut history = Vec::new();
+        for (time, passwd) in self.storage.history(name.as_str())? {
+            let plaintext =
+                decrypt_password(passwd, key.clone(), &self.encryption)?;
+            history.push((time, Entry::from_plaintext(&plaintext)?));
+        }
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssap::storage::MemoryStorage;
+    use std::fs;
+
+    fn new_vault(storage: Box<dyn VaultStorage>) -> Vault {
+        let mut vault =
+            Vault::create_with(storage, Encryption::Aes_256_cbc, 1_000)
+                .unwrap();
+        vault.unlock("password").unwrap();
+        vault
+    }
+
+    #[test]
+    fn test_save_and_open() {
+        let path = std::env::temp_dir()
+            .join(format!("ssap-vault-{}", std::process::id()))
+            .join("vault.ssap");
+        let mut vault = Vault::open_or_create(
+            &path,
+            Encryption::Aes_256_cbc,
+            1_000,
+            StorageKind::Text,
+        )
+        .unwrap();
+        vault.unlock("password").unwrap();
+        let mut entry = Entry::new("hunter2".to_owned());
+        entry.set("username", "alice".to_owned());
+        vault.insert("work/github", entry.clone()).unwrap();
+        vault
+            .insert("mail", Entry::new("secret".to_owned()))
+            .unwrap();
+        vault.save().unwrap();
+
+        let mut vault = Vault::open(&path, Encryption::Aes_256_cbc).unwrap();
+        assert_eq!(vault.list(), vec!["work/github", "mail"]);
+        assert!(vault.contains("/work/github"));
+        assert!(matches!(vault.get("mail"), Err(SsapError::VaultLocked)));
+        vault.unlock("password").unwrap();
+        let record = vault.get("work/github").unwrap();
+        assert_eq!(record.name, "work/github");
+        assert_eq!(record.entry, entry);
+
+        vault.unlock("wrong").unwrap();
+        assert!(vault.get("mail").is_err());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_sqlite_history() {
+        let path = std::env::temp_dir()
+            .join(format!("ssap-vault-history-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut vault = Vault::open_or_create(
+            &path,
+            Encryption::Aes_256_cbc,
+            1_000,
+            StorageKind::Sqlite,
+        )
+        .unwrap();
+        vault.unlock("password").unwrap();
+        vault.insert("a", Entry::new("one".to_owned())).unwrap();
+        vault.save().unwrap();
+        vault.insert("a", Entry::new("two".to_owned())).unwrap();
+        vault.save().unwrap();
+
+        let mut vault = Vault::open(&path, Encryption::Aes_256_cbc).unwrap();
+        vault.unlock("password").unwrap();
+        assert_eq!(vault.get("a").unwrap().entry.password, "two");
+        let history = vault.history("a").unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].1.password, "one");
+
+        // Older versions added later still come first
+        let old = Entry::new("zero".to_owned());
+        vault.add_history("a", &[(1_000, old)]).unwrap();
+        vault.insert("a", Entry::new("three".to_owned())).unwrap();
+        vault.save().unwrap();
+        let history = vault.history("a").unwrap();
+        let passwords: Vec<&str> =
+            history.iter().map(|(_, e)| e.password.as_str()).collect();
+        assert_eq!(passwords, ["zero", "one", "two"]);
+        assert_eq!(history[0], (1_000, Entry::new("zero".to_owned())));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_changes_only() {
+        let path = std::env::temp_dir()
+            .join(format!("ssap-vault-changes-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let open = || {
+            let mut vault = Vault::open_or_create(
+                &path,
+                Encryption::Aes_256_cbc,
+                1_000,
+                StorageKind::Sqlite,
+            )
+            .unwrap();
+            vault.unlock("password").unwrap();
+            vault
+        };
+        let mut vault = open();
+        vault.insert("a", Entry::new("one".to_owned())).unwrap();
+        vault.insert("b", Entry::new("two".to_owned())).unwrap();
+        vault.save().unwrap();
+
+        // Saving one vault does not undo the changes saved by the other
+        let mut first = open();
+        let mut second = open();
+        first.insert("c", Entry::new("three".to_owned())).unwrap();
+        first.save().unwrap();
+        second.remove("a").unwrap();
+        second.rename("b", "d").unwrap();
+        second.save().unwrap();
+
+        let vault = open();
+        assert_eq!(vault.list(), vec!["c", "d"]);
+        assert_eq!(vault.get("d").unwrap().entry.password, "two");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_memory() {
+        let storage = MemoryStorage::new();
+        assert!(matches!(
+            Vault::open_with(
+                Box::new(storage.clone()),
+                Encryption::Aes_256_cbc
+            ),
+            Err(SsapError::InvalidPath)
+        ));
+        let mut vault = new_vault(Box::new(storage.clone()));
+        vault.insert("a", Entry::new("one".to_owned())).unwrap();
+        vault.save().unwrap();
+
+        let mut vault =
+            Vault::open_with(Box::new(storage), Encryption::Aes_256_cbc)
+                .unwrap();
+        vault.unlock("password").unwrap();
+        assert_eq!(vault.get("a").unwrap().entry.password, "one");
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut vault = new_vault(Box::new(MemoryStorage::new()));
+        vault.insert("a", Entry::new("one".to_owned())).unwrap();
+        vault.insert("b", Entry::new("two".to_owned())).unwrap();
+        vault.insert("a", Entry::new("three".to_owned())).unwrap();
+        assert_eq!(vault.list(), vec!["a", "b"]);
+        assert_eq!(vault.get("a").unwrap().entry.password, "three");
+        assert!(matches!(
+            vault.insert("..", Entry::default()),
+            Err(SsapError::InvalidPasswordName)
+        ));
+    }
+
+    #[test]
+    fn test_remove_and_rename() {
+        let mut vault = new_vault(Box::new(MemoryStorage::new()));
+        for name in ["a/x", "a/y", "b/x"] {
+            vault.insert(name, Entry::new(name.to_owned())).unwrap();
+        }
+        assert!(matches!(
+            vault.rename("a/x", "b/x"),
+            Err(SsapError::PasswordAlreadyRegistered)
+        ));
+        let moves = vec![
+            ("a/x".to_owned(), "b/x".to_owned()),
+            ("b/x".to_owned(), "c/x".to_owned()),
+        ];
+        vault.rename_all(&moves).unwrap();
+        assert_eq!(vault.list(), vec!["b/x", "a/y", "c/x"]);
+        assert_eq!(vault.get("b/x").unwrap().entry.password, "a/x");
+
+        vault.remove("a/y").unwrap();
+        assert!(matches!(
+            vault.remove("a/y"),
+            Err(SsapError::PasswordNameNotFound)
+        ));
+        assert_eq!(vault.list(), vec!["b/x", "c/x"]);
+    }
+}